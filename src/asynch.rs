@@ -0,0 +1,417 @@
+use crate::builder::Identity;
+use crate::{answers, Error, RetryPolicy, DEFAULT_TIMEOUT};
+
+use znp_types::command::sys::{Capability, Ping, SysVersion, VersionInfo};
+use znp_types::command::{de, ser, CommandType};
+use znp_types::packet::{self, Decoder, Packet};
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use enumflags2::BitFlags;
+use log::{debug, warn};
+use semver::Version;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadHalf, WriteHalf};
+use tokio::sync::{broadcast, oneshot};
+use tokio::task::JoinHandle;
+
+/// Byte stream a ZNP is attached to, e.g. a `tokio_serial::SerialStream`.
+pub trait AsyncTransport: AsyncRead + AsyncWrite + Send + 'static {}
+
+impl<T: AsyncRead + AsyncWrite + Send + 'static> AsyncTransport for T {}
+
+/// Callbacks buffered per subscriber before the oldest are dropped.
+const CALLBACK_CAPACITY: usize = 64;
+
+/// SRSP awaited by the request in flight.
+struct Pending {
+    cmd: [u8; 2],
+    tx: oneshot::Sender<Packet>,
+}
+
+type Slot = Arc<Mutex<Option<Pending>>>;
+
+/// Clears the pending slot when a request completes or is cancelled.
+struct PendingGuard<'a>(&'a Slot);
+
+impl Drop for PendingGuard<'_> {
+    fn drop(&mut self) { self.0.lock().unwrap().take(); }
+}
+
+/// Asynchronous counterpart of [`crate::Session`].
+///
+/// A background task reads frames from the transport, completing the
+/// request in flight with its SRSP and broadcasting every AREQ to
+/// subscribers. Requests are serialised, as the ZNP handles one SREQ at a
+/// time, and dropping a request future releases the session for the next.
+pub struct AsyncSession<T: AsyncTransport> {
+    writer: tokio::sync::Mutex<WriteHalf<T>>,
+    pending: Slot,
+    callbacks: broadcast::Sender<Packet>,
+    reader: JoinHandle<()>,
+
+    timeout: Duration,
+    retry_policy: RetryPolicy,
+}
+
+impl<T: AsyncTransport> AsyncSession<T> {
+    /// Must be called from within a tokio runtime.
+    pub fn new(transport: T) -> Self {
+        let (reader, writer) = tokio::io::split(transport);
+        let pending = Slot::default();
+        let (callbacks, _) = broadcast::channel(CALLBACK_CAPACITY);
+        let reader = tokio::spawn(Self::read_loop(reader, pending.clone(), callbacks.clone()));
+        Self {
+            writer: tokio::sync::Mutex::new(writer),
+            pending,
+            callbacks,
+            reader,
+            timeout: DEFAULT_TIMEOUT,
+            retry_policy: RetryPolicy::none(),
+        }
+    }
+
+    /// Time to wait for the SRSP of each request.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Retry policy for requests that timed out.
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    async fn read_loop(
+        mut reader: ReadHalf<T>,
+        pending: Slot,
+        callbacks: broadcast::Sender<Packet>,
+    ) {
+        let mut decoder = Decoder::new();
+        let mut chunk = [u8::MIN; 256];
+        loop {
+            while let Some(frame) = decoder.decode() {
+                if frame.command_type() == CommandType::AREQ as u8 {
+                    // no subscribers is not an error
+                    let _ = callbacks.send(frame);
+                    continue;
+                }
+                let mut pending = pending.lock().unwrap();
+                let expected = pending.as_ref().is_some_and(|e| answers(&frame, e.cmd));
+                if expected {
+                    let _ = pending.take().unwrap().tx.send(frame);
+                } else {
+                    debug!("discarding unexpected frame: {:x?}", frame.command);
+                }
+            }
+            match reader.read(&mut chunk).await {
+                Ok(0) => break,
+                Ok(len) => decoder.extend(&chunk[..len]),
+                Err(e) => {
+                    warn!("transport read failed: {:?}", e);
+                    break;
+                }
+            }
+        }
+        debug!("transport closed");
+        // fail the request in flight
+        pending.lock().unwrap().take();
+    }
+
+    /// Returns a receiver of every AREQ received from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<Packet> { self.callbacks.subscribe() }
+
+    /// Waits up to `timeout` for the next `C` callback on `rx`, subscribed
+    /// before sending the request that triggers it.
+    pub async fn wait_for<C>(
+        &self,
+        rx: &mut broadcast::Receiver<Packet>,
+        timeout: Duration,
+    ) -> Result<C::Output, Error>
+    where
+        C: de::Command + Default,
+    {
+        let wait = async {
+            loop {
+                let frame = match rx.recv().await {
+                    Ok(frame) => frame,
+                    Err(broadcast::error::RecvError::Lagged(n)) => {
+                        warn!("dropped {} callbacks", n);
+                        continue;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => {
+                        return Err(Error::Packet(packet::Error::UnexpectedEOF));
+                    }
+                };
+                if frame.command_id() != C::ID.to_cmd() {
+                    continue;
+                }
+                return C::default()
+                    .deserialize(frame.command)
+                    .map_err(Error::Deserialization);
+            }
+        };
+        tokio::time::timeout(timeout, wait)
+            .await
+            .map_err(|_| Error::Timeout)?
+    }
+
+    /// Sends `command` without waiting for a response, e.g. for AREQs.
+    pub async fn send_command(&self, command: &impl ser::Command) -> Result<(), Error> {
+        let packet = Packet::from_command(command).serialize();
+        let mut writer = self.writer.lock().await;
+        writer
+            .write_all(packet.as_slice())
+            .await
+            .map_err(Error::IO)?;
+        writer.flush().await.map_err(Error::IO)
+    }
+
+    pub async fn request<C: ser::Command + de::Command>(
+        &self,
+        command: &C,
+    ) -> Result<C::Output, Error> {
+        self.request_timeout(command, self.timeout).await
+    }
+
+    /// Sends `command` and waits up to `timeout` for its response, retrying
+    /// according to the session's retry policy.
+    pub async fn request_timeout<C: ser::Command + de::Command>(
+        &self,
+        command: &C,
+        timeout: Duration,
+    ) -> Result<C::Output, Error> {
+        let mut attempt = 0;
+        loop {
+            match self.request_once(command, timeout).await {
+                Err(Error::Timeout) if attempt < self.retry_policy.retries => {
+                    let backoff = self.retry_policy.backoff(attempt);
+                    warn!(
+                        "request {:?} timed out, retrying in {:?}",
+                        C::ID.to_cmd(),
+                        backoff
+                    );
+                    tokio::time::sleep(backoff).await;
+                    attempt += 1;
+                }
+                ret => return ret,
+            }
+        }
+    }
+
+    /// Sends `command` once and waits up to `timeout` for its response.
+    pub async fn request_once<C: ser::Command + de::Command>(
+        &self,
+        command: &C,
+        timeout: Duration,
+    ) -> Result<C::Output, Error> {
+        let mut writer = self.writer.lock().await;
+        if self.reader.is_finished() {
+            return Err(Error::Packet(packet::Error::UnexpectedEOF));
+        }
+
+        let (tx, rx) = oneshot::channel();
+        *self.pending.lock().unwrap() = Some(Pending {
+            cmd: C::ID.to_cmd(),
+            tx,
+        });
+        let _guard = PendingGuard(&self.pending);
+
+        let packet = Packet::from_command(command).serialize();
+        writer
+            .write_all(packet.as_slice())
+            .await
+            .map_err(Error::IO)?;
+        writer.flush().await.map_err(Error::IO)?;
+
+        let frame = tokio::time::timeout(timeout, rx)
+            .await
+            .map_err(|_| Error::Timeout)?
+            .map_err(|_| Error::Packet(packet::Error::UnexpectedEOF))?;
+        match command.deserialize(frame.command) {
+            Ok(ret) => Ok(ret),
+            Err(de::Error::CommandNotFound { .. }) => Err(Error::CommandNotFound),
+            Err(e) => Err(Error::Deserialization(e)),
+        }
+    }
+}
+
+impl<T: AsyncTransport> Drop for AsyncSession<T> {
+    fn drop(&mut self) { self.reader.abort(); }
+}
+
+/// Asynchronous counterpart of [`crate::ZNP`].
+pub struct AsyncZNP<T: AsyncTransport> {
+    version: Version,
+    firmware: VersionInfo,
+
+    align_structs: bool,
+    capabilities: BitFlags<Capability>,
+
+    session: AsyncSession<T>,
+}
+
+impl<T: AsyncTransport> AsyncZNP<T> {
+    /// Probes the ZNP behind `session` the same way [`crate::Builder`] does.
+    pub async fn connect(session: AsyncSession<T>) -> Result<Self, Error> {
+        let capabilities = session.request(&Ping::default()).await?;
+        let mut identity = Identity::new(session.request(&SysVersion::default()).await?);
+        if let Some(probe) = identity.alignment_probe() {
+            identity.set_alignment(&session.request(&probe).await?)?;
+        }
+
+        let ret = Self {
+            version: identity.version,
+            firmware: identity.firmware,
+            align_structs: identity.align_structs,
+            capabilities,
+            session,
+        };
+        Ok(ret)
+    }
+
+    pub fn version(&self) -> Version { self.version.clone() }
+    pub fn firmware(&self) -> VersionInfo { self.firmware.clone() }
+
+    pub fn align_structs(&self) -> bool { self.align_structs }
+    pub fn capabilities(&self) -> BitFlags<Capability> { self.capabilities }
+
+    pub fn session(&self) -> &AsyncSession<T> { &self.session }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AsyncSession, AsyncZNP};
+    use crate::sim::{Profile, Simulator};
+    use crate::Error;
+
+    use znp_types::command::sys::{Capability, Ping, Product};
+    use znp_types::command::{de, Command, CommandID, CommandType, Subsystem};
+    use znp_types::packet::Decoder;
+
+    use std::time::Duration;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream};
+
+    /// Answers the first ping with an AREQ followed by its SRSP and ignores
+    /// the rest.
+    async fn device(mut pipe: DuplexStream) {
+        let mut decoder = Decoder::new();
+        let mut chunk = [0u8; 64];
+        let mut answered = false;
+        loop {
+            while let Some(frame) = decoder.decode() {
+                assert_eq!(frame.command_id(), [0x01, 0x01]);
+                if answered {
+                    continue;
+                }
+                answered = true;
+                // SYS_RESET_IND, then SYS_PING SRSP with SYS | UTIL
+                let areq = [0xFE, 0x01, 0x41, 0x80, 0x00, 0xC0];
+                let srsp = [0xFE, 0x02, 0x61, 0x01, 0x41, 0x00, 0x23];
+                pipe.write_all(&areq).await.unwrap();
+                pipe.write_all(&srsp).await.unwrap();
+            }
+            match pipe.read(&mut chunk).await {
+                Ok(0) | Err(_) => return,
+                Ok(len) => decoder.extend(&chunk[..len]),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn request_over_duplex() {
+        let (host, dev) = tokio::io::duplex(256);
+        tokio::spawn(device(dev));
+        let session = AsyncSession::new(host).timeout(Duration::from_millis(100));
+        let mut callbacks = session.subscribe();
+
+        let capabilities = session.request(&Ping::default()).await.unwrap();
+        assert_eq!(capabilities, Capability::SYS | Capability::UTIL);
+        assert_eq!(callbacks.recv().await.unwrap().command_id(), [0x01, 0x80]);
+
+        let ret = session.request(&Ping::default()).await;
+        assert!(matches!(ret, Err(Error::Timeout)));
+    }
+
+    #[tokio::test]
+    async fn cancelled_request_releases_session() {
+        let (host, dev) = tokio::io::duplex(256);
+        let session = AsyncSession::new(host);
+
+        let cancelled =
+            tokio::time::timeout(Duration::from_millis(10), session.request(&Ping::default()))
+                .await;
+        assert!(cancelled.is_err());
+
+        tokio::spawn(device(dev));
+        let capabilities = session.request(&Ping::default()).await.unwrap();
+        assert_eq!(capabilities, Capability::SYS | Capability::UTIL);
+    }
+
+    /// `SYS_RESET_IND`, as sent by [`device`].
+    #[derive(Default)]
+    struct ResetInd {}
+
+    impl Command for ResetInd {
+        const ID: CommandID = CommandID {
+            subsystem: Subsystem::IFaceSYS,
+            id: 0x80,
+        };
+    }
+
+    impl de::Command for ResetInd {
+        const RESPONSE_TYPE: CommandType = CommandType::AREQ;
+        type Output = Vec<u8>;
+        fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+            Ok(data_frame)
+        }
+    }
+
+    #[tokio::test]
+    async fn callback_before_wait() {
+        let (host, dev) = tokio::io::duplex(256);
+        tokio::spawn(device(dev));
+        let session = AsyncSession::new(host);
+
+        let mut callbacks = session.subscribe();
+        session.request(&Ping::default()).await.unwrap();
+        let timeout = Duration::from_millis(100);
+        let ret = session.wait_for::<ResetInd>(&mut callbacks, timeout).await;
+        assert_eq!(ret.unwrap(), [0x00]);
+    }
+
+    #[tokio::test]
+    async fn rpc_error_of_other_command() {
+        let (host, mut dev) = tokio::io::duplex(256);
+        let session = AsyncSession::new(host).timeout(Duration::from_millis(100));
+        // RPC_Error for SYS_VERSION, then for SYS_PING
+        let other = [0xFE, 0x03, 0x60, 0x00, 0x02, 0x21, 0x02, 0x42];
+        let ping = [0xFE, 0x03, 0x60, 0x00, 0x02, 0x21, 0x01, 0x41];
+
+        let request = Ping::default();
+        for (frame, answers) in [(other, false), (ping, true)] {
+            let answer = async {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                dev.write_all(&frame).await.unwrap();
+            };
+            let (ret, _) = tokio::join!(session.request(&request), answer);
+            // the matching error fails the request right away
+            assert_eq!(matches!(ret, Err(Error::Timeout)), !answers);
+        }
+    }
+
+    #[tokio::test]
+    async fn connect_zstack_1_2() {
+        let (host, dev) = tokio::io::duplex(256);
+        let (sim, _) = Simulator::spawn(Profile::zstack_1_2());
+        tokio::spawn(sim.serve(dev));
+        let session = AsyncSession::new(host).timeout(Duration::from_millis(100));
+
+        let znp = AsyncZNP::connect(session).await.unwrap();
+        assert_eq!(znp.firmware().product, Product::ZStack12);
+        assert_eq!(znp.version().major, 1);
+        assert!(!znp.align_structs());
+    }
+}
@@ -0,0 +1,714 @@
+//! Network backups in the open coordinator backup format, see
+//! <https://github.com/zigpy/open-coordinator-backup>.
+
+use crate::{Error, NVRam};
+
+use znp_types::command::sys::NVID;
+use znp_types::command::Status;
+use znp_types::nv::{
+    AddrMgrEntry, AddrMgrUser, ApsLinkKeyData, ApsLinkKeyTable, ApsLinkKeyTableEntry,
+    AuthenticationState, KeyAttributes, Nib, NvStruct, NwkActiveKeyItems, NwkKeyDesc,
+    NwkSecMaterialDesc, OsalNvIds, TCLinkKeyEntry, ADDRMGR, ADDRMGR_TABLE, APS_KEY_DATA_TABLE,
+    APS_LINK_KEY_TABLE, APS_USE_EXT_PANID, BDB_NODE_IS_ON_A_NETWORK, CHANLIST, CONFIGURED_MARKER,
+    EXTADDR, EXTENDED_PAN_ID, HAS_CONFIGURED_ZSTACK3, LOGICAL_TYPE, NIB, NWK_ACTIVE_KEY_INFO,
+    NWK_ALTERN_KEY_INFO, NWK_SEC_MATERIAL_TABLE, PANID, PRECFGKEY, PRECFGKEYS_ENABLE, TCLK_SEED,
+    TCLK_TABLE,
+};
+
+use std::fmt;
+use std::str::FromStr;
+
+use enumflags2::BitFlags;
+use semver::Version;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+pub const BACKUP_FORMAT: &str = "zigpy/open-coordinator-backup";
+pub const BACKUP_VERSION: u32 = 1;
+
+/// Added to the network frame counter on restore, so that frames sent
+/// between the backup and the restore are not replayed.
+pub const FRAME_COUNTER_MARGIN: u32 = 2500;
+
+/// Fixed-size byte string, written as hex.
+///
+/// Addresses are kept in over-the-air (little-endian) order and written
+/// most significant byte first, keys are written as is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Hex<const N: usize, const REVERSED: bool>(pub [u8; N]);
+
+/// IEEE address or extended PAN ID.
+pub type Eui64 = Hex<8, true>;
+/// 128-bit AES key.
+pub type Key = Hex<16, false>;
+
+impl<const N: usize, const REVERSED: bool> fmt::Display for Hex<N, REVERSED> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut bytes = self.0;
+        if REVERSED {
+            bytes.reverse();
+        }
+        bytes.iter().try_for_each(|e| write!(f, "{:02x}", e))
+    }
+}
+
+impl<const N: usize, const REVERSED: bool> FromStr for Hex<N, REVERSED> {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.replace(':', "");
+        if s.len() != N * 2 || !s.is_ascii() {
+            return Err(format!("expected {} hex bytes, got {:?}", N, s));
+        }
+        let mut ret = [u8::MIN; N];
+        for (i, e) in ret.iter_mut().enumerate() {
+            *e = u8::from_str_radix(&s[i * 2..i * 2 + 2], 16).map_err(|e| e.to_string())?;
+        }
+        if REVERSED {
+            ret.reverse();
+        }
+        Ok(Self(ret))
+    }
+}
+
+impl<const N: usize, const REVERSED: bool> Serialize for Hex<N, REVERSED> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de, const N: usize, const REVERSED: bool> Deserialize<'de> for Hex<N, REVERSED> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+/// 16-bit address or PAN ID, written as 4 hex digits.
+mod hex_u16 {
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(val: &u16, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("{:04x}", val))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u16, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        u16::from_str_radix(&s, 16).map_err(de::Error::custom)
+    }
+}
+
+/// Like [`hex_u16`], `null` if unknown.
+mod hex_u16_opt {
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(val: &Option<u16>, serializer: S) -> Result<S::Ok, S::Error> {
+        match val {
+            Some(val) => super::hex_u16::serialize(val, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<u16>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|s| u16::from_str_radix(&s, 16).map_err(de::Error::custom))
+            .transpose()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Metadata {
+    pub format: String,
+    pub version: u32,
+    pub source: String,
+    /// Free-form details of the tool that wrote the backup.
+    #[serde(default)]
+    pub internal: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct StackSpecific {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub zstack: Option<ZStackSpecific>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ZStackSpecific {
+    /// Seed the trust center link keys are derived from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tclk_seed: Option<Key>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NetworkKey {
+    pub key: Key,
+    pub sequence_number: u8,
+    pub frame_counter: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LinkKey {
+    pub key: Key,
+    pub tx_counter: u32,
+    pub rx_counter: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Device {
+    /// Unknown for devices only the trust center keys are kept for.
+    #[serde(default, with = "hex_u16_opt")]
+    pub nwk_address: Option<u16>,
+    pub ieee_address: Eui64,
+    /// Whether the device is a child of the coordinator.
+    #[serde(default)]
+    pub is_child: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link_key: Option<LinkKey>,
+}
+
+/// Everything needed to move a network to another coordinator.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Backup {
+    pub metadata: Metadata,
+    #[serde(default)]
+    pub stack_specific: StackSpecific,
+    pub coordinator_ieee: Eui64,
+    #[serde(with = "hex_u16")]
+    pub pan_id: u16,
+    pub extended_pan_id: Eui64,
+    pub nwk_update_id: u8,
+    pub security_level: u8,
+    pub channel: u8,
+    pub channel_mask: Vec<u8>,
+    pub network_key: NetworkKey,
+    pub devices: Vec<Device>,
+}
+
+impl Backup {
+    pub fn to_json(&self) -> String { serde_json::to_string_pretty(self).unwrap() }
+
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> { serde_json::from_str(json) }
+}
+
+/// Channels set in a channel bit mask.
+pub(crate) fn mask_to_channels(mask: u32) -> Vec<u8> {
+    (11..=26).filter(|e| mask & (1 << e) != 0).collect()
+}
+
+/// Trust center link key of `ieee`, derived from the seed as Z-Stack does.
+pub(crate) fn derive_link_key(seed: &[u8; 16], ieee: &[u8; 8], shift: u8) -> [u8; 16] {
+    let mut ret = *seed;
+    ret.rotate_left(shift as usize % seed.len());
+    for (i, e) in ret.iter_mut().enumerate() {
+        *e ^= ieee[i % ieee.len()];
+    }
+    ret
+}
+
+/// Shift of the seed `key` is derived with, if any.
+fn seed_shift(seed: &[u8; 16], ieee: &[u8; 8], key: &[u8; 16]) -> Option<u8> {
+    (0..16).find(|&e| derive_link_key(seed, ieee, e) == *key)
+}
+
+fn channels_to_mask(channels: &[u8]) -> u32 {
+    channels
+        .iter()
+        .filter(|e| (11..=26).contains(*e))
+        .fold(0, |acc, e| acc | 1 << e)
+}
+
+/// NIB of a freshly formed coordinator, for sticks without one.
+fn coordinator_nib() -> Nib {
+    Nib {
+        passive_ack_timeout: 5,
+        max_broadcast_retries: 2,
+        max_depth: 20,
+        broadcast_delivery_time: 30,
+        secure_all_frames: 1,
+        security_level: 5,
+        sym_link: 1,
+        capability_flags: 0x8F,
+        transaction_persistence_time: 7,
+        nwk_protocol_version: 2,
+        route_discovery_time: 5,
+        route_expiry_time: 30,
+        beacon_order: 15,
+        super_frame_order: 15,
+        nwk_link_status_period: 15,
+        nwk_router_age_limit: 3,
+        nwk_is_concentrator: true,
+        nwk_concentrator_discovery_time: 120,
+        nwk_concentrator_radius: 30,
+        nwk_all_fresh: 1,
+        ..Default::default()
+    }
+}
+
+/// Maps a missing item to `None`.
+pub(crate) fn optional<T>(ret: Result<T, Error>) -> Result<Option<T>, Error> {
+    match ret {
+        Ok(val) => Ok(Some(val)),
+        Err(Error::Status(Status::NvItemUninit)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Network backup and restore on top of [`NVRam`].
+pub trait NetworkBackup: NVRam {
+    /// Reads the network settings, keys and known devices.
+    fn backup(&mut self) -> Result<Backup, Error> {
+        let extended = self.version() >= Version::new(3, 30, 0);
+
+        let nib = self.nv_get(NIB)?;
+        let key_info = self.nv_get(NWK_ACTIVE_KEY_INFO)?;
+        let tclk_seed = optional(self.nv_get(TCLK_SEED))?;
+
+        // Z-Stack 3 keeps the frame counter per extended PAN ID, a wildcard
+        // entry applying to any network
+        let frame_counter = self
+            .nv_table(&NWK_SEC_MATERIAL_TABLE)?
+            .into_iter()
+            .filter(|e| e.extended_pan_id == nib.extended_pan_id || e.extended_pan_id == [0xFF; 8])
+            .max_by_key(|e| e.extended_pan_id == nib.extended_pan_id)
+            .map_or(key_info.frame_counter, |e| e.frame_counter);
+
+        let addr_mgr = match extended {
+            true => self.nv_table(&ADDRMGR_TABLE)?,
+            false => self.nv_get(ADDRMGR)?,
+        };
+        let mut devices = addr_mgr
+            .iter()
+            .filter(|e| !e.users().is_empty() && e.ext_addr != [0xFF; 8])
+            .map(|e| Device {
+                nwk_address: Some(e.nwk_addr),
+                ieee_address: Hex(e.ext_addr),
+                is_child: e.users().contains(AddrMgrUser::Assoc),
+                link_key: None,
+            })
+            .collect::<Vec<_>>();
+
+        if let Some(seed) = tclk_seed {
+            for entry in self.nv_table(&TCLK_TABLE)? {
+                let verified = [KeyAttributes::VerifiedKey, KeyAttributes::UnverifiedKey]
+                    .iter()
+                    .any(|&e| e as u8 == entry.key_attributes);
+                let device = devices
+                    .iter_mut()
+                    .find(|e| e.ieee_address.0 == entry.ext_addr);
+                if let (true, Some(device)) = (verified, device) {
+                    let key = derive_link_key(&seed, &entry.ext_addr, entry.seed_shift_ic_index);
+                    device.link_key = Some(LinkKey {
+                        key: Hex(key),
+                        tx_counter: entry.tx_frame_counter,
+                        rx_counter: entry.rx_frame_counter,
+                    });
+                }
+            }
+        }
+
+        // application link keys, indexed by address manager entry
+        if let Some(table) = optional(self.nv_get(APS_LINK_KEY_TABLE))? {
+            for entry in table.entries {
+                let Some(AddrMgrEntry { ext_addr, .. }) =
+                    addr_mgr.get(entry.addr_mgr_index as usize)
+                else {
+                    continue;
+                };
+                let index = entry
+                    .link_key_nv_id
+                    .wrapping_sub(OsalNvIds::ApsLinkKeyDataStart as u16);
+                let Some(item) = APS_KEY_DATA_TABLE.entry(index, extended) else {
+                    continue;
+                };
+                let Some(data) = optional(self.nv_get(item))? else {
+                    continue;
+                };
+                if let Some(device) = devices.iter_mut().find(|e| e.ieee_address.0 == *ext_addr) {
+                    device.link_key = Some(LinkKey {
+                        key: Hex(data.key),
+                        tx_counter: data.tx_frame_counter,
+                        rx_counter: data.rx_frame_counter,
+                    });
+                }
+            }
+        }
+
+        let ret = Backup {
+            metadata: Metadata {
+                format: BACKUP_FORMAT.to_string(),
+                version: BACKUP_VERSION,
+                source: format!("znp@{}", env!("CARGO_PKG_VERSION")),
+                internal: serde_json::json!({
+                    "zstack": { "version": self.version().to_string() },
+                }),
+            },
+            stack_specific: StackSpecific {
+                zstack: Some(ZStackSpecific {
+                    tclk_seed: tclk_seed.map(Hex),
+                }),
+            },
+            coordinator_ieee: Hex(self.nv_get(EXTADDR)?),
+            pan_id: nib.nwk_pan_id,
+            extended_pan_id: Hex(nib.extended_pan_id),
+            nwk_update_id: nib.nwk_update_id,
+            security_level: nib.security_level,
+            channel: nib.nwk_logical_channel,
+            channel_mask: mask_to_channels(self.nv_get(CHANLIST)?),
+            network_key: NetworkKey {
+                key: Hex(key_info.active.key),
+                sequence_number: key_info.active.key_seq_num,
+                frame_counter,
+            },
+            devices,
+        };
+        Ok(ret)
+    }
+
+    /// Writes the network in `backup`, so that the coordinator resumes it on
+    /// its next start. Every item is read back to verify it.
+    ///
+    /// Fails with [`Error::Unsupported`] on firmware older than Z-Stack 3.0,
+    /// whose link key tables are not derived from a seed.
+    fn restore(&mut self, backup: &Backup) -> Result<(), Error> {
+        if self.version() < Version::new(3, 0, 0) {
+            return Err(Error::Unsupported(self.version()));
+        }
+        let extended = self.version() >= Version::new(3, 30, 0);
+        let align = self.align_structs();
+
+        let epid = backup.extended_pan_id.0;
+        let mut channel_mask = channels_to_mask(&backup.channel_mask);
+        if channel_mask == 0 {
+            channel_mask = channels_to_mask(&[backup.channel]);
+        }
+        let key_info = NwkActiveKeyItems {
+            active: NwkKeyDesc {
+                key_seq_num: backup.network_key.sequence_number,
+                key: backup.network_key.key.0,
+            },
+            frame_counter: backup
+                .network_key
+                .frame_counter
+                .saturating_add(FRAME_COUNTER_MARGIN),
+        };
+        let mut nib = optional(self.nv_get(NIB))?.unwrap_or_else(coordinator_nib);
+        nib.nwk_dev_address = 0x0000;
+        nib.nwk_pan_id = backup.pan_id;
+        nib.extended_pan_id = epid;
+        nib.nwk_logical_channel = backup.channel;
+        nib.channel_list = channel_mask;
+        nib.nwk_update_id = backup.nwk_update_id;
+        nib.security_level = backup.security_level;
+        nib.nwk_key_loaded = false;
+
+        let mut items: Vec<(NVID, Vec<u8>)> = vec![
+            (EXTADDR.id(), backup.coordinator_ieee.0.encode(align)),
+            (LOGICAL_TYPE.id(), 0u8.encode(align)),
+            (PANID.id(), backup.pan_id.encode(align)),
+            (EXTENDED_PAN_ID.id(), epid.encode(align)),
+            (APS_USE_EXT_PANID.id(), epid.encode(align)),
+            (CHANLIST.id(), channel_mask.encode(align)),
+            (PRECFGKEY.id(), backup.network_key.key.0.encode(align)),
+            (PRECFGKEYS_ENABLE.id(), true.encode(align)),
+            (NWK_ACTIVE_KEY_INFO.id(), key_info.encode(align)),
+            (NWK_ALTERN_KEY_INFO.id(), key_info.encode(align)),
+            (NIB.id(), nib.encode(align)),
+            (BDB_NODE_IS_ON_A_NETWORK.id(), true.encode(align)),
+            (HAS_CONFIGURED_ZSTACK3.id(), CONFIGURED_MARKER.encode(align)),
+        ];
+
+        // the frame counter actually used lives in the security material
+        let material = NwkSecMaterialDesc {
+            frame_counter: key_info.frame_counter,
+            extended_pan_id: epid,
+        };
+        let item = NWK_SEC_MATERIAL_TABLE.entry(0, extended).unwrap();
+        items.push((item.id(), material.encode(align)));
+
+        let seed = backup
+            .stack_specific
+            .zstack
+            .as_ref()
+            .and_then(|e| e.tclk_seed)
+            .map(|e| e.0);
+        if let Some(seed) = seed {
+            items.push((TCLK_SEED.id(), seed.encode(align)));
+        }
+        let mut tclk = vec![];
+        let mut aps_link_keys = ApsLinkKeyTable::default();
+        for (i, device) in backup.devices.iter().enumerate() {
+            let Some(link_key) = &device.link_key else {
+                continue;
+            };
+            let ieee = device.ieee_address.0;
+            if let Some(shift) = seed.and_then(|e| seed_shift(&e, &ieee, &link_key.key.0)) {
+                tclk.push(TCLinkKeyEntry {
+                    tx_frame_counter: link_key.tx_counter,
+                    rx_frame_counter: link_key.rx_counter,
+                    ext_addr: ieee,
+                    key_attributes: KeyAttributes::VerifiedKey as u8,
+                    key_type: 0,
+                    seed_shift_ic_index: shift,
+                });
+                continue;
+            }
+            // keys not derived from the seed are kept as application link
+            // keys, indexed by the address manager entry written below
+            let index = aps_link_keys.entries.len() as u16;
+            let item = APS_KEY_DATA_TABLE
+                .entry(index, extended)
+                .ok_or(Error::Status(Status::NvBadItemLen))?;
+            let data = ApsLinkKeyData {
+                key: link_key.key.0,
+                tx_frame_counter: link_key.tx_counter,
+                rx_frame_counter: link_key.rx_counter,
+            };
+            items.push((item.id(), data.encode(align)));
+            aps_link_keys.entries.push(ApsLinkKeyTableEntry {
+                addr_mgr_index: i as u16,
+                link_key_nv_id: OsalNvIds::ApsLinkKeyDataStart as u16 + index,
+                authentication_state: AuthenticationState::AuthenticatedCbck as u8,
+            });
+        }
+        // the firmware expects the table at its original size
+        let old = optional(self.nv_read(APS_LINK_KEY_TABLE.id()))?;
+        if old.is_some() || !aps_link_keys.entries.is_empty() {
+            let mut value = aps_link_keys.encode(align);
+            let len = old.map_or(0, |e| e.len());
+            value.resize(len.max(value.len()), 0);
+            items.push((APS_LINK_KEY_TABLE.id(), value));
+        }
+        // clear stale entries left by a previous network
+        let tclk_len = tclk.len().max(self.nv_table(&TCLK_TABLE)?.len());
+        tclk.resize(
+            tclk_len,
+            TCLinkKeyEntry {
+                key_attributes: KeyAttributes::DefaultKey as u8,
+                ..Default::default()
+            },
+        );
+        for (i, entry) in tclk.iter().enumerate() {
+            let item = TCLK_TABLE
+                .entry(i as u16, extended)
+                .ok_or(Error::Status(Status::NvBadItemLen))?;
+            items.push((item.id(), entry.encode(align)));
+        }
+
+        let mut addr_mgr = backup
+            .devices
+            .iter()
+            .map(|e| {
+                let mut user = BitFlags::<AddrMgrUser>::empty();
+                if e.is_child {
+                    user |= AddrMgrUser::Assoc;
+                }
+                if e.link_key.is_some() {
+                    user |= AddrMgrUser::Security;
+                }
+                AddrMgrEntry {
+                    user: user.bits(),
+                    // unknown addresses are invalid to the address manager
+                    nwk_addr: e.nwk_address.unwrap_or(0xFFFE),
+                    ext_addr: e.ieee_address.0,
+                }
+            })
+            .collect::<Vec<_>>();
+        if extended {
+            let len = addr_mgr.len().max(self.nv_table(&ADDRMGR_TABLE)?.len());
+            addr_mgr.resize(len, AddrMgrEntry::default());
+            for (i, entry) in addr_mgr.iter().enumerate() {
+                let item = ADDRMGR_TABLE.entry(i as u16, true).unwrap();
+                items.push((item.id(), entry.encode(align)));
+            }
+        } else {
+            // the table has a fixed size, keep it
+            let len = optional(self.nv_get(ADDRMGR))?.map_or(0, |e| e.len());
+            addr_mgr.resize(len.max(addr_mgr.len()), AddrMgrEntry::default());
+            items.push((ADDRMGR.id(), addr_mgr.encode(align)));
+        }
+
+        for (id, value) in &items {
+            self.nv_store(*id, value)?;
+        }
+        for (id, value) in &items {
+            if self.nv_read(*id)? != *value {
+                return Err(Error::Verification(*id));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<T: NVRam> NetworkBackup for T {}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        derive_link_key, Backup, Device, Hex, LinkKey, NetworkBackup, FRAME_COUNTER_MARGIN,
+    };
+    use crate::sim::{Profile, Simulator};
+    use crate::{Builder, Error, ZNP};
+
+    use znp_types::nv::{
+        AddrMgrEntry, KeyAttributes, Nib, NvStruct, NwkActiveKeyItems, NwkKeyDesc,
+        NwkSecMaterialDesc, TCLinkKeyEntry, ADDRMGR, ADDRMGR_TABLE, BDB_NODE_IS_ON_A_NETWORK,
+        CHANLIST, EXTADDR, HAS_CONFIGURED_ZSTACK3, NIB, NWK_ACTIVE_KEY_INFO,
+        NWK_SEC_MATERIAL_TABLE, TCLK_SEED, TCLK_TABLE,
+    };
+
+    use std::time::Duration;
+
+    const SEED: [u8; 16] = [0x5A; 16];
+    const EPID: [u8; 8] = [0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08];
+    const DEVICE: [u8; 8] = [0xA0, 0xA1, 0xA2, 0xA3, 0xA4, 0xA5, 0xA6, 0xA7];
+
+    /// Populates the NV of a coordinator with one joined device.
+    fn provision(sim: &Simulator) {
+        let profile = sim.profile();
+        let align = profile.align_structs;
+        let extended = profile.firmware == crate::sim::Firmware::ZStack3x0;
+        let set = |id, value: Vec<u8>| sim.set_nv(id, value);
+
+        let nib = Nib {
+            nwk_pan_id: 0x1A62,
+            extended_pan_id: EPID,
+            nwk_logical_channel: 15,
+            security_level: 5,
+            nwk_update_id: 2,
+            ..Default::default()
+        };
+        set(NIB.id(), nib.encode(align));
+        set(EXTADDR.id(), [0x11; 8].encode(align));
+        set(CHANLIST.id(), (1u32 << 15 | 1 << 20).encode(align));
+        set(TCLK_SEED.id(), SEED.encode(align));
+        let key_info = NwkActiveKeyItems {
+            active: NwkKeyDesc {
+                key_seq_num: 0,
+                key: [0x42; 16],
+            },
+            frame_counter: 10,
+        };
+        set(NWK_ACTIVE_KEY_INFO.id(), key_info.encode(align));
+        let material = NwkSecMaterialDesc {
+            frame_counter: 4000,
+            extended_pan_id: EPID,
+        };
+        let item = NWK_SEC_MATERIAL_TABLE.entry(0, extended).unwrap();
+        set(item.id(), material.encode(align));
+
+        let device = AddrMgrEntry {
+            user: 0x03,
+            nwk_addr: 0x1234,
+            ext_addr: DEVICE,
+        };
+        let addr_mgr = [device, AddrMgrEntry::default()];
+        if extended {
+            for (i, entry) in addr_mgr.iter().enumerate() {
+                let item = ADDRMGR_TABLE.entry(i as u16, true).unwrap();
+                set(item.id(), entry.encode(align));
+            }
+        } else {
+            set(ADDRMGR.id(), addr_mgr.to_vec().encode(align));
+        }
+        let tclk = TCLinkKeyEntry {
+            tx_frame_counter: 7,
+            rx_frame_counter: 9,
+            ext_addr: DEVICE,
+            key_attributes: KeyAttributes::VerifiedKey as u8,
+            key_type: 0,
+            seed_shift_ic_index: 3,
+        };
+        let item = TCLK_TABLE.entry(0, extended).unwrap();
+        set(item.id(), tclk.encode(align));
+    }
+
+    #[test]
+    fn backup() {
+        for profile in [Profile::zstack_3_0(), Profile::zstack_3_30()] {
+            let (sim, pipe) = Simulator::spawn(profile);
+            provision(&sim);
+            let mut znp = Builder::from_transport(pipe)
+                .timeout(Duration::from_millis(200))
+                .connect()
+                .unwrap();
+            let backup = znp.backup().unwrap();
+            assert_eq!(backup.pan_id, 0x1A62);
+            assert_eq!(backup.channel, 15);
+            assert_eq!(backup.channel_mask, vec![15, 20]);
+            assert_eq!(backup.network_key.frame_counter, 4000);
+            assert_eq!(backup.devices.len(), 1, "{}", znp.version());
+
+            let device = &backup.devices[0];
+            assert!(device.is_child);
+            let link_key = device.link_key.as_ref().unwrap();
+            assert_eq!(link_key.key.0, derive_link_key(&SEED, &DEVICE, 3));
+            assert_eq!(link_key.rx_counter, 9);
+
+            let json = backup.to_json();
+            assert!(json.contains("\"extended_pan_id\": \"0807060504030201\""));
+            assert!(json.contains("\"nwk_address\": \"1234\""));
+            assert_eq!(Backup::from_json(&json).unwrap(), backup);
+        }
+    }
+
+    #[test]
+    fn unknown_nwk_address() {
+        let json = r#"{"nwk_address": null, "ieee_address": "00124b0001020304"}"#;
+        let device: Device = serde_json::from_str(json).unwrap();
+        assert_eq!(device.nwk_address, None);
+        assert_eq!(device.ieee_address.0, [4, 3, 2, 1, 0, 0x4B, 0x12, 0]);
+        let json = serde_json::to_string(&device).unwrap();
+        assert!(json.contains("\"nwk_address\":null"));
+
+        let json = r#"{"nwk_address": "1a2b", "ieee_address": "00124b0001020304"}"#;
+        let device: Device = serde_json::from_str(json).unwrap();
+        assert_eq!(device.nwk_address, Some(0x1A2B));
+    }
+
+    #[test]
+    fn restore() {
+        for profile in [Profile::zstack_3_0(), Profile::zstack_3_30()] {
+            let (sim, pipe) = Simulator::spawn(profile.clone());
+            provision(&sim);
+            let connect = |pipe| {
+                Builder::from_transport(pipe)
+                    .timeout(Duration::from_millis(200))
+                    .connect()
+                    .unwrap()
+            };
+            let mut backup = connect(pipe).backup().unwrap();
+            // a key the seed cannot produce
+            backup.devices.push(Device {
+                nwk_address: Some(0x5678),
+                ieee_address: Hex([0x22; 8]),
+                is_child: false,
+                link_key: Some(LinkKey {
+                    key: Hex([0x33; 16]),
+                    tx_counter: 1,
+                    rx_counter: 2,
+                }),
+            });
+
+            let (fresh, pipe) = Simulator::spawn(profile);
+            let mut znp = connect(pipe);
+            znp.restore(&backup).unwrap();
+            assert_eq!(fresh.nv(TCLK_SEED.id()).unwrap(), SEED);
+            assert_eq!(fresh.nv(BDB_NODE_IS_ON_A_NETWORK.id()).unwrap(), [0x01]);
+            assert_eq!(fresh.nv(HAS_CONFIGURED_ZSTACK3.id()).unwrap(), [0x55]);
+
+            let restored = znp.backup().unwrap();
+            let frame_counter = backup.network_key.frame_counter + FRAME_COUNTER_MARGIN;
+            assert_eq!(restored.network_key.frame_counter, frame_counter);
+            assert_eq!(restored.devices, backup.devices);
+            assert_eq!(restored.pan_id, backup.pan_id);
+            assert_eq!(restored.extended_pan_id, backup.extended_pan_id);
+            assert_eq!(restored.channel_mask, backup.channel_mask);
+
+            let (_, pipe) = Simulator::spawn(Profile::zstack_1_2());
+            let ret = connect(pipe).restore(&backup);
+            assert!(matches!(ret, Err(Error::Unsupported(_))));
+        }
+    }
+}
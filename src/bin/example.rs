@@ -1,22 +1,16 @@
-use serialport::SerialPortType;
-
-use znp::{Builder, Session, ZNP};
-use znp_types::command::sys::{ExNvIds, NVLength, NvSysIds, NVID};
-
-fn get_first_usb_serial() -> String {
-    let ports = serialport::available_ports().unwrap();
-    for port in ports {
-        if let SerialPortType::UsbPort(_) = port.port_type {
-            return port.port_name;
-        }
-    }
-    String::new()
-}
+use znp::{Builder, Discovery, ZNP};
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // let port = get_first_usb_serial();
-    let port = std::env::var("ZNP_SERIAL").unwrap();
-    let mut controller = Builder::from_port(port).connect()?;
+    let port = match std::env::var("ZNP_SERIAL") {
+        Ok(port) => port,
+        Err(_) => Discovery::new()
+            .scan()?
+            .first()
+            .ok_or("no port found")?
+            .port
+            .clone(),
+    };
+    let controller = Builder::from_port(port).connect()?;
     println!("version: {}", controller.version());
     println!(
         "caps: {}, align: {}",
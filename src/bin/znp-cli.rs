@@ -0,0 +1,496 @@
+//! Command-line tool for day-to-day coordinator operations.
+
+use clap::{Args, Parser, Subcommand};
+use serde_json::{json, Value};
+
+use znp::backup::{Backup, Eui64, Hex, Key};
+use znp::dump::NvDump;
+use znp::firmware::HexImage;
+use znp::sbl::{Cc2530Bootloader, Cc26xxBootloader, CC2530_IMAGE_START};
+use znp::{
+    Builder, Candidate, Discovery, JoinEvent, JoinTarget, NVRam, Network, NetworkBackup,
+    NetworkConfig, NvDumper, PermitJoin, Session, APS_TIMEOUT, MAX_PERMIT_DURATION, ZNP,
+};
+use znp_types::command::sys::{Ping, ResetType, VersionInfo, NVID};
+use znp_types::command::zdo::{MgmtNwkUpdateNotify, MgmtNwkUpdateReq, ZdpStatus};
+use znp_types::command::{Command as _, Status};
+
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
+/// Single argument holding bytes, as opposed to a list of arguments.
+type Bytes = Vec<u8>;
+
+#[derive(Parser)]
+#[command(name = "znp-cli", version, about)]
+struct Cli {
+    /// serial port, `tcp://host:port` or `socket://path`, the likeliest
+    /// USB serial port if missing
+    #[arg(short, long, env = "ZNP_SERIAL", global = true)]
+    port: Option<String>,
+    /// time to wait for each response, in ms
+    #[arg(long, global = true)]
+    timeout: Option<u64>,
+    /// prints JSON instead of text, for scripting
+    #[arg(long, global = true)]
+    json: bool,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Lists the USB serial ports, likeliest coordinator first
+    Ports {
+        /// pings each port, without resetting the devices
+        #[arg(long)]
+        probe: bool,
+    },
+    /// Shows the firmware, capabilities and device state
+    Info,
+    /// Checks the device answers
+    Ping,
+    /// Restarts the stack, or the whole chip with --hard
+    Reset {
+        #[arg(long)]
+        hard: bool,
+    },
+    /// Reads and writes NV items
+    #[command(subcommand)]
+    Nv(NvCommand),
+    /// Saves the network to a file, stdout if missing
+    Backup { output: Option<PathBuf> },
+    /// Restores a network saved by `backup`
+    Restore { input: PathBuf },
+    /// Forms a new network as coordinator
+    Form(FormArgs),
+    /// Lets devices join and reports them
+    PermitJoin {
+        /// in seconds, up to 254
+        #[arg(short, long, default_value_t = 60)]
+        duration: u8,
+        /// short address of the router to open, every router if missing
+        #[arg(long, value_parser = parse_u16)]
+        router: Option<u16>,
+    },
+    /// Measures the energy on each channel
+    Scan {
+        /// channels to scan, 11 to 26
+        #[arg(
+            short,
+            long,
+            value_delimiter = ',',
+            default_values_t = 11..=26,
+            value_parser = clap::value_parser!(u8).range(11..=26)
+        )]
+        channels: Vec<u8>,
+        /// exponent of the time spent on each channel, up to 5
+        #[arg(
+            short,
+            long,
+            default_value_t = 3,
+            value_parser = clap::value_parser!(u8).range(0..=5)
+        )]
+        duration: u8,
+    },
+    /// Writes a firmware image through the serial bootloader
+    Flash(FlashArgs),
+}
+
+#[derive(Subcommand)]
+enum NvCommand {
+    /// Prints an item as hex
+    Read {
+        /// `sys:item:sub` in hex, or the id of a legacy item
+        #[arg(value_parser = parse_nvid)]
+        id: NVID,
+    },
+    /// Writes an item, creating it if missing
+    Write {
+        #[arg(value_parser = parse_nvid)]
+        id: NVID,
+        /// value in hex
+        #[arg(value_parser = parse_hex)]
+        value: Bytes,
+    },
+    /// Saves every item to a file, stdout if missing
+    Dump { output: Option<PathBuf> },
+}
+
+#[derive(Args)]
+struct FormArgs {
+    #[arg(long, value_parser = parse_u16, default_value = "1a62")]
+    pan_id: u16,
+    #[arg(long, default_value = "dddddddddddddddd")]
+    extended_pan_id: Eui64,
+    /// 11 to 26
+    #[arg(short, long, default_value_t = 15, value_parser = clap::value_parser!(u8).range(11..=26))]
+    channel: u8,
+    /// random if missing
+    #[arg(long)]
+    network_key: Option<Key>,
+}
+
+#[derive(Args)]
+struct FlashArgs {
+    /// Intel HEX image
+    image: PathBuf,
+    /// talks to the serial boot loader of CC2530 and CC2531 chips instead
+    /// of the ROM bootloader of CC13xx and CC26xx chips
+    #[arg(long)]
+    cc2530: bool,
+    /// flashes images whose CCFG disables the bootloader
+    #[arg(long)]
+    force: bool,
+}
+
+fn parse_u16(s: &str) -> std::result::Result<u16, String> {
+    let s = s.trim_start_matches("0x");
+    u16::from_str_radix(s, 16).map_err(|e| e.to_string())
+}
+
+fn parse_nvid(s: &str) -> std::result::Result<NVID, String> {
+    let parts = s.split(':').collect::<Vec<_>>();
+    match *parts {
+        [id] => Ok(NVID::legacy(parse_u16(id)?)),
+        [sys_id, item_id, sub_id] => {
+            let sys_id = u8::from_str_radix(sys_id, 16).map_err(|e| e.to_string())?;
+            Ok(NVID::new(sys_id, parse_u16(item_id)?, parse_u16(sub_id)?))
+        }
+        _ => Err(format!("expected sys:item:sub or a legacy id, got {:?}", s)),
+    }
+}
+
+fn parse_hex(s: &str) -> std::result::Result<Bytes, String> {
+    let s = s.replace(':', "");
+    if !s.len().is_multiple_of(2) || !s.is_ascii() {
+        return Err(format!("odd number of hex digits in {:?}", s));
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).map_err(|e| e.to_string()))
+        .collect()
+}
+
+fn eui64(addr: [u8; 8]) -> Eui64 { Hex(addr) }
+
+fn to_hex(data: &[u8]) -> String { data.iter().map(|e| format!("{:02x}", e)).collect() }
+
+fn version_string(firmware: &VersionInfo) -> String {
+    let revision = firmware.revision.map(|e| e.to_string());
+    format!(
+        "{:?} {}.{}.{} ({})",
+        firmware.product,
+        firmware.major_rel,
+        firmware.minor_rel,
+        firmware.maint_rel,
+        revision.as_deref().unwrap_or("unknown build"),
+    )
+}
+
+/// Likeliest coordinator among the USB serial ports.
+fn discover_port() -> Result<String> {
+    let candidates = Discovery::new().scan()?;
+    let candidate = candidates.into_iter().next();
+    Ok(candidate.ok_or("no serial port given nor found")?.port)
+}
+
+fn candidate_json(candidate: &Candidate) -> Value {
+    json!({
+        "port": candidate.port,
+        "vid": format!("{:04x}", candidate.vid),
+        "pid": format!("{:04x}", candidate.pid),
+        "manufacturer": candidate.manufacturer,
+        "product": candidate.product,
+        "serial_number": candidate.serial_number,
+        "adapter": candidate.adapter.map(|e| e.name),
+        "fingerprint": format!("{:?}", candidate.fingerprint()),
+        "responds": candidate.responds,
+    })
+}
+
+/// Prints `value` in JSON mode, `text` otherwise.
+fn emit(json: bool, value: Value, text: impl FnOnce() -> String) {
+    match json {
+        true => println!("{}", value),
+        false => println!("{}", text()),
+    }
+}
+
+/// Writes `data` to `path`, stdout if missing.
+fn save(path: Option<&Path>, data: &str) -> Result<()> {
+    match path {
+        Some(path) => std::fs::write(path, data)?,
+        None => println!("{}", data),
+    }
+    Ok(())
+}
+
+fn main() {
+    if let Err(e) = run(Cli::parse()) {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+fn run(cli: Cli) -> Result<()> {
+    if let Command::Ports { probe } = cli.command {
+        let mut discovery = Discovery::new().probe(probe);
+        if let Some(timeout) = cli.timeout {
+            discovery = discovery.timeout(Duration::from_millis(timeout));
+        }
+        for candidate in discovery.scan()? {
+            emit(cli.json, candidate_json(&candidate), || {
+                let adapter = candidate.adapter.map(|e| e.name);
+                let responds = match candidate.responds {
+                    Some(true) => ", responds",
+                    Some(false) => ", silent",
+                    None => "",
+                };
+                format!(
+                    "{} {:04x}:{:04x} {} ({:?}{})",
+                    candidate.port,
+                    candidate.vid,
+                    candidate.pid,
+                    adapter
+                        .or(candidate.product.as_deref())
+                        .unwrap_or("unknown"),
+                    candidate.fingerprint(),
+                    responds,
+                )
+            });
+        }
+        return Ok(());
+    }
+    let port = match cli.port {
+        Some(port) => port,
+        None => discover_port()?,
+    };
+    // the bootloaders are reached before any firmware answers
+    if let Command::Flash(args) = &cli.command {
+        return flash(&port, args, cli.json);
+    }
+
+    let mut builder = Builder::from_port(port);
+    if let Some(timeout) = cli.timeout {
+        builder = builder.timeout(Duration::from_millis(timeout));
+    }
+    let mut znp = builder.connect()?;
+    let json = cli.json;
+    match cli.command {
+        Command::Info => {
+            let firmware = znp.firmware();
+            let info = znp.device_info()?;
+            let value = json!({
+                "version": znp.version().to_string(),
+                "product": format!("{:?}", firmware.product),
+                "revision": firmware.revision,
+                "capabilities": format!("{:?}", znp.capabilities()),
+                "align_structs": znp.align_structs(),
+                "ieee_addr": eui64(info.ieee_addr).to_string(),
+                "short_addr": format!("{:04x}", info.short_addr),
+                "device_state": format!("{:?}", info.device_state),
+                "assoc_devices": info.assoc_devices.len(),
+            });
+            emit(json, value, || {
+                format!(
+                    "firmware: {}\ncapabilities: {:?}\nieee: {}\nshort: {:04x}\nstate: {:?}\n\
+                     associated devices: {}",
+                    version_string(&firmware),
+                    znp.capabilities(),
+                    eui64(info.ieee_addr),
+                    info.short_addr,
+                    info.device_state,
+                    info.assoc_devices.len(),
+                )
+            });
+        }
+        Command::Ping => {
+            let start = Instant::now();
+            let capabilities = znp.request(&Ping::default())?;
+            let elapsed = start.elapsed();
+            let value = json!({
+                "capabilities": format!("{:?}", capabilities),
+                "rtt_ms": elapsed.as_secs_f64() * 1000.0,
+            });
+            emit(json, value, || format!("pong in {:?}", elapsed));
+        }
+        Command::Reset { hard } => {
+            let kind = match hard {
+                true => ResetType::Hard,
+                false => ResetType::Soft,
+            };
+            let info = znp.reset(kind)?;
+            let value = json!({
+                "reason": format!("{:?}", info.reason),
+                "hw_rev": info.hw_rev,
+            });
+            emit(json, value, || format!("reset, reason: {:?}", info.reason));
+        }
+        Command::Nv(NvCommand::Read { id }) => {
+            let value = znp.nv_read(id)?;
+            emit(json, json!({ "value": to_hex(&value) }), || to_hex(&value));
+        }
+        Command::Nv(NvCommand::Write { id, value }) => {
+            znp.nv_store(id, &value)?;
+            emit(json, json!({ "written": value.len() }), || {
+                format!("wrote {} bytes", value.len())
+            });
+        }
+        Command::Nv(NvCommand::Dump { output }) => {
+            let dump: NvDump = znp.nv_dump()?;
+            save(output.as_deref(), &dump.to_json())?;
+        }
+        Command::Backup { output } => {
+            let backup = znp.backup()?;
+            save(output.as_deref(), &backup.to_json())?;
+        }
+        Command::Restore { input } => {
+            let backup = Backup::from_json(&std::fs::read_to_string(input)?)?;
+            znp.restore(&backup)?;
+            let value = json!({ "devices": backup.devices.len() });
+            emit(json, value, || {
+                format!("restored network with {} devices", backup.devices.len())
+            });
+        }
+        Command::Form(args) => {
+            let network_key = match args.network_key {
+                Some(key) => key.0,
+                None => {
+                    let mut key = [u8::MIN; 16];
+                    getrandom::getrandom(&mut key).map_err(|e| e.to_string())?;
+                    key
+                }
+            };
+            let config = NetworkConfig::new(
+                args.pan_id,
+                args.extended_pan_id.0,
+                1 << args.channel,
+                network_key,
+            );
+            znp.form_network(&config)?;
+            let value = json!({
+                "pan_id": format!("{:04x}", args.pan_id),
+                "extended_pan_id": args.extended_pan_id.to_string(),
+                "channel": args.channel,
+            });
+            emit(json, value, || {
+                format!(
+                    "formed network {:04x} on channel {}",
+                    args.pan_id, args.channel
+                )
+            });
+        }
+        Command::PermitJoin { duration, router } => {
+            let target = router.map_or(JoinTarget::All, JoinTarget::Router);
+            let duration = Duration::from_secs(duration.into()).min(MAX_PERMIT_DURATION);
+            let window = znp.permit_join(duration, target)?;
+            while let Some(event) = znp.next_join_event(&window)? {
+                let (value, text) = match event {
+                    JoinEvent::Joined(device) => {
+                        let ieee = eui64(device.ieee_addr);
+                        let value = json!({
+                            "event": "joined",
+                            "ieee_addr": ieee.to_string(),
+                            "nwk_addr": format!("{:04x}", device.nwk_addr),
+                            "parent_addr": format!("{:04x}", device.parent_addr),
+                        });
+                        (value, format!("joined: {} ({:04x})", ieee, device.nwk_addr))
+                    }
+                    JoinEvent::Announced(device) => {
+                        let ieee = eui64(device.ieee_addr);
+                        let value = json!({
+                            "event": "announced",
+                            "ieee_addr": ieee.to_string(),
+                            "nwk_addr": format!("{:04x}", device.nwk_addr),
+                        });
+                        (
+                            value,
+                            format!("announced: {} ({:04x})", ieee, device.nwk_addr),
+                        )
+                    }
+                };
+                emit(json, value, || text);
+            }
+        }
+        Command::Scan { channels, duration } => {
+            let mask = channels.iter().fold(0u32, |acc, e| acc | 1 << e);
+            let notifications = znp.dispatcher().subscribe(MgmtNwkUpdateNotify::ID);
+            match znp.request(&MgmtNwkUpdateReq::new(0x0000, mask, duration, 1))? {
+                Status::Success => {}
+                status => return Err(znp::Error::Status(status).into()),
+            }
+            // (2^n + 1) superframes of 15.36 ms on each channel
+            let per_channel = Duration::from_micros(15360 * ((1 << duration) + 1));
+            let timeout = APS_TIMEOUT + per_channel * channels.len() as u32;
+            let rsp = znp.wait_for::<MgmtNwkUpdateNotify>(&notifications, timeout)?;
+            if rsp.status != ZdpStatus::Success {
+                return Err(znp::Error::Zdp(rsp.status).into());
+            }
+            let scanned = (11..=26).filter(|e| rsp.scanned_channels & 1 << e != 0);
+            let energy = scanned.zip(rsp.energy_values).collect::<Vec<_>>();
+            let value = energy
+                .iter()
+                .map(|(channel, energy)| json!({ "channel": channel, "energy": energy }))
+                .collect();
+            emit(json, Value::Array(value), || {
+                energy
+                    .iter()
+                    .map(|(channel, energy)| format!("{:>2}: {:>3}", channel, energy))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            });
+        }
+        Command::Ports { .. } | Command::Flash(_) => unreachable!(),
+    }
+    Ok(())
+}
+
+fn flash(port: &str, args: &FlashArgs, json: bool) -> Result<()> {
+    let image = HexImage::parse(&std::fs::read_to_string(&args.image)?)?;
+    let (start, mut data) = image.to_bytes();
+    let start = match args.cc2530 {
+        true => {
+            // the serial boot loader sits below the image and stays
+            let skip = CC2530_IMAGE_START.saturating_sub(start) as usize;
+            data.drain(..skip.min(data.len()));
+            start.max(CC2530_IMAGE_START)
+        }
+        false => {
+            let bootloader = image.ccfg().is_some_and(|e| e.bootloader_enabled());
+            if !bootloader && !args.force {
+                return Err("the image disables the bootloader, use --force to flash it".into());
+            }
+            start
+        }
+    };
+    if !json {
+        let version = image.version();
+        let version = version.as_ref().map(version_string);
+        println!(
+            "flashing {} bytes at {:#x}, firmware {}",
+            data.len(),
+            start,
+            version.as_deref().unwrap_or("unknown"),
+        );
+    }
+
+    match args.cc2530 {
+        true => {
+            let mut sbl = Cc2530Bootloader::open(port)?;
+            sbl.flash(start, &data)?;
+            sbl.enable()?;
+            sbl.skip()?;
+        }
+        false => {
+            let mut sbl = Cc26xxBootloader::open(port)?;
+            sbl.flash(start, &data)?;
+            sbl.reset()?;
+        }
+    }
+    let value = json!({ "address": start, "len": data.len() });
+    emit(json, value, || "done".to_string());
+    Ok(())
+}
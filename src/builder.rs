@@ -1,61 +1,180 @@
-use semver::Version;
+use semver::{BuildMetadata, Version};
+use std::net::TcpStream;
 use std::time::Duration;
 
+use enumflags2::BitFlags;
 use serialport::{DataBits, StopBits};
 
-use znp_types::command::sys::{ExNvIds, NVLength, NvSysIds, Ping, NVID};
+use znp_types::command::sys::{Ping, Product, SysVersion, VersionInfo};
 use znp_types::command::util::AssocFindDevice;
+use znp_types::packet::Decoder;
 
-use crate::{Error, Session, ZNPImpl, ZNP};
+use crate::sbl::SKIP_BOOTLOADER;
+use crate::transport::reset_tty;
+use crate::{Dispatcher, Error, RetryPolicy, Session, Transport, ZNPImpl, DEFAULT_TIMEOUT, ZNP};
+
+enum Target {
+    Port(String),
+    Transport(Box<dyn Transport>),
+}
+
+/// Whether the firmware pads its structs, from the length of the
+/// `UTIL_ASSOC_FIND_DEVICE` response.
+pub(crate) fn align_structs(device: &[u8]) -> Result<bool, Error> {
+    match device.len() {
+        28 => Ok(false),
+        36 => Ok(true),
+        _ => Err(Error::Unknown),
+    }
+}
+
+/// What connecting learns about the firmware, shared by [`Builder::connect`]
+/// and [`crate::AsyncZNP::connect`], which send the requests.
+pub(crate) struct Identity {
+    pub firmware: VersionInfo,
+    pub version: Version,
+    pub align_structs: bool,
+}
+
+impl Identity {
+    /// Identifies the firmware from its `SYS_VERSION` response.
+    pub fn new(firmware: VersionInfo) -> Self {
+        Self {
+            version: stack_version(&firmware),
+            firmware,
+            align_structs: false,
+        }
+    }
+
+    /// Request whose response [`Self::set_alignment`] expects, if the struct
+    /// alignment needs probing.
+    pub fn alignment_probe(&self) -> Option<AssocFindDevice> {
+        // Z-Stack 1.2 runs on 8051 chips, its structs are packed
+        (self.firmware.product != Product::ZStack12).then(|| AssocFindDevice::new(0))
+    }
+
+    pub fn set_alignment(&mut self, device: &[u8]) -> Result<(), Error> {
+        self.align_structs = align_structs(device)?;
+        Ok(())
+    }
+}
+
+/// Z-Stack version [`ZNP::version`] reports for the product in
+/// `SYS_VERSION`, with the release it reports as build metadata.
+pub(crate) fn stack_version(firmware: &VersionInfo) -> Version {
+    let mut ret = match firmware.product {
+        Product::ZStack12 => Version::new(1, 2, 0),
+        Product::ZStack30 => Version::new(3, 0, 0),
+        Product::ZStack3x0 => Version::new(3, 30, 0),
+    };
+    let release = format!(
+        "{}.{}.{}",
+        firmware.major_rel, firmware.minor_rel, firmware.maint_rel
+    );
+    ret.build = BuildMetadata::new(&release).unwrap_or(BuildMetadata::EMPTY);
+    ret
+}
 
 pub struct Builder {
-    port: String,
+    target: Target,
+
+    timeout: Duration,
+    retry_policy: RetryPolicy,
 }
 
 impl Builder {
-    pub fn from_port(port: String) -> Self { Self { port } }
+    /// Connects to a tty name, `tcp://host:port`, or `socket://host:port`.
+    /// On unix, `socket:///path` connects to a Unix socket instead.
+    pub fn from_port(port: String) -> Self { Self::from_target(Target::Port(port)) }
 
-    pub fn connect(self) -> Result<impl ZNP, Error> {
-        let mut tty = serialport::new(self.port, 115200)
+    /// Connects over an already opened transport, e.g. a [`crate::pipe`].
+    pub fn from_transport(transport: impl Transport + 'static) -> Self {
+        Self::from_target(Target::Transport(Box::new(transport)))
+    }
+
+    fn from_target(target: Target) -> Self {
+        Self {
+            target,
+            timeout: DEFAULT_TIMEOUT,
+            retry_policy: RetryPolicy::none(),
+        }
+    }
+
+    /// Time to wait for the SRSP of each request.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Retry policy for requests that timed out.
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    fn open_tty(port: String) -> Result<Box<dyn Transport>, Error> {
+        let mut tty = serialport::new(port, 115200)
             .data_bits(DataBits::Eight)
             .stop_bits(StopBits::One)
             .open()
             .map_err(Error::TTY)?;
         // reset ZNP board to skip bootloader
-        for (dtr, rts) in [(false, false), (false, true), (false, false)] {
-            tty.write_data_terminal_ready(dtr).map_err(Error::TTY)?;
-            tty.write_request_to_send(rts).map_err(Error::TTY)?;
-        }
+        reset_tty(tty.as_mut(), false).map_err(Error::TTY)?;
         std::thread::sleep(Duration::from_millis(150));
 
-        // clear tty
-        let clr = vec![0x10u8 ^ 0xFF; 256];
+        // clear tty, skipping the serial boot loader of CC2530 chips
+        let clr = vec![SKIP_BOOTLOADER; 256];
         tty.write_all(clr.as_slice()).map_err(Error::IO)?;
         std::thread::sleep(Duration::from_millis(2500));
 
-        let capabilities = tty.request(&Ping::default())?;
-        let device = tty.request(&AssocFindDevice::new(0))?;
-        let align_structs = match device.len() {
-            28 => false,
-            36 => true,
-            _ => return Err(Error::Unknown),
-        };
+        Ok(Box::new(tty))
+    }
 
-        let mut version = Version::new(3, 30, 0);
-        if let Err(crate::Error::CommandNotFound) = tty.request(&NVLength::new(NVID::new(
-            NvSysIds::ZStack as u8,
-            ExNvIds::TClkTable as u16,
-            0,
-        ))) {
-            version = Version::new(3, 0, 0);
+    fn open(port: String) -> Result<Box<dyn Transport>, Error> {
+        if let Some(addr) = port.strip_prefix("tcp://") {
+            let stream = TcpStream::connect(addr).map_err(Error::IO)?;
+            return Ok(Box::new(stream));
+        }
+        if let Some(addr) = port.strip_prefix("socket://") {
+            #[cfg(unix)]
+            if addr.starts_with('/') {
+                let stream = std::os::unix::net::UnixStream::connect(addr).map_err(Error::IO)?;
+                return Ok(Box::new(stream));
+            }
+            let stream = TcpStream::connect(addr).map_err(Error::IO)?;
+            return Ok(Box::new(stream));
         }
+        Self::open_tty(port)
+    }
 
-        let ret = ZNPImpl {
-            version,
-            align_structs,
-            capabilities,
-            tty,
+    pub fn connect(self) -> Result<impl ZNP, Error> {
+        let transport = match self.target {
+            Target::Port(port) => Self::open(port)?,
+            Target::Transport(transport) => transport,
         };
+
+        let mut ret = ZNPImpl {
+            version: Version::new(0, 0, 0),
+            firmware: VersionInfo::default(),
+            align_structs: false,
+            capabilities: BitFlags::empty(),
+            transport,
+            decoder: Decoder::new(),
+            dispatcher: Dispatcher::new(),
+            timeout: self.timeout,
+            retry_policy: self.retry_policy,
+            tsn: 0,
+        };
+
+        ret.capabilities = ret.request(&Ping::default())?;
+        let mut identity = Identity::new(ret.request(&SysVersion::default())?);
+        if let Some(probe) = identity.alignment_probe() {
+            identity.set_alignment(&ret.request(&probe)?)?;
+        }
+        ret.firmware = identity.firmware;
+        ret.version = identity.version;
+        ret.align_structs = identity.align_structs;
+
         Ok(ret)
     }
 }
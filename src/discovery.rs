@@ -0,0 +1,293 @@
+//! Finding the serial port of a coordinator among the USB serial ports.
+
+use crate::{Error, Transport};
+
+use serialport::{DataBits, SerialPortInfo, SerialPortType, StopBits};
+use znp_types::command::sys::Ping;
+use znp_types::command::CommandType;
+use znp_types::packet::{Decoder, Packet};
+
+use std::cmp::Reverse;
+use std::io::ErrorKind;
+use std::time::{Duration, Instant};
+
+use log::debug;
+
+/// How strongly a USB device looks like a coordinator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Fingerprint {
+    /// any other USB serial port
+    Unknown,
+    /// USB to UART bridge also found on other devices
+    Bridge,
+    /// device only found on coordinators
+    Coordinator,
+}
+
+/// USB device known to carry a Z-Stack coordinator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KnownAdapter {
+    pub vid: u16,
+    pub pid: u16,
+    pub name: &'static str,
+    pub fingerprint: Fingerprint,
+}
+
+/// USB devices of the common coordinator sticks and boards.
+pub const KNOWN_ADAPTERS: &[KnownAdapter] = &[
+    KnownAdapter {
+        vid: 0x0451,
+        pid: 0x16A8,
+        name: "TI CC2531 USB dongle",
+        fingerprint: Fingerprint::Coordinator,
+    },
+    KnownAdapter {
+        vid: 0x0451,
+        pid: 0xBEF3,
+        name: "TI LaunchPad with XDS110 debugger",
+        fingerprint: Fingerprint::Coordinator,
+    },
+    // SONOFF ZBDongle-P, slaesh's CC2652RB stick and many others
+    KnownAdapter {
+        vid: 0x10C4,
+        pid: 0xEA60,
+        name: "Silicon Labs CP210x UART bridge",
+        fingerprint: Fingerprint::Bridge,
+    },
+];
+
+/// USB serial port that may be a coordinator, see [`Discovery`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candidate {
+    pub port: String,
+    pub vid: u16,
+    pub pid: u16,
+    pub manufacturer: Option<String>,
+    pub product: Option<String>,
+    pub serial_number: Option<String>,
+    /// known adapter with the VID:PID of the port
+    pub adapter: Option<KnownAdapter>,
+    /// whether a coordinator answered on the port, `None` if not probed
+    pub responds: Option<bool>,
+}
+
+impl Candidate {
+    /// Candidate for a USB port, `None` for other ports.
+    pub fn from_port(info: SerialPortInfo) -> Option<Self> {
+        let SerialPortType::UsbPort(usb) = info.port_type else {
+            return None;
+        };
+        let adapter = KNOWN_ADAPTERS
+            .iter()
+            .find(|e| e.vid == usb.vid && e.pid == usb.pid)
+            .copied();
+        let ret = Self {
+            port: info.port_name,
+            vid: usb.vid,
+            pid: usb.pid,
+            manufacturer: usb.manufacturer,
+            product: usb.product,
+            serial_number: usb.serial_number,
+            adapter,
+            responds: None,
+        };
+        Some(ret)
+    }
+
+    /// Fingerprint of the port, bridges whose product string names Zigbee
+    /// counting as coordinators.
+    pub fn fingerprint(&self) -> Fingerprint {
+        let named = self
+            .product
+            .as_ref()
+            .is_some_and(|e| e.to_lowercase().contains("zigbee"));
+        match self.adapter {
+            Some(_) if named => Fingerprint::Coordinator,
+            Some(adapter) => adapter.fingerprint,
+            None => Fingerprint::Unknown,
+        }
+    }
+}
+
+/// Sends `SYS_PING` and waits up to `timeout` for its SRSP, leaving the
+/// device and whatever it sent before untouched.
+pub(crate) fn ping(transport: &mut impl Transport, timeout: Duration) -> Result<(), Error> {
+    let request = Packet::from_command(&Ping::default());
+    transport
+        .write_all(&request.serialize())
+        .map_err(Error::IO)?;
+    let deadline = Instant::now() + timeout;
+    let mut decoder = Decoder::new();
+    let mut chunk = [u8::MIN; 256];
+    loop {
+        while let Some(frame) = decoder.decode() {
+            if frame.command_type() == CommandType::SRSP as u8
+                && frame.command_id() == request.command_id()
+            {
+                return Ok(());
+            }
+        }
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(Error::Timeout);
+        }
+        transport.set_timeout(remaining).map_err(Error::IO)?;
+        match transport.read(&mut chunk) {
+            Ok(0) => return Err(Error::Timeout),
+            Ok(len) => decoder.extend(&chunk[..len]),
+            Err(e) if e.kind() == ErrorKind::TimedOut => {}
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(Error::IO(e)),
+        }
+    }
+}
+
+/// Opens `port` as is, without the reset [`crate::Builder`] does, and pings
+/// it.
+fn probe_port(port: &str, timeout: Duration) -> Result<(), Error> {
+    let mut tty = serialport::new(port, 115200)
+        .data_bits(DataBits::Eight)
+        .stop_bits(StopBits::One)
+        .timeout(timeout)
+        .open()
+        .map_err(Error::TTY)?;
+    ping(&mut tty, timeout)
+}
+
+/// Ranks the USB serial ports by how likely they are a coordinator.
+///
+/// Ports answering the probe come first, then ports by [`Fingerprint`],
+/// then by name, putting the application UART of an XDS110 before its
+/// auxiliary port.
+#[derive(Debug, Clone)]
+pub struct Discovery {
+    probe: bool,
+    timeout: Duration,
+}
+
+impl Default for Discovery {
+    fn default() -> Self { Self::new() }
+}
+
+impl Discovery {
+    pub fn new() -> Self {
+        Self {
+            probe: false,
+            timeout: Duration::from_secs(1),
+        }
+    }
+
+    /// Sends `SYS_PING` on each port and waits up to [`Discovery::timeout`]
+    /// for the answer. The devices are neither reset nor flushed, a device
+    /// still in its bootloader stays silent.
+    pub fn probe(mut self, probe: bool) -> Self {
+        self.probe = probe;
+        self
+    }
+
+    /// Time to wait for the probe of each port.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Candidates among the serial ports of the system, best first.
+    pub fn scan(&self) -> Result<Vec<Candidate>, Error> {
+        let ports = serialport::available_ports().map_err(Error::TTY)?;
+        Ok(self.rank(ports))
+    }
+
+    /// Candidates among `ports`, best first.
+    pub fn rank(&self, ports: Vec<SerialPortInfo>) -> Vec<Candidate> {
+        let mut ret = ports
+            .into_iter()
+            .filter_map(Candidate::from_port)
+            .collect::<Vec<_>>();
+        if self.probe {
+            for candidate in ret.iter_mut() {
+                let ret = probe_port(&candidate.port, self.timeout);
+                if let Err(e) = &ret {
+                    debug!("probing {} failed: {}", candidate.port, e);
+                }
+                candidate.responds = Some(ret.is_ok());
+            }
+        }
+        ret.sort_by(|a, b| {
+            let key = |e: &Candidate| (e.responds != Some(true), Reverse(e.fingerprint()));
+            key(a).cmp(&key(b)).then_with(|| a.port.cmp(&b.port))
+        });
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ping, Discovery, Fingerprint};
+    use crate::pipe;
+    use crate::sim::{Profile, Simulator};
+    use crate::Error;
+
+    use serialport::{SerialPortInfo, SerialPortType, UsbPortInfo};
+
+    use std::time::Duration;
+
+    fn usb(port: &str, vid: u16, pid: u16, product: &str) -> SerialPortInfo {
+        SerialPortInfo {
+            port_name: port.to_string(),
+            port_type: SerialPortType::UsbPort(UsbPortInfo {
+                vid,
+                pid,
+                serial_number: None,
+                manufacturer: None,
+                product: Some(product.to_string()),
+            }),
+        }
+    }
+
+    #[test]
+    fn rank() {
+        let ports = vec![
+            SerialPortInfo {
+                port_name: "/dev/ttyS0".to_string(),
+                port_type: SerialPortType::Unknown,
+            },
+            usb("/dev/ttyUSB0", 0x0403, 0x6001, "FT232R USB UART"),
+            usb("/dev/ttyUSB1", 0x10C4, 0xEA60, "CP2102 USB to UART Bridge"),
+            usb("/dev/ttyACM1", 0x0451, 0xBEF3, "XDS110"),
+            usb("/dev/ttyACM0", 0x0451, 0xBEF3, "XDS110"),
+            usb(
+                "/dev/ttyUSB2",
+                0x10C4,
+                0xEA60,
+                "Sonoff Zigbee 3.0 USB Dongle Plus",
+            ),
+        ];
+        let ret = Discovery::new().rank(ports);
+        let ports = ret.iter().map(|e| e.port.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            ports,
+            [
+                "/dev/ttyACM0",
+                "/dev/ttyACM1",
+                "/dev/ttyUSB2",
+                "/dev/ttyUSB1",
+                "/dev/ttyUSB0"
+            ]
+        );
+        assert_eq!(ret[2].fingerprint(), Fingerprint::Coordinator);
+        assert_eq!(ret[3].fingerprint(), Fingerprint::Bridge);
+        assert_eq!(ret[4].adapter, None);
+        assert_eq!(ret[0].responds, None);
+    }
+
+    #[test]
+    fn ping_answers() {
+        let (_sim, mut host) = Simulator::spawn(Profile::zstack_3_30());
+        ping(&mut host, Duration::from_millis(200)).unwrap();
+
+        let (mut host, _device) = pipe();
+        let ret = ping(&mut host, Duration::from_millis(50));
+        assert!(matches!(ret, Err(Error::Timeout)));
+    }
+}
@@ -0,0 +1,125 @@
+use znp_types::command::{de, CommandID};
+use znp_types::packet::Packet;
+
+use std::collections::HashMap;
+use std::sync::mpsc;
+
+use log::debug;
+
+type Handler = Box<dyn FnMut(&Packet) + Send>;
+
+/// Routes unsolicited AREQ frames to registered handlers and channels,
+/// keyed on subsystem and command id.
+#[derive(Default)]
+pub struct Dispatcher {
+    handlers: HashMap<[u8; 2], Vec<Handler>>,
+    channels: HashMap<[u8; 2], Vec<mpsc::Sender<Packet>>>,
+}
+
+impl Dispatcher {
+    pub fn new() -> Self { Self::default() }
+
+    /// Registers a handler called with every raw frame matching `id`.
+    pub fn on_frame(&mut self, id: CommandID, handler: impl FnMut(&Packet) + Send + 'static) {
+        self.handlers
+            .entry(id.to_cmd())
+            .or_default()
+            .push(Box::new(handler));
+    }
+
+    /// Registers a handler called with every decoded `C` indication.
+    pub fn on<C>(&mut self, mut handler: impl FnMut(C::Output) + Send + 'static)
+    where
+        C: de::Command + Default,
+    {
+        self.on_frame(C::ID, move |frame| {
+            match C::default().deserialize(frame.command.clone()) {
+                Ok(output) => handler(output),
+                Err(e) => debug!("failed to decode callback {:?}: {:?}", C::ID.to_cmd(), e),
+            }
+        });
+    }
+
+    /// Returns a channel receiving every raw frame matching `id`.
+    /// The channel is unregistered once the receiver is dropped.
+    pub fn subscribe(&mut self, id: CommandID) -> mpsc::Receiver<Packet> {
+        let (tx, rx) = mpsc::channel();
+        self.channels.entry(id.to_cmd()).or_default().push(tx);
+        rx
+    }
+
+    /// Removes every handler and channel registered for `id`.
+    pub fn clear(&mut self, id: CommandID) {
+        self.handlers.remove(&id.to_cmd());
+        self.channels.remove(&id.to_cmd());
+    }
+
+    /// Delivers `frame` to everything registered for its command id.
+    /// Returns whether anything consumed the frame.
+    pub fn dispatch(&mut self, frame: &Packet) -> bool {
+        let cmd = frame.command_id();
+        let mut consumed = false;
+        if let Some(handlers) = self.handlers.get_mut(&cmd) {
+            for handler in handlers.iter_mut() {
+                handler(frame);
+            }
+            consumed |= !handlers.is_empty();
+        }
+        if let Some(channels) = self.channels.get_mut(&cmd) {
+            channels.retain(|tx| tx.send(frame.clone()).is_ok());
+            consumed |= !channels.is_empty();
+        }
+        if !consumed {
+            debug!("unhandled callback {:?}", cmd);
+        }
+        consumed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Dispatcher;
+
+    use znp_types::command::{CommandID, Subsystem};
+    use znp_types::packet::Packet;
+
+    use std::sync::{Arc, Mutex};
+
+    fn frame(cmd: [u8; 2]) -> Packet {
+        let command = vec![0x00, cmd[0], cmd[1]];
+        let frame_check_sequence = command.iter().fold(0, |acc, &e| acc ^ e);
+        Packet {
+            start_of_frame: 0xFE,
+            command,
+            frame_check_sequence,
+        }
+    }
+
+    #[test]
+    fn dispatch_by_command_id() {
+        let mut dispatcher = Dispatcher::new();
+        let seen = Arc::new(Mutex::new(0));
+        let seen_handler = seen.clone();
+        dispatcher.on_frame(
+            CommandID {
+                subsystem: Subsystem::IFaceZDO,
+                id: 0xC0,
+            },
+            move |_| *seen_handler.lock().unwrap() += 1,
+        );
+        let rx = dispatcher.subscribe(CommandID {
+            subsystem: Subsystem::IFaceSYS,
+            id: 0x80,
+        });
+
+        assert!(dispatcher.dispatch(&frame([0x45, 0xC0])));
+        assert!(dispatcher.dispatch(&frame([0x41, 0x80])));
+        assert!(!dispatcher.dispatch(&frame([0x44, 0x81])));
+
+        assert_eq!(*seen.lock().unwrap(), 1);
+        assert_eq!(rx.try_recv().unwrap().command_id(), [0x01, 0x80]);
+
+        drop(rx);
+        assert!(!dispatcher.dispatch(&frame([0x41, 0x80])));
+    }
+}
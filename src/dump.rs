@@ -0,0 +1,234 @@
+//! Byte-exact dumps of every NV item, for cloning coordinators.
+
+use crate::{Error, NVRam};
+
+use znp_types::command::sys::{ExNvIds, NvSysIds, NVID};
+
+use log::{debug, warn};
+use semver::Version;
+use serde::{Deserialize, Serialize};
+
+pub const NV_DUMP_FORMAT: &str = "znp/nv-dump";
+pub const NV_DUMP_VERSION: u32 = 1;
+
+/// Highest legacy OSAL item id.
+pub const LEGACY_ID_MAX: u16 = 0x0FFF;
+
+const SYS_IDS: [NvSysIds; 8] = [
+    NvSysIds::NvDrvr,
+    NvSysIds::ZStack,
+    NvSysIds::TiMac,
+    NvSysIds::Remoti,
+    NvSysIds::Ble,
+    NvSysIds::_6Mesh,
+    NvSysIds::TiOp,
+    NvSysIds::App,
+];
+
+const EX_IDS: [ExNvIds; 7] = [
+    ExNvIds::AddrMgr,
+    ExNvIds::BindingTable,
+    ExNvIds::DeviceList,
+    ExNvIds::TClkTable,
+    ExNvIds::TClkIcTable,
+    ExNvIds::ApsKeyDataTable,
+    ExNvIds::NwkSecMaterialTable,
+];
+
+/// Value as lowercase hex.
+mod hex_bytes {
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(val: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        let s = val.iter().map(|e| format!("{:02x}", e)).collect::<String>();
+        serializer.serialize_str(&s)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        if s.len() % 2 != 0 || !s.is_ascii() {
+            return Err(de::Error::custom("odd number of hex digits"));
+        }
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).map_err(de::Error::custom))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DumpItem {
+    pub sys_id: u8,
+    pub item_id: u16,
+    pub sub_id: u16,
+    #[serde(with = "hex_bytes")]
+    pub value: Vec<u8>,
+}
+
+impl DumpItem {
+    pub fn id(&self) -> NVID { NVID::new(self.sys_id, self.item_id, self.sub_id) }
+}
+
+/// Every NV item of a device, legacy items under [`NVID::legacy`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NvDump {
+    pub format: String,
+    pub version: u32,
+    /// Firmware version the dump was taken from.
+    pub firmware: String,
+    pub align_structs: bool,
+    pub items: Vec<DumpItem>,
+    /// Legacy items left out as too long for the firmware to read whole.
+    #[serde(default)]
+    pub skipped: Vec<u16>,
+}
+
+impl NvDump {
+    pub fn to_json(&self) -> String { serde_json::to_string_pretty(self).unwrap() }
+
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        let ret: Self = serde_json::from_str(json)?;
+        if ret.format != NV_DUMP_FORMAT || ret.version > NV_DUMP_VERSION {
+            let msg = format!("unsupported dump {} version {}", ret.format, ret.version);
+            return Err(serde::de::Error::custom(msg));
+        }
+        Ok(ret)
+    }
+}
+
+/// Raw dump and restore of the whole NV memory.
+pub trait NvDumper: NVRam {
+    /// Reads every legacy item up to [`LEGACY_ID_MAX`] and, on Z-Stack
+    /// 3.x.0, every extended item, each sub id up to the first missing one.
+    /// Items the firmware cannot read whole are recorded as skipped.
+    fn nv_dump(&mut self) -> Result<NvDump, Error> {
+        let mut items = vec![];
+        let mut skipped = vec![];
+        let mut push = |id: NVID, value: Vec<u8>| {
+            items.push(DumpItem {
+                sys_id: id.sys_id(),
+                item_id: id.item_id(),
+                sub_id: id.sub_id(),
+                value,
+            })
+        };
+
+        for osal_id in 0..=LEGACY_ID_MAX {
+            let id = NVID::legacy(osal_id);
+            if !self.nv_exists(id)? {
+                continue;
+            }
+            match self.nv_read(id) {
+                Ok(value) => push(id, value),
+                // beyond the 8-bit offsets of old firmware
+                Err(Error::Unsupported(_)) => {
+                    warn!("skipping NV item {:?}, too long to read", id);
+                    skipped.push(osal_id);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        if self.version() >= Version::new(3, 30, 0) {
+            for sys_id in SYS_IDS {
+                for item_id in EX_IDS {
+                    for sub_id in 0..=u16::MAX {
+                        let id = NVID::new(sys_id as u8, item_id as u16, sub_id);
+                        if !self.nv_exists(id)? {
+                            break;
+                        }
+                        push(id, self.nv_read(id)?);
+                    }
+                }
+            }
+        }
+        debug!("dumped {} NV items", items.len());
+
+        let ret = NvDump {
+            format: NV_DUMP_FORMAT.to_string(),
+            version: NV_DUMP_VERSION,
+            firmware: self.version().to_string(),
+            align_structs: self.align_structs(),
+            items,
+            skipped,
+        };
+        Ok(ret)
+    }
+
+    /// Writes every item of `dump`, creating missing ones.
+    ///
+    /// Fails with [`Error::Unsupported`] if the struct layout of the device
+    /// differs from the dump, or the dump holds extended items the firmware
+    /// cannot store, and with [`Error::IncompleteDump`] if items were
+    /// skipped when dumping.
+    fn nv_restore(&mut self, dump: &NvDump) -> Result<(), Error> {
+        if !dump.skipped.is_empty() {
+            let ids = dump.skipped.iter().map(|&e| NVID::legacy(e)).collect();
+            return Err(Error::IncompleteDump(ids));
+        }
+        let has_extended = dump.items.iter().any(|e| e.id().legacy_id().is_none());
+        let extended = self.version() >= Version::new(3, 30, 0);
+        if dump.align_structs != self.align_structs() || (has_extended && !extended) {
+            return Err(Error::Unsupported(self.version()));
+        }
+
+        for item in &dump.items {
+            self.nv_store(item.id(), &item.value)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: NVRam> NvDumper for T {}
+
+#[cfg(test)]
+mod tests {
+    use super::{NvDump, NvDumper};
+    use crate::sim::{Profile, Simulator};
+    use crate::Error;
+
+    use znp_types::command::sys::{ExNvIds, NvSysIds, NVID};
+
+    #[test]
+    fn dump_and_restore() {
+        let ex_id = |sub_id| NVID::new(NvSysIds::ZStack as u8, ExNvIds::TClkTable as u16, sub_id);
+        let (sim, mut znp) = Simulator::connect(Profile::zstack_3_30());
+        sim.set_nv(NVID::legacy(0x0021), vec![0x21; 116]);
+        sim.set_nv(NVID::legacy(0x0F00), vec![0x55]);
+        sim.set_nv(ex_id(0), vec![0x00; 20]);
+        sim.set_nv(ex_id(1), vec![0x01; 20]);
+        // not reachable past the missing sub id 2
+        sim.set_nv(ex_id(3), vec![0x03; 20]);
+
+        let dump = znp.nv_dump().unwrap();
+        assert_eq!(dump.items.len(), 4);
+        let dump = NvDump::from_json(&dump.to_json()).unwrap();
+
+        let (fresh, mut znp) = Simulator::connect(Profile::zstack_3_30());
+        fresh.set_nv(NVID::legacy(0x0021), vec![0x00; 110]);
+        znp.nv_restore(&dump).unwrap();
+        for item in &dump.items {
+            assert_eq!(fresh.nv(item.id()).unwrap(), item.value);
+        }
+
+        let (_, mut znp) = Simulator::connect(Profile::zstack_3_0());
+        assert!(matches!(znp.nv_restore(&dump), Err(Error::Unsupported(_))));
+    }
+
+    #[test]
+    fn refuse_incomplete_restore() {
+        let (sim, mut znp) = Simulator::connect(Profile::zstack_3_30());
+        sim.set_nv(NVID::legacy(0x0021), vec![0x21; 16]);
+        let mut dump = znp.nv_dump().unwrap();
+        // as if too long for old firmware to read
+        dump.skipped.push(0x0080);
+        let dump = NvDump::from_json(&dump.to_json()).unwrap();
+
+        let (fresh, mut znp) = Simulator::connect(Profile::zstack_3_30());
+        match znp.nv_restore(&dump) {
+            Err(Error::IncompleteDump(ids)) => assert_eq!(ids, vec![NVID::legacy(0x0080)]),
+            _ => panic!("restored an incomplete dump"),
+        }
+        assert_eq!(fresh.nv(NVID::legacy(0x0021)), None);
+    }
+}
@@ -0,0 +1,283 @@
+//! Firmware images in Intel HEX format.
+
+use znp_types::command::de::Command;
+use znp_types::command::sys::{Product, SysVersion, VersionInfo};
+
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+/// Flash sizes of the supported chips, the CCFG area ending the flash.
+const FLASH_SIZES: [u32; 3] = [0x20000, 0x58000, 0xB0000];
+
+/// Length of the customer configuration area of CC13xx and CC26xx chips.
+pub const CCFG_LEN: usize = 0x58;
+
+/// Value of the CCFG fields enabling the bootloader and its backdoor.
+const CCFG_ENABLED: u8 = 0xC5;
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum HexError {
+    #[error("line {0}: malformed record")]
+    Syntax(usize),
+    #[error("line {0}: checksum mismatch")]
+    Checksum(usize),
+    #[error("line {0}: unsupported record type {1:#04x}")]
+    RecordType(usize, u8),
+    #[error("line {0}: data overlaps a previous record")]
+    Overlap(usize),
+    #[error("missing end of file record")]
+    MissingEof,
+}
+
+/// Sparse memory image, contiguous data merged into segments.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HexImage {
+    segments: BTreeMap<u32, Vec<u8>>,
+    /// entry point from a start address record
+    pub start_address: Option<u32>,
+}
+
+impl HexImage {
+    /// Parses Intel HEX, with extended segment and extended linear
+    /// addresses.
+    pub fn parse(hex: &str) -> Result<Self, HexError> {
+        let mut ret = Self::default();
+        let mut base = 0u32;
+        for (i, line) in hex.lines().enumerate() {
+            let line_no = i + 1;
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let record = line
+                .strip_prefix(':')
+                .filter(|e| e.len() % 2 == 0 && e.is_ascii())
+                .ok_or(HexError::Syntax(line_no))?;
+            let record = (0..record.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(&record[i..i + 2], 16))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| HexError::Syntax(line_no))?;
+            let [len, hi, lo, kind, ..] = *record else {
+                return Err(HexError::Syntax(line_no));
+            };
+            if record.len() != len as usize + 5 {
+                return Err(HexError::Syntax(line_no));
+            }
+            if record.iter().fold(u8::MIN, |acc, &e| acc.wrapping_add(e)) != 0 {
+                return Err(HexError::Checksum(line_no));
+            }
+            let data = &record[4..record.len() - 1];
+            let be = |len: usize| {
+                (data.len() == len)
+                    .then(|| data.iter().fold(0u32, |acc, &e| acc << 8 | e as u32))
+                    .ok_or(HexError::Syntax(line_no))
+            };
+            match kind {
+                0x00 => {
+                    let address = base.wrapping_add(u16::from_be_bytes([hi, lo]).into());
+                    if !ret.insert(address, data) {
+                        return Err(HexError::Overlap(line_no));
+                    }
+                }
+                0x01 => return Ok(ret),
+                0x02 => base = be(2)? << 4,
+                0x04 => base = be(2)? << 16,
+                // CS:IP
+                0x03 => {
+                    let cs_ip = be(4)?;
+                    ret.start_address = Some((cs_ip >> 16 << 4) + (cs_ip & 0xFFFF));
+                }
+                0x05 => ret.start_address = Some(be(4)?),
+                kind => return Err(HexError::RecordType(line_no, kind)),
+            }
+        }
+        Err(HexError::MissingEof)
+    }
+
+    /// Adds `data`, returning `false` if it overlaps existing data.
+    fn insert(&mut self, address: u32, data: &[u8]) -> bool {
+        let end = address as u64 + data.len() as u64;
+        if let Some((&start, prev)) = self.segments.range(..end as u32).next_back() {
+            if start as u64 + prev.len() as u64 > address as u64 {
+                return false;
+            }
+        }
+        // merge with the previous and the next segment when contiguous
+        let mut start = address;
+        let mut merged = data.to_vec();
+        if let Some((&prev, bytes)) = self.segments.range(..address).next_back() {
+            if prev as u64 + bytes.len() as u64 == address as u64 {
+                start = prev;
+                let mut bytes = self.segments.remove(&prev).unwrap();
+                bytes.extend(merged);
+                merged = bytes;
+            }
+        }
+        if end <= u32::MAX as u64 {
+            if let Some(next) = self.segments.remove(&(end as u32)) {
+                merged.extend(next);
+            }
+        }
+        self.segments.insert(start, merged);
+        true
+    }
+
+    /// Contiguous runs of data, by address.
+    pub fn segments(&self) -> impl Iterator<Item = (u32, &[u8])> {
+        self.segments.iter().map(|(&k, v)| (k, v.as_slice()))
+    }
+
+    /// First and one past the last address holding data.
+    pub fn range(&self) -> Option<(u32, u32)> {
+        let (&start, _) = self.segments.first_key_value()?;
+        let (&last, data) = self.segments.last_key_value()?;
+        Some((start, last + data.len() as u32))
+    }
+
+    /// Bytes from `address` if every one of them is in the image.
+    pub fn read(&self, address: u32, len: usize) -> Option<&[u8]> {
+        let (&start, data) = self.segments.range(..=address).next_back()?;
+        let offset = (address - start) as usize;
+        data.get(offset..offset + len)
+    }
+
+    /// Whole image from its first address, gaps filled with `0xFF` as
+    /// erased flash.
+    pub fn to_bytes(&self) -> (u32, Vec<u8>) {
+        let Some((start, end)) = self.range() else {
+            return (0, vec![]);
+        };
+        let mut ret = vec![0xFF; (end - start) as usize];
+        for (address, data) in self.segments() {
+            let offset = (address - start) as usize;
+            ret[offset..offset + data.len()].copy_from_slice(data);
+        }
+        (start, ret)
+    }
+
+    /// Customer configuration area ending the image, if the image ends with
+    /// the flash of a CC13xx or CC26xx chip.
+    pub fn ccfg(&self) -> Option<Ccfg> {
+        let (_, end) = self.range()?;
+        if !FLASH_SIZES.contains(&end) {
+            return None;
+        }
+        let address = end - CCFG_LEN as u32;
+        let data = self.read(address, CCFG_LEN)?.try_into().unwrap();
+        Some(Ccfg { address, data })
+    }
+
+    /// Version the firmware reports through `SYS_VERSION`, found by looking
+    /// for its constant response carrying a build date.
+    pub fn version(&self) -> Option<VersionInfo> {
+        self.segments().find_map(|(_, data)| {
+            data.windows(9).find_map(|e| {
+                let ret = SysVersion::default().to_output(e.to_vec()).ok()?;
+                let plausible = ret.transport_rev == 2
+                    && ret.product != Product::ZStack12
+                    && ret.major_rel == 2
+                    && ret.revision.is_some_and(is_date);
+                plausible.then_some(ret)
+            })
+        })
+    }
+
+    /// Build date of the firmware as `YYYYMMDD`.
+    pub fn build_date(&self) -> Option<u32> { self.version()?.revision }
+
+    /// How the image compares to the firmware `running` on a device,
+    /// [`Ordering::Greater`] meaning the image is newer.
+    ///
+    /// `None` if either build date is unknown or the image is built from
+    /// another Z-Stack generation.
+    pub fn compare(&self, running: &VersionInfo) -> Option<Ordering> {
+        let image = self.version()?;
+        if image.product != running.product {
+            return None;
+        }
+        Some(image.revision?.cmp(&running.revision?))
+    }
+}
+
+fn is_date(code: u32) -> bool {
+    let (year, month, day) = (code / 10000, code / 100 % 100, code % 100);
+    (2010..2100).contains(&year) && (1..=12).contains(&month) && (1..=31).contains(&day)
+}
+
+/// Customer configuration of a CC13xx or CC26xx chip, applied at boot.
+/// See CC13x2, CC26x2 SimpleLink Wireless MCU Technical Reference Manual,
+/// 9.1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ccfg {
+    pub address: u32,
+    pub data: [u8; CCFG_LEN],
+}
+
+impl Ccfg {
+    fn bl_config(&self) -> u32 { u32::from_le_bytes(self.data[0x30..0x34].try_into().unwrap()) }
+
+    /// Whether the ROM bootloader stays reachable once the image is
+    /// flashed.
+    pub fn bootloader_enabled(&self) -> bool { (self.bl_config() >> 24) as u8 == CCFG_ENABLED }
+
+    /// Pin and active level that enter the bootloader at reset, if the
+    /// backdoor is enabled.
+    pub fn backdoor(&self) -> Option<(u8, bool)> {
+        let config = self.bl_config();
+        let enabled = self.bootloader_enabled() && config as u8 == CCFG_ENABLED;
+        enabled.then_some(((config >> 8) as u8, config & 1 << 16 != 0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HexError, HexImage};
+    use crate::sim::{Profile, Simulator};
+    use crate::{Builder, ZNP};
+
+    use znp_types::command::sys::Product;
+
+    use std::cmp::Ordering;
+    use std::time::Duration;
+
+    const LAUNCHPAD: &str =
+        include_str!("../firmware/CC1352P2_CC2652P_launchpad_coordinator_20230507.hex");
+
+    #[test]
+    fn parse() {
+        let image = HexImage::parse(":0400000A01020304E8\n:00000001FF\n");
+        assert_eq!(image, Err(HexError::RecordType(1, 0x0A)));
+        let image = HexImage::parse(":020000040001F9\n:0400000001020304F2\n");
+        assert_eq!(image, Err(HexError::MissingEof));
+        let hex = ":020000040001F9\n:0400000001020304F2\n:020004000506EF\n:00000001FF\n";
+        let image = HexImage::parse(hex).unwrap();
+        assert_eq!(image.read(0x10000, 6), Some(&[1, 2, 3, 4, 5, 6][..]));
+        assert_eq!(image.segments().count(), 1);
+    }
+
+    #[test]
+    fn launchpad() {
+        let image = HexImage::parse(LAUNCHPAD).unwrap();
+        assert_eq!(image.range(), Some((0x00000, 0x58000)));
+        let ccfg = image.ccfg().unwrap();
+        assert_eq!(ccfg.address, 0x57FA8);
+        assert!(ccfg.bootloader_enabled());
+        // BTN-1, active low
+        assert_eq!(ccfg.backdoor(), Some((15, false)));
+
+        let version = image.version().unwrap();
+        assert_eq!(version.product, Product::ZStack3x0);
+        assert_eq!(image.build_date(), Some(20230507));
+
+        let (_, pipe) = Simulator::spawn(Profile::zstack_3_30());
+        let znp = Builder::from_transport(pipe)
+            .timeout(Duration::from_millis(200))
+            .connect()
+            .unwrap();
+        assert_eq!(image.compare(&znp.firmware()), Some(Ordering::Greater));
+        let (_, pipe) = Simulator::spawn(Profile::zstack_3_0());
+        let znp = Builder::from_transport(pipe).connect().unwrap();
+        assert_eq!(image.compare(&znp.firmware()), None);
+    }
+}
@@ -1,48 +1,77 @@
-use crate::{Error, Session, ZNP};
+use crate::{Dispatcher, Error, RetryPolicy, Session, Transport, ZNP};
 
-use znp_types::command::ser;
 use znp_types::command::ser::Command;
-use znp_types::command::sys::Capability;
-use znp_types::packet::Packet;
+use znp_types::command::sys::{Capability, VersionInfo};
+use znp_types::packet::{self, Decoder, Packet};
 
-use std::io::Write;
+use std::io::ErrorKind;
+use std::time::{Duration, Instant};
 
 use enumflags2::BitFlags;
 use semver::Version;
-use serialport::SerialPort;
 
-impl Session for Box<dyn SerialPort> {
-    fn send_command(&mut self, command: &impl ser::Command) -> Result<(), Error> {
-        let packet = Packet::from_command(command).serialize();
-        self.write_all(packet.as_slice()).map_err(Error::IO)?;
-
-        Ok(())
-    }
-
-    fn recv_frame(&mut self) -> Result<Packet, Error> {
-        let packet = Packet::from_reader(self).map_err(Error::Packet)?;
-        Ok(packet)
-    }
-}
-
-pub struct ZNPImpl {
+pub struct ZNPImpl<T: Transport> {
     pub(crate) version: Version,
+    pub(crate) firmware: VersionInfo,
 
     pub(crate) align_structs: bool,
     pub(crate) capabilities: enumflags2::BitFlags<Capability>,
 
-    pub(crate) tty: Box<dyn SerialPort>,
+    pub(crate) transport: T,
+    pub(crate) decoder: Decoder,
+    pub(crate) dispatcher: Dispatcher,
+
+    pub(crate) timeout: Duration,
+    pub(crate) retry_policy: RetryPolicy,
+    /// transaction sequence number of the last data request
+    pub(crate) tsn: u8,
 }
 
-impl Session for ZNPImpl {
+impl<T: Transport> Session for ZNPImpl<T> {
     fn send_command(&mut self, command: &impl Command) -> Result<(), Error> {
-        self.tty.send_command(command)
+        let packet = Packet::from_command(command).serialize();
+        self.transport
+            .write_all(packet.as_slice())
+            .map_err(Error::IO)?;
+
+        Ok(())
+    }
+    fn recv_frame(&mut self, timeout: Duration) -> Result<Packet, Error> {
+        let deadline = Instant::now() + timeout;
+        let mut chunk = [u8::MIN; 256];
+        loop {
+            if let Some(packet) = self.decoder.decode() {
+                return Ok(packet);
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(Error::Timeout);
+            }
+            self.transport.set_timeout(remaining).map_err(Error::IO)?;
+            match self.transport.read(&mut chunk) {
+                Ok(0) => return Err(Error::Packet(packet::Error::UnexpectedEOF)),
+                Ok(len) => self.decoder.extend(&chunk[..len]),
+                Err(e) if e.kind() == ErrorKind::TimedOut => {}
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(Error::IO(e)),
+            }
+        }
+    }
+    fn dispatcher(&mut self) -> &mut Dispatcher { &mut self.dispatcher }
+
+    fn timeout(&self) -> Duration { self.timeout }
+    fn retry_policy(&self) -> RetryPolicy { self.retry_policy.clone() }
+
+    fn next_tsn(&mut self) -> u8 {
+        self.tsn = self.tsn.wrapping_add(1);
+        self.tsn
     }
-    fn recv_frame(&mut self) -> Result<Packet, Error> { self.tty.recv_frame() }
 }
 
-impl ZNP for ZNPImpl {
+impl<T: Transport> ZNP for ZNPImpl<T> {
     fn version(&self) -> Version { self.version.clone() }
+    fn firmware(&self) -> VersionInfo { self.firmware.clone() }
 
     fn align_structs(&self) -> bool { self.align_structs }
     fn capabilities(&self) -> BitFlags<Capability> { self.capabilities }
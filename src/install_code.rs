@@ -0,0 +1,244 @@
+//! Install codes of Zigbee 3.0 devices and the link keys derived from them.
+
+use crate::{check, Error, NVRam};
+
+use znp_types::command::app_cnf::{BdbAddInstallCode, InstallCodeFormat};
+use znp_types::command::Status;
+use znp_types::nv::{KeyAttributes, TCLinkKeyEntry, TCLK_IC_TABLE, TCLK_TABLE};
+
+use std::str::FromStr;
+
+use aes::cipher::{generic_array::GenericArray, BlockEncrypt, KeyInit};
+use aes::Aes128;
+use log::debug;
+use semver::Version;
+
+/// Lengths of an install code without its CRC.
+pub const INSTALL_CODE_LENGTHS: [usize; 4] = [6, 8, 12, 16];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InstallCodeError {
+    /// not one of [`INSTALL_CODE_LENGTHS`] plus the CRC
+    Length(usize),
+    Crc {
+        expected: u16,
+        found: u16,
+    },
+    Hex,
+}
+
+/// Install code followed by its CRC, as printed on the device.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstallCode(Vec<u8>);
+
+impl InstallCode {
+    /// Validates `bytes`, the code followed by its little-endian CRC.
+    pub fn new(bytes: &[u8]) -> Result<Self, InstallCodeError> {
+        let len = bytes.len().saturating_sub(2);
+        if !INSTALL_CODE_LENGTHS.contains(&len) {
+            return Err(InstallCodeError::Length(bytes.len()));
+        }
+        let (code, crc) = bytes.split_at(len);
+        let expected = crc16(code);
+        let found = u16::from_le_bytes([crc[0], crc[1]]);
+        if expected != found {
+            return Err(InstallCodeError::Crc { expected, found });
+        }
+        Ok(Self(bytes.to_vec()))
+    }
+
+    /// Code without the CRC.
+    pub fn code(&self) -> &[u8] { &self.0[..self.0.len() - 2] }
+
+    /// Code followed by the CRC.
+    pub fn as_bytes(&self) -> &[u8] { &self.0 }
+
+    /// Trust center link key the device uses to join.
+    pub fn link_key(&self) -> [u8; 16] { mmo_hash(&self.0) }
+}
+
+impl FromStr for InstallCode {
+    type Err = InstallCodeError;
+
+    /// Parses hex digits, ignoring whitespace and dashes.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let digits = s
+            .chars()
+            .filter(|e| !e.is_whitespace() && *e != '-')
+            .map(|e| e.to_digit(16).map(|e| e as u8))
+            .collect::<Option<Vec<_>>>()
+            .ok_or(InstallCodeError::Hex)?;
+        if digits.len() % 2 != 0 {
+            return Err(InstallCodeError::Hex);
+        }
+        let bytes = digits
+            .chunks(2)
+            .map(|e| e[0] << 4 | e[1])
+            .collect::<Vec<_>>();
+        Self::new(&bytes)
+    }
+}
+
+/// CRC-16/X-25 protecting install codes.
+/// See Zigbee Base Device Behavior Specification, 10.1.
+pub fn crc16(data: &[u8]) -> u16 {
+    let crc = data.iter().fold(0xFFFFu16, |mut crc, &e| {
+        crc ^= e as u16;
+        for _ in 0..8 {
+            crc = match crc & 1 {
+                0 => crc >> 1,
+                _ => crc >> 1 ^ 0x8408,
+            };
+        }
+        crc
+    });
+    !crc
+}
+
+/// Matyas-Meyer-Oseas hash over AES-128.
+/// See Zigbee Specification, B.6.
+pub fn mmo_hash(data: &[u8]) -> [u8; 16] {
+    // a single 1 bit, zeros and the length in bits, for messages shorter
+    // than 2^16 bits
+    let mut padded = data.to_vec();
+    padded.push(0x80);
+    while padded.len() % 16 != 14 {
+        padded.push(0x00);
+    }
+    padded.extend(((data.len() * 8) as u16).to_be_bytes());
+
+    let mut hash = [u8::MIN; 16];
+    for block in padded.chunks(16) {
+        let cipher = Aes128::new(GenericArray::from_slice(&hash));
+        let mut out = GenericArray::clone_from_slice(block);
+        cipher.encrypt_block(&mut out);
+        for (i, e) in hash.iter_mut().enumerate() {
+            *e = out[i] ^ block[i];
+        }
+    }
+    hash
+}
+
+/// Install code commissioning on top of [`NVRam`].
+pub trait InstallCodes: NVRam {
+    /// Lets the device `ieee_addr` join with the link key derived from
+    /// `code`.
+    ///
+    /// Registers the key with `APP_CNF_BDB_ADD_INSTALLCODE`, or in the
+    /// trust center key tables if the firmware lacks the command. Z-Stack
+    /// Home 1.2 has no install code support.
+    fn add_install_code(&mut self, ieee_addr: [u8; 8], code: &InstallCode) -> Result<(), Error> {
+        if self.version() < Version::new(3, 0, 0) {
+            return Err(Error::Unsupported(self.version()));
+        }
+        let key = code.link_key();
+        let request = BdbAddInstallCode::new(InstallCodeFormat::DerivedKey, ieee_addr, key.into());
+        match self.request(&request) {
+            Err(Error::CommandNotFound) => {
+                debug!("no APP_CNF_BDB_ADD_INSTALLCODE, writing the key tables");
+                store_key(self, ieee_addr, key)
+            }
+            ret => check(ret?),
+        }
+    }
+}
+
+impl<T: NVRam> InstallCodes for T {}
+
+/// Writes `key` to the install code table, referenced by a provisional
+/// entry of the trust center link key table.
+fn store_key<N: NVRam + ?Sized>(
+    nv: &mut N,
+    ieee_addr: [u8; 8],
+    key: [u8; 16],
+) -> Result<(), Error> {
+    let extended = nv.version() >= Version::new(3, 30, 0);
+    let tclk = nv.nv_table(&TCLK_TABLE)?;
+    let provisional = |e: &TCLinkKeyEntry| e.key_attributes == KeyAttributes::ProvisionalKey as u8;
+
+    // the entry of the device, or the first unused one
+    let index = tclk
+        .iter()
+        .position(|e| e.ext_addr == ieee_addr)
+        .or_else(|| {
+            tclk.iter()
+                .position(|e| e.key_attributes == KeyAttributes::DefaultKey as u8)
+        })
+        .unwrap_or(tclk.len());
+    let ic_index = match tclk.get(index) {
+        Some(e) if e.ext_addr == ieee_addr && provisional(e) => Some(e.seed_shift_ic_index),
+        _ => (0..=u8::MAX).find(|&i| {
+            !tclk
+                .iter()
+                .any(|e| provisional(e) && e.seed_shift_ic_index == i)
+        }),
+    };
+
+    let full = || Error::Status(Status::ApsTableFull);
+    let ic_index = ic_index.ok_or_else(full)?;
+    let ic_item = TCLK_IC_TABLE
+        .entry(ic_index.into(), extended)
+        .ok_or_else(full)?;
+    let tclk_item = TCLK_TABLE.entry(index as u16, extended).ok_or_else(full)?;
+    nv.nv_set(ic_item, &key)?;
+    let entry = TCLinkKeyEntry {
+        ext_addr: ieee_addr,
+        key_attributes: KeyAttributes::ProvisionalKey as u8,
+        seed_shift_ic_index: ic_index,
+        ..Default::default()
+    };
+    nv.nv_set(tclk_item, &entry)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{InstallCode, InstallCodeError, InstallCodes};
+    use crate::sim::{Profile, Simulator};
+    use crate::{Error, NVRam};
+
+    use znp_types::nv::{KeyAttributes, TCLK_IC_TABLE, TCLK_TABLE};
+
+    const CODE: &str = "83FED3407A939723A5C639B26916D505C3B5";
+    const KEY: [u8; 16] = [
+        0x66, 0xB6, 0x90, 0x09, 0x81, 0xE1, 0xEE, 0x3C, 0xA4, 0x20, 0x6B, 0x6B, 0x86, 0x1C, 0x02,
+        0xBB,
+    ];
+
+    #[test]
+    fn derive_key() {
+        let code = CODE.parse::<InstallCode>().unwrap();
+        assert_eq!(code.code().len(), 16);
+        assert_eq!(code.link_key(), KEY);
+
+        let ret = "83FED3407A939723A5C639B26916D505C3B6".parse::<InstallCode>();
+        assert!(matches!(ret, Err(InstallCodeError::Crc { .. })));
+        let ret = "83FED3407A939723A5".parse::<InstallCode>();
+        assert_eq!(ret, Err(InstallCodeError::Length(9)));
+    }
+
+    #[test]
+    fn add_install_code() {
+        let code = CODE.parse::<InstallCode>().unwrap();
+        let ieee = [0x11; 8];
+
+        let (sim, mut znp) = Simulator::connect(Profile::zstack_3_30());
+        znp.add_install_code(ieee, &code).unwrap();
+        assert_eq!(sim.install_codes(), vec![(ieee, KEY.to_vec())]);
+
+        // no APP_CNF_BDB_ADD_INSTALLCODE
+        let (_, mut znp) = Simulator::connect(Profile::zstack_3_0());
+        znp.add_install_code([0x22; 8], &code).unwrap();
+        znp.add_install_code(ieee, &code).unwrap();
+        znp.add_install_code(ieee, &code).unwrap();
+        let tclk = znp.nv_table(&TCLK_TABLE).unwrap();
+        assert_eq!(tclk.len(), 2);
+        assert_eq!(tclk[1].ext_addr, ieee);
+        assert_eq!(tclk[1].key_attributes, KeyAttributes::ProvisionalKey as u8);
+        assert_eq!(tclk[1].seed_shift_ic_index, 1);
+        assert_eq!(znp.nv_table(&TCLK_IC_TABLE).unwrap(), vec![KEY; 2]);
+
+        let (_, mut znp) = Simulator::connect(Profile::zstack_1_2());
+        let ret = znp.add_install_code(ieee, &code);
+        assert!(matches!(ret, Err(Error::Unsupported(_))));
+    }
+}
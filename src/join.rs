@@ -0,0 +1,208 @@
+//! Opening the network to joining devices.
+
+use crate::{check, Error, APS_TIMEOUT, ZNP};
+
+use znp_types::command::zdo::{
+    DeviceAnnouncement, EndDeviceAnnceInd, MgmtPermitJoinReq, MgmtPermitJoinRsp, TcDevInd,
+    TcDevice, ZdpStatus,
+};
+use znp_types::command::{de, Command};
+use znp_types::packet::Packet;
+
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use log::debug;
+
+/// Broadcast address of the coordinator and every router.
+pub const BROADCAST_ROUTERS: u16 = 0xFFFC;
+
+/// Longest joining window, Zigbee 3.0 no longer allows keeping the
+/// network open indefinitely.
+pub const MAX_PERMIT_DURATION: Duration = Duration::from_secs(254);
+
+/// Devices asked to accept joining devices.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoinTarget {
+    /// the coordinator and every router
+    All,
+    /// a single router, or the coordinator at `0x0000`
+    Router(u16),
+}
+
+impl JoinTarget {
+    fn addr(self) -> u16 {
+        match self {
+            JoinTarget::All => BROADCAST_ROUTERS,
+            JoinTarget::Router(addr) => addr,
+        }
+    }
+}
+
+/// Device joining while a [`JoinWindow`] is open.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JoinEvent {
+    /// the trust center let a device in, from `ZDO_TC_DEV_IND`
+    Joined(TcDevice),
+    /// a device announced itself, from `ZDO_END_DEVICE_ANNCE_IND`
+    Announced(DeviceAnnouncement),
+}
+
+/// Joining window opened by [`PermitJoin::permit_join`], collecting the
+/// devices that join until it closes.
+pub struct JoinWindow {
+    target: JoinTarget,
+    deadline: Instant,
+    joins: mpsc::Receiver<Packet>,
+    announcements: mpsc::Receiver<Packet>,
+}
+
+impl JoinWindow {
+    pub fn target(&self) -> JoinTarget { self.target }
+
+    /// Time until the devices stop accepting joins.
+    pub fn remaining(&self) -> Duration { self.deadline.saturating_duration_since(Instant::now()) }
+
+    pub fn is_open(&self) -> bool { !self.remaining().is_zero() }
+
+    /// Next event already received, if any.
+    fn try_event(&self) -> Result<Option<JoinEvent>, Error> {
+        fn decode<C: de::Command + Default>(frame: Packet) -> Result<C::Output, Error> {
+            C::default()
+                .deserialize(frame.command)
+                .map_err(Error::Deserialization)
+        }
+
+        if let Ok(frame) = self.joins.try_recv() {
+            return Ok(Some(JoinEvent::Joined(decode::<TcDevInd>(frame)?)));
+        }
+        if let Ok(frame) = self.announcements.try_recv() {
+            return Ok(Some(JoinEvent::Announced(decode::<EndDeviceAnnceInd>(
+                frame,
+            )?)));
+        }
+        Ok(None)
+    }
+}
+
+/// Permit joining through `ZDO_MGMT_PERMIT_JOIN_REQ`.
+pub trait PermitJoin: ZNP {
+    /// Lets devices join through `target` for `duration`, capped to
+    /// [`MAX_PERMIT_DURATION`], once the target confirms.
+    fn permit_join(&mut self, duration: Duration, target: JoinTarget) -> Result<JoinWindow, Error> {
+        // subscribe first, devices may join before the confirmation
+        let joins = self.dispatcher().subscribe(TcDevInd::ID);
+        let announcements = self.dispatcher().subscribe(EndDeviceAnnceInd::ID);
+
+        let seconds = duration.min(MAX_PERMIT_DURATION).as_secs() as u8;
+        request_permit_join(self, target, seconds)?;
+        let ret = JoinWindow {
+            target,
+            deadline: Instant::now() + Duration::from_secs(seconds.into()),
+            joins,
+            announcements,
+        };
+        Ok(ret)
+    }
+
+    /// Closes `window` before it runs out, events already received stay
+    /// available.
+    fn close_join(&mut self, window: &mut JoinWindow) -> Result<(), Error> {
+        request_permit_join(self, window.target, 0)?;
+        window.deadline = Instant::now();
+        Ok(())
+    }
+
+    /// Waits for the next device joining through `window`, returning `None`
+    /// once the window is closed and every event was received.
+    fn next_join_event(&mut self, window: &JoinWindow) -> Result<Option<JoinEvent>, Error> {
+        loop {
+            if let Some(event) = window.try_event()? {
+                return Ok(Some(event));
+            }
+            let remaining = window.remaining();
+            if remaining.is_zero() {
+                return Ok(None);
+            }
+            match self.poll(remaining) {
+                Err(Error::Timeout) => {}
+                ret => ret?,
+            }
+        }
+    }
+}
+
+impl<T: ZNP> PermitJoin for T {}
+
+/// Sends the request and waits up to [`APS_TIMEOUT`] for the response
+/// of the target, the coordinator answering for broadcasts.
+fn request_permit_join<Z: ZNP + ?Sized>(
+    znp: &mut Z,
+    target: JoinTarget,
+    seconds: u8,
+) -> Result<(), Error> {
+    let responses = znp.dispatcher().subscribe(MgmtPermitJoinRsp::ID);
+    check(znp.request(&MgmtPermitJoinReq::new(target.addr(), seconds))?)?;
+
+    let src_addr = match target {
+        JoinTarget::All => 0x0000,
+        JoinTarget::Router(addr) => addr,
+    };
+    let deadline = Instant::now() + APS_TIMEOUT;
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let rsp = znp.wait_for::<MgmtPermitJoinRsp>(&responses, remaining)?;
+        if rsp.src_addr != src_addr {
+            debug!("skipping permit join response of {:#06x}", rsp.src_addr);
+            continue;
+        }
+        return match rsp.status {
+            ZdpStatus::Success => Ok(()),
+            status => Err(Error::Zdp(status)),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{JoinEvent, JoinTarget, PermitJoin};
+    use crate::sim::{Profile, Simulator};
+    use crate::{Builder, Error};
+
+    use znp_types::command::zdo::ZdpStatus;
+
+    use std::time::Duration;
+
+    #[test]
+    fn permit_join() {
+        let (sim, pipe) = Simulator::spawn(Profile::zstack_3_30());
+        let mut znp = Builder::from_transport(pipe)
+            .timeout(Duration::from_millis(200))
+            .connect()
+            .unwrap();
+        assert!(!sim.join(0x1234, [0xAA; 8]));
+
+        let mut window = znp
+            .permit_join(Duration::from_secs(60), JoinTarget::All)
+            .unwrap();
+        assert!(window.remaining() > Duration::from_secs(58));
+        assert!(sim.join(0x1234, [0xAA; 8]));
+        let Some(JoinEvent::Joined(device)) = znp.next_join_event(&window).unwrap() else {
+            panic!("expected a trust center indication");
+        };
+        assert_eq!(device.nwk_addr, 0x1234);
+        let Some(JoinEvent::Announced(device)) = znp.next_join_event(&window).unwrap() else {
+            panic!("expected an announcement");
+        };
+        assert_eq!(device.ieee_addr, [0xAA; 8]);
+
+        znp.close_join(&mut window).unwrap();
+        assert!(!window.is_open());
+        assert!(!sim.join(0x5678, [0xBB; 8]));
+        assert_eq!(znp.next_join_event(&window).unwrap(), None);
+
+        // no such router
+        let ret = znp.permit_join(Duration::from_secs(60), JoinTarget::Router(0x4321));
+        assert!(matches!(ret, Err(Error::Zdp(ZdpStatus::DeviceNotFound))));
+    }
+}
@@ -1,18 +1,73 @@
-use znp_types::command::sys::Capability;
-use znp_types::command::{de, ser};
+use znp_types::command::af::{DataConfirm, DataRequestExt, DataStore, DATA_STORE_MAX};
+use znp_types::command::app_cnf::CommissioningStatus;
+use znp_types::command::sys::{
+    Capability, ResetInd, ResetInfo, ResetReq, ResetType, VersionInfo, NVID,
+};
+use znp_types::command::util::{DeviceInfo, GetDeviceInfo};
+use znp_types::command::zdo::{StartupState, ZdpStatus};
+use znp_types::command::{de, ser, Command, CommandType, Status, COMMAND_TYPE_FLAG};
 use znp_types::packet::{self, Packet};
 
-use std::time::Duration;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use log::{debug, warn};
 
 use semver::Version;
 
+#[cfg(feature = "tokio")]
+mod asynch;
+#[cfg(feature = "tokio")]
+pub use asynch::{AsyncSession, AsyncTransport, AsyncZNP};
 mod builder;
 pub use builder::Builder;
+mod discovery;
+pub use discovery::{Candidate, Discovery, Fingerprint, KnownAdapter, KNOWN_ADAPTERS};
+mod dispatch;
+pub use dispatch::Dispatcher;
 mod imple;
+mod policy;
+#[cfg(any(test, feature = "sim"))]
+pub mod sim;
+pub use policy::{RetryPolicy, APS_TIMEOUT, DEFAULT_TIMEOUT, RESET_TIMEOUT};
+mod transport;
+pub use transport::{pipe, Pipe, Transport};
 mod nv;
+pub use nv::{NVRam, NV_CHUNK_LEN};
+pub mod backup;
+pub use backup::{Backup, NetworkBackup};
+pub mod dump;
+pub mod firmware;
+pub mod sbl;
+pub use dump::{NvDump, NvDumper};
+mod network;
+pub use network::{ConfigMismatch, Network, NetworkConfig, FORMATION_TIMEOUT};
+mod install_code;
+pub use install_code::{
+    crc16, mmo_hash, InstallCode, InstallCodeError, InstallCodes, INSTALL_CODE_LENGTHS,
+};
+mod join;
+pub use join::{
+    JoinEvent, JoinTarget, JoinWindow, PermitJoin, BROADCAST_ROUTERS, MAX_PERMIT_DURATION,
+};
 
 use imple::ZNPImpl;
 
+/// Command id of `RPC_Error`, the SRSP to a request the device rejects.
+const RPC_ERROR: [u8; 2] = [0x00, 0x00];
+
+/// Whether `frame` answers a request for `cmd`, as its SRSP or as an
+/// `RPC_Error` echoing it.
+pub(crate) fn answers(frame: &Packet, cmd: [u8; 2]) -> bool {
+    if frame.command_id() == RPC_ERROR {
+        return match frame.command[3..] {
+            [_, cmd0, cmd1] => [cmd0 & !COMMAND_TYPE_FLAG, cmd1] == cmd,
+            _ => false,
+        };
+    }
+    frame.command_id() == cmd
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
     #[error("unknown error")]
@@ -25,42 +80,259 @@ pub enum Error {
     #[error("packet error: {0:?}")]
     Packet(packet::Error),
 
+    #[error("serialization error: {0:?}")]
+    Serialization(ser::Error),
     #[error("deserialization error: {0:?}")]
     Deserialization(de::Error),
     #[error("command not found")]
     CommandNotFound,
+    #[error("timed out waiting for response")]
+    Timeout,
+    #[error("command failed with status {0:?}")]
+    Status(Status),
+
+    #[error("unsupported firmware version {0}")]
+    Unsupported(Version),
+    #[error("NV item {0:?} differs from the value written")]
+    Verification(NVID),
+    #[error("dump lacks NV items {0:?}")]
+    IncompleteDump(Vec<NVID>),
+    #[error("commissioning failed with status {0:?}")]
+    Commissioning(CommissioningStatus),
+    #[error("network not restored on startup: {0:?}")]
+    Startup(StartupState),
+    #[error("stored network differs from the configuration: {0:?}")]
+    Mismatch(ConfigMismatch),
+    #[error("bootloader did not acknowledge the command")]
+    Nack,
+    #[error("bootloader command failed with status {0:?}")]
+    Bootloader(sbl::SblStatus),
+    #[error("serial boot command failed with status {0:?}")]
+    SerialBoot(znp_types::command::sbl::SbStatus),
+    #[error("flash written at {0:#010x} differs from the image")]
+    FlashMismatch(u32),
+    #[error("ZDO request failed with status {0:?}")]
+    Zdp(ZdpStatus),
+}
+
+/// Fails with [`Error::Status`] unless `status` is a success.
+pub(crate) fn check(status: Status) -> Result<(), Error> {
+    match status {
+        Status::Success => Ok(()),
+        status => Err(Error::Status(status)),
+    }
 }
 
 pub trait Session {
     fn send_command(&mut self, command: &impl ser::Command) -> Result<(), Error>;
-    fn recv_frame(&mut self) -> Result<Packet, Error>;
+    /// Receives a single frame, failing with [`Error::Timeout`] if none
+    /// arrives within `timeout`.
+    fn recv_frame(&mut self, timeout: Duration) -> Result<Packet, Error>;
+    /// Handlers and channels for asynchronous callbacks.
+    fn dispatcher(&mut self) -> &mut Dispatcher;
+
+    /// Time to wait for the SRSP of a request.
+    fn timeout(&self) -> Duration;
+    fn retry_policy(&self) -> RetryPolicy;
+
+    /// Allocates the transaction sequence number of the next data request.
+    fn next_tsn(&mut self) -> u8;
+
+    /// Receives a single frame, routing it to the dispatcher.
+    fn poll(&mut self, timeout: Duration) -> Result<(), Error> {
+        let frame = self.recv_frame(timeout)?;
+        self.dispatcher().dispatch(&frame);
+        Ok(())
+    }
+
     fn request<C: ser::Command + de::Command>(&mut self, command: &C) -> Result<C::Output, Error> {
-        self.send_command(command)?;
+        let timeout = self.timeout();
+        self.request_timeout(command, timeout)
+    }
+
+    /// Sends `command` and waits up to `timeout` for its response, retrying
+    /// according to [`Session::retry_policy`].
+    fn request_timeout<C: ser::Command + de::Command>(
+        &mut self,
+        command: &C,
+        timeout: Duration,
+    ) -> Result<C::Output, Error> {
+        let policy = self.retry_policy();
+        let mut attempt = 0;
         loop {
-            let mut exec_fn = || -> Result<_, Error> {
-                let frame = self.recv_frame()?;
-                let ret = command
-                    .deserialize(frame.command)
-                    .map_err(Error::Deserialization)?;
-                Ok(ret)
-            };
-            match exec_fn() {
-                Ok(ret) => {
-                    return Ok(ret);
+            match self.request_once(command, timeout) {
+                Err(Error::Timeout) if attempt < policy.retries => {
+                    let backoff = policy.backoff(attempt);
+                    warn!(
+                        "request {:?} timed out, retrying in {:?}",
+                        C::ID.to_cmd(),
+                        backoff
+                    );
+                    std::thread::sleep(backoff);
+                    attempt += 1;
                 }
-                Err(Error::Deserialization(de::Error::CommandNotFound { .. })) => {
-                    return Err(Error::CommandNotFound);
-                }
-                _ => {}
+                ret => return ret,
+            }
+        }
+    }
+
+    /// Waits up to `timeout` for a `C` callback on `rx`, a channel from
+    /// [`Dispatcher::subscribe`], receiving frames meanwhile.
+    fn wait_for<C>(
+        &mut self,
+        rx: &mpsc::Receiver<Packet>,
+        timeout: Duration,
+    ) -> Result<C::Output, Error>
+    where
+        C: de::Command + Default,
+    {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Ok(frame) = rx.try_recv() {
+                return C::default()
+                    .deserialize(frame.command)
+                    .map_err(Error::Deserialization);
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(Error::Timeout);
+            }
+            match self.poll(remaining) {
+                Err(Error::Timeout) => {}
+                ret => ret?,
+            }
+        }
+    }
+
+    /// Sends application data and waits up to [`APS_TIMEOUT`] for its
+    /// `AF_DATA_CONFIRM`, returning the transaction sequence number used.
+    ///
+    /// The transaction sequence number of `request` is replaced by one from
+    /// [`Session::next_tsn`], and confirms of other requests are skipped.
+    /// Payloads too long for a single frame are sent in [`DataStore`]
+    /// chunks.
+    fn send_aps(&mut self, request: DataRequestExt) -> Result<u8, Error> {
+        let tsn = self.next_tsn();
+        let request = request.trans_id(tsn);
+        let confirms = self.dispatcher().subscribe(DataConfirm::ID);
+
+        check(self.request(&request)?)?;
+        if request.is_stored() {
+            let payload = request.payload();
+            for (i, chunk) in payload.chunks(DATA_STORE_MAX).enumerate() {
+                let store = DataStore::new((i * DATA_STORE_MAX) as u16, chunk.to_vec())
+                    .map_err(Error::Serialization)?;
+                check(self.request(&store)?)?;
+            }
+            let end = DataStore::new(payload.len() as u16, vec![]).map_err(Error::Serialization)?;
+            check(self.request(&end)?)?;
+        }
+
+        let deadline = Instant::now() + APS_TIMEOUT;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let confirm = self.wait_for::<DataConfirm>(&confirms, remaining)?;
+            if confirm.trans_id != tsn {
+                debug!("skipping confirm of transaction {}", confirm.trans_id);
+                continue;
+            }
+            check(confirm.status)?;
+            return Ok(tsn);
+        }
+    }
+
+    /// Sends `command` once and waits up to `timeout` for its response.
+    fn request_once<C: ser::Command + de::Command>(
+        &mut self,
+        command: &C,
+        timeout: Duration,
+    ) -> Result<C::Output, Error> {
+        self.send_command(command)?;
+        let deadline = Instant::now() + timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(Error::Timeout);
+            }
+            let frame = self.recv_frame(remaining)?;
+            if frame.command_type() == CommandType::AREQ as u8 {
+                self.dispatcher().dispatch(&frame);
+                continue;
+            }
+            // a late answer to an earlier request, e.g. one that timed out
+            if !answers(&frame, C::ID.to_cmd()) {
+                debug!("discarding unexpected frame: {:x?}", frame.command);
+                continue;
+            }
+            match command.deserialize(frame.command) {
+                Ok(ret) => return Ok(ret),
+                Err(de::Error::CommandNotFound { .. }) => return Err(Error::CommandNotFound),
+                Err(e) => debug!("discarding unexpected frame: {:?}", e),
             }
-            std::thread::sleep(Duration::from_millis(500));
         }
     }
 }
 
 pub trait ZNP: Session {
+    /// Z-Stack version from the product reported by `SYS_VERSION`, `3.30.0`
+    /// standing for any Z-Stack 3.x.0, with the reported release as build
+    /// metadata, e.g. `3.30.0+2.7.1`.
     fn version(&self) -> Version;
+    /// Firmware version reported by `SYS_VERSION` when connecting.
+    fn firmware(&self) -> VersionInfo;
 
     fn align_structs(&self) -> bool;
     fn capabilities(&self) -> enumflags2::BitFlags<Capability>;
+
+    /// Addresses and state of the device, from `UTIL_GET_DEVICE_INFO`.
+    fn device_info(&mut self) -> Result<DeviceInfo, Error> {
+        let (status, ret) = self.request(&GetDeviceInfo::default())?;
+        check(status)?;
+        Ok(ret)
+    }
+
+    /// Resets the device and waits up to [`RESET_TIMEOUT`] for it to come
+    /// back.
+    ///
+    /// After a hard reset USB devices re-enumerate, which may close the
+    /// transport.
+    fn reset(&mut self, kind: ResetType) -> Result<ResetInfo, Error> {
+        let resets = self.dispatcher().subscribe(ResetInd::ID);
+        self.send_command(&ResetReq::new(kind))?;
+        let ret = self.wait_for::<ResetInd>(&resets, RESET_TIMEOUT)?;
+        debug!("device reset: {:?}", ret);
+        Ok(ret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::answers;
+
+    use znp_types::packet::Packet;
+
+    fn frame(command: &[u8]) -> Packet {
+        let mut data = vec![0xFE];
+        data.extend(command);
+        data.push(command.iter().fold(0, |acc, e| acc ^ e));
+        Packet::from_reader(data.as_slice()).unwrap()
+    }
+
+    #[test]
+    fn rpc_error_echoes_request() {
+        // SYS_PING SRSP
+        assert!(answers(
+            &frame(&[0x02, 0x61, 0x01, 0x59, 0x06]),
+            [0x01, 0x01]
+        ));
+        // RPC_Error for SYS_PING, then for SYS_OSAL_NV_READ
+        let ping_error = frame(&[0x03, 0x60, 0x00, 0x02, 0x21, 0x01]);
+        assert!(answers(&ping_error, [0x01, 0x01]));
+        assert!(!answers(&ping_error, [0x01, 0x08]));
+        assert!(!answers(
+            &frame(&[0x03, 0x60, 0x00, 0x02, 0x21, 0x08]),
+            [0x01, 0x01]
+        ));
+        assert!(!answers(&frame(&[0x01, 0x60, 0x00, 0x02]), [0x01, 0x01]));
+    }
 }
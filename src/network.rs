@@ -0,0 +1,383 @@
+use crate::backup::optional;
+use crate::{check, Error, NVRam};
+
+use znp_types::command::app_cnf::{
+    BdbCommissioningNotification, BdbSetChannel, BdbSetTcRequireKeyExchange, BdbStartCommissioning,
+    CommissioningMode, CommissioningStatus, SetAllowRejoinTcPolicy,
+};
+use znp_types::command::sys::ResetType;
+use znp_types::command::zdo::{DeviceState, StartupFromApp, StartupState, StateChangeInd};
+use znp_types::command::Command;
+use znp_types::nv::{
+    Item, LogicalType, StartupOption, APS_USE_EXT_PANID, CHANLIST, CONFIGURED_MARKER,
+    EXTENDED_PAN_ID, HAS_CONFIGURED_ZSTACK1, HAS_CONFIGURED_ZSTACK3, LOGICAL_TYPE, NIB,
+    NWK_ACTIVE_KEY_INFO, PANID, PRECFGKEY, PRECFGKEYS_ENABLE, STARTUP_OPTION, ZDO_DIRECT_CB,
+};
+
+use std::time::{Duration, Instant};
+
+use log::debug;
+use semver::Version;
+
+/// Time allowed for forming or joining a network.
+pub const FORMATION_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Delay passed to `ZDO_STARTUP_FROM_APP`, in ms.
+const START_DELAY: u16 = 100;
+
+/// Difference between the network stored on the device and the desired
+/// configuration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigMismatch {
+    LogicalType {
+        stored: u8,
+        desired: LogicalType,
+    },
+    PanId {
+        stored: u16,
+        desired: u16,
+    },
+    ExtendedPanId {
+        stored: [u8; 8],
+        desired: [u8; 8],
+    },
+    /// the stored channel is not in the desired channel mask
+    Channel {
+        stored: u8,
+        desired_mask: u32,
+    },
+    NetworkKey,
+}
+
+/// Bit of `channel` in a channel mask, none for channels out of range.
+fn channel_bit(channel: u8) -> u32 {
+    match channel {
+        11..=26 => 1 << channel,
+        _ => 0,
+    }
+}
+
+/// Has configured marker of the firmware.
+fn configured_marker(version: &Version) -> Item<u8> {
+    match *version >= Version::new(3, 0, 0) {
+        true => HAS_CONFIGURED_ZSTACK3,
+        false => HAS_CONFIGURED_ZSTACK1,
+    }
+}
+
+/// Parameters of the network to form.
+#[derive(Debug, Clone)]
+pub struct NetworkConfig {
+    pub logical_type: LogicalType,
+    pub pan_id: u16,
+    /// little-endian, as stored in NV
+    pub extended_pan_id: [u8; 8],
+    /// channel bit mask, bit `n` for channel `n`
+    pub channel_mask: u32,
+    pub network_key: [u8; 16],
+    /// whether joining devices must replace the default trust center link key
+    pub tc_require_key_exchange: bool,
+    /// whether devices may rejoin using their trust center link key
+    pub allow_rejoin: bool,
+}
+
+impl NetworkConfig {
+    /// Coordinator of a network on the given channels.
+    pub fn new(
+        pan_id: u16,
+        extended_pan_id: [u8; 8],
+        channel_mask: u32,
+        network_key: [u8; 16],
+    ) -> Self {
+        Self {
+            logical_type: LogicalType::Coordinator,
+            pan_id,
+            extended_pan_id,
+            channel_mask,
+            network_key,
+            tc_require_key_exchange: false,
+            allow_rejoin: true,
+        }
+    }
+
+    /// State the device reports once on the network.
+    pub(crate) fn started_state(&self) -> DeviceState {
+        match self.logical_type {
+            LogicalType::Coordinator => DeviceState::ZbCoord,
+            LogicalType::Router => DeviceState::Router,
+            LogicalType::EndDevice => DeviceState::EndDevice,
+        }
+    }
+}
+
+/// Network management on top of [`NVRam`].
+pub trait Network: NVRam {
+    /// Writes `config` to NV and starts the stack, forming the network or,
+    /// for routers and end devices, joining one. Any network the device was
+    /// on before is left.
+    ///
+    /// Z-Stack 3 commissions through base device behavior, older firmware
+    /// forms the network on `ZDO_STARTUP_FROM_APP` alone. Returns once the
+    /// device reports the started state of its logical type, after marking
+    /// the network as configured.
+    fn form_network(&mut self, config: &NetworkConfig) -> Result<(), Error> {
+        // the stack only forms a network if it is on none, the startup
+        // option makes it forget the previous one on reset
+        let clear = StartupOption::ClearState | StartupOption::ClearConfig;
+        self.nv_set(STARTUP_OPTION, &clear.bits())?;
+        self.reset(ResetType::Soft)?;
+
+        self.nv_set(LOGICAL_TYPE, &(config.logical_type as u8))?;
+        self.nv_set(PANID, &config.pan_id)?;
+        self.nv_set(EXTENDED_PAN_ID, &config.extended_pan_id)?;
+        self.nv_set(APS_USE_EXT_PANID, &config.extended_pan_id)?;
+        self.nv_set(CHANLIST, &config.channel_mask)?;
+        self.nv_set(PRECFGKEY, &config.network_key)?;
+        self.nv_set(PRECFGKEYS_ENABLE, &true)?;
+        self.nv_set(ZDO_DIRECT_CB, &true)?;
+
+        let bdb = self.version() >= Version::new(3, 0, 0);
+        if bdb {
+            check(self.request(&BdbSetChannel::new(true, config.channel_mask))?)?;
+            check(self.request(&BdbSetChannel::new(false, 0))?)?;
+            check(self.request(&SetAllowRejoinTcPolicy::new(config.allow_rejoin))?)?;
+            let require_key_exchange = config.tc_require_key_exchange;
+            check(self.request(&BdbSetTcRequireKeyExchange::new(require_key_exchange))?)?;
+        }
+
+        // subscribe first, the callbacks may arrive with the responses
+        let states = self.dispatcher().subscribe(StateChangeInd::ID);
+        let notifications = self
+            .dispatcher()
+            .subscribe(BdbCommissioningNotification::ID);
+        let deadline = Instant::now() + FORMATION_TIMEOUT;
+        let remaining = || deadline.saturating_duration_since(Instant::now());
+
+        let startup = self.request(&StartupFromApp::new(START_DELAY))?;
+        debug!("startup state: {:?}", startup);
+
+        if bdb {
+            let mode = match config.logical_type {
+                LogicalType::Coordinator => CommissioningMode::NwkFormation,
+                _ => CommissioningMode::NwkSteering,
+            };
+            check(self.request(&BdbStartCommissioning::new(mode.into()))?)?;
+            loop {
+                let notification =
+                    self.wait_for::<BdbCommissioningNotification>(&notifications, remaining())?;
+                debug!("commissioning notification: {:?}", notification);
+                match notification.status {
+                    CommissioningStatus::InProgress => {}
+                    CommissioningStatus::Success => break,
+                    status => return Err(Error::Commissioning(status)),
+                }
+            }
+        }
+
+        let started = config.started_state();
+        loop {
+            let state = self.wait_for::<StateChangeInd>(&states, remaining())?;
+            debug!("device state: {:?}", state);
+            if state == started {
+                break;
+            }
+        }
+
+        let marker = configured_marker(&self.version());
+        self.nv_set(marker, &CONFIGURED_MARKER)
+    }
+
+    /// Whether a network was set up, by [`Network::form_network`] or a
+    /// restore.
+    fn is_configured(&mut self) -> Result<bool, Error> {
+        let marker = configured_marker(&self.version());
+        Ok(optional(self.nv_get(marker))? == Some(CONFIGURED_MARKER))
+    }
+
+    /// Resumes the network stored on the device, or forms one if there is
+    /// none.
+    ///
+    /// Fails with [`Error::Mismatch`] rather than reforming if the stored
+    /// network differs from `config`.
+    fn start(&mut self, config: &NetworkConfig) -> Result<(), Error> {
+        if !self.is_configured()? {
+            debug!("no network configured, forming one");
+            return self.form_network(config);
+        }
+
+        let logical_type = self.nv_get(LOGICAL_TYPE)?;
+        let nib = self.nv_get(NIB)?;
+        let key = self.nv_get(NWK_ACTIVE_KEY_INFO)?;
+        let mismatch = if logical_type != config.logical_type as u8 {
+            Some(ConfigMismatch::LogicalType {
+                stored: logical_type,
+                desired: config.logical_type,
+            })
+        } else if nib.nwk_pan_id != config.pan_id {
+            Some(ConfigMismatch::PanId {
+                stored: nib.nwk_pan_id,
+                desired: config.pan_id,
+            })
+        } else if nib.extended_pan_id != config.extended_pan_id {
+            Some(ConfigMismatch::ExtendedPanId {
+                stored: nib.extended_pan_id,
+                desired: config.extended_pan_id,
+            })
+        } else if config.channel_mask & channel_bit(nib.nwk_logical_channel) == 0 {
+            Some(ConfigMismatch::Channel {
+                stored: nib.nwk_logical_channel,
+                desired_mask: config.channel_mask,
+            })
+        } else if key.active.key != config.network_key {
+            Some(ConfigMismatch::NetworkKey)
+        } else {
+            None
+        };
+        if let Some(mismatch) = mismatch {
+            return Err(Error::Mismatch(mismatch));
+        }
+
+        // start from a clean stack, a previous session may have started it
+        self.reset(ResetType::Soft)?;
+
+        let states = self.dispatcher().subscribe(StateChangeInd::ID);
+        let startup = self.request(&StartupFromApp::new(START_DELAY))?;
+        if startup != StartupState::RestoredNetworkState {
+            return Err(Error::Startup(startup));
+        }
+        let deadline = Instant::now() + FORMATION_TIMEOUT;
+        let started = config.started_state();
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let state = self.wait_for::<StateChangeInd>(&states, remaining)?;
+            debug!("device state: {:?}", state);
+            if state == started {
+                return Ok(());
+            }
+        }
+    }
+}
+
+impl<T: NVRam> Network for T {}
+
+#[cfg(test)]
+mod tests {
+    use super::{ConfigMismatch, Network, NetworkConfig};
+    use crate::sim::{Profile, Simulator};
+    use crate::{Builder, Error, NVRam, ZNP};
+
+    use znp_types::command::zdo::DeviceState;
+    use znp_types::nv::{
+        CONFIGURED_MARKER, HAS_CONFIGURED_ZSTACK1, HAS_CONFIGURED_ZSTACK3, NIB, STARTUP_OPTION,
+    };
+
+    use std::time::Duration;
+
+    #[test]
+    fn form_network() {
+        let config = NetworkConfig::new(0x1A62, [0xDD; 8], 1 << 15 | 1 << 20, [0x01; 16]);
+        for profile in [Profile::zstack_3_0(), Profile::zstack_3_30()] {
+            let (_, pipe) = Simulator::spawn(profile);
+            let mut znp = Builder::from_transport(pipe)
+                .timeout(Duration::from_millis(200))
+                .connect()
+                .unwrap();
+            znp.form_network(&config).unwrap();
+
+            let nib = znp.nv_get(NIB).unwrap();
+            assert_eq!(nib.nwk_pan_id, 0x1A62);
+            assert_eq!(nib.nwk_logical_channel, 15);
+            let marker = znp.nv_get(HAS_CONFIGURED_ZSTACK3).unwrap();
+            assert_eq!(marker, CONFIGURED_MARKER);
+            assert_eq!(
+                znp.device_info().unwrap().device_state,
+                DeviceState::ZbCoord
+            );
+        }
+    }
+
+    #[test]
+    fn form_network_zstack_1_2() {
+        let config = NetworkConfig::new(0x1A62, [0xDD; 8], 1 << 15, [0x01; 16]);
+        let (_, pipe) = Simulator::spawn(Profile::zstack_1_2());
+        let mut znp = Builder::from_transport(pipe)
+            .timeout(Duration::from_millis(200))
+            .connect()
+            .unwrap();
+        znp.form_network(&config).unwrap();
+        znp.form_network(&config).unwrap();
+
+        let nib = znp.nv_get(NIB).unwrap();
+        assert_eq!(nib.nwk_pan_id, 0x1A62);
+        assert_eq!(nib.nwk_logical_channel, 15);
+        let marker = znp.nv_get(HAS_CONFIGURED_ZSTACK1).unwrap();
+        assert_eq!(marker, CONFIGURED_MARKER);
+    }
+
+    #[test]
+    fn form_network_again() {
+        let config = NetworkConfig::new(0x1A62, [0xDD; 8], 1 << 15, [0x01; 16]);
+        let other = NetworkConfig::new(0x2B73, [0xEE; 8], 1 << 25, [0x02; 16]);
+        for profile in [Profile::zstack_3_0(), Profile::zstack_3_30()] {
+            let (_, pipe) = Simulator::spawn(profile);
+            let mut znp = Builder::from_transport(pipe)
+                .timeout(Duration::from_millis(200))
+                .connect()
+                .unwrap();
+            znp.form_network(&config).unwrap();
+            znp.form_network(&other).unwrap();
+
+            let nib = znp.nv_get(NIB).unwrap();
+            assert_eq!(nib.nwk_pan_id, 0x2B73);
+            assert_eq!(nib.extended_pan_id, [0xEE; 8]);
+            assert_eq!(nib.nwk_logical_channel, 25);
+            assert_eq!(znp.nv_get(STARTUP_OPTION).unwrap(), 0);
+        }
+    }
+
+    #[test]
+    fn start_resumes() {
+        let config = NetworkConfig::new(0x1A62, [0xDD; 8], 1 << 15, [0x01; 16]);
+        let (sim, pipe) = Simulator::spawn(Profile::zstack_3_30());
+        let connect = |pipe| {
+            Builder::from_transport(pipe)
+                .timeout(Duration::from_millis(200))
+                .connect()
+                .unwrap()
+        };
+        let mut znp = connect(pipe);
+        assert!(!znp.is_configured().unwrap());
+        znp.start(&config).unwrap();
+        assert!(znp.is_configured().unwrap());
+        drop(znp);
+
+        // restart on the same device
+        let mut znp = connect(sim.reconnect());
+        znp.start(&config).unwrap();
+
+        let other = NetworkConfig {
+            pan_id: 0x0001,
+            ..config
+        };
+        let ret = znp.start(&other);
+        let expected = ConfigMismatch::PanId {
+            stored: 0x1A62,
+            desired: 0x0001,
+        };
+        assert!(matches!(ret, Err(Error::Mismatch(e)) if e == expected));
+
+        // garbage in NV is a mismatch, not a panic
+        let mut nib = znp.nv_get(NIB).unwrap();
+        nib.nwk_logical_channel = 40;
+        znp.nv_set(NIB, &nib).unwrap();
+        let ret = znp.start(&NetworkConfig {
+            channel_mask: u32::MAX,
+            ..config
+        });
+        let expected = ConfigMismatch::Channel {
+            stored: 40,
+            desired_mask: u32::MAX,
+        };
+        assert!(matches!(ret, Err(Error::Mismatch(e)) if e == expected));
+    }
+}
@@ -1,7 +1,184 @@
-use crate::{Error, ZNP};
+use crate::{check, Error, ZNP};
 
+use znp_types::command::sys::{
+    NVCreate, NVDelete, NVLength, NVRead, NVWrite, OSALNVDelete, OSALNVItemInit, OSALNVLength,
+    OSALNVRead, OSALNVReadExt, OSALNVWrite, OSALNVWriteExt, NVID,
+};
+use znp_types::command::Status;
+use znp_types::nv::{Item, NvStruct, Table};
+
+use semver::Version;
+
+/// Largest value carried by a single NV read or write frame.
+pub const NV_CHUNK_LEN: usize = 240;
+
+/// OSAL id for `id` on firmware without the extended commands.
+fn osal_id<Z: ZNP + ?Sized>(znp: &Z, id: NVID) -> Result<Option<u16>, Error> {
+    if znp.version() >= Version::new(3, 30, 0) {
+        return Ok(None);
+    }
+    id.legacy_id().map(Some).ok_or(Error::CommandNotFound)
+}
+
+/// NV memory access, split into frame-sized chunks.
+///
+/// Z-Stack 3.x.0 is accessed with the extended `SYS_NV_*` commands, older
+/// firmware with the `SYS_OSAL_NV_*` commands, which only reach legacy
+/// items, see [`NVID::legacy`].
 pub trait NVRam: ZNP {
-    fn nv_exists(&mut self) -> bool;
-    fn nv_len(&mut self) -> u8;
-    fn nv_read(&mut self) -> Result<Vec<u8>, Error>;
+    fn nv_exists(&mut self, id: NVID) -> Result<bool, Error> { Ok(self.nv_len(id)? > 0) }
+
+    /// Length of the item, 0 if it does not exist.
+    fn nv_len(&mut self, id: NVID) -> Result<usize, Error> {
+        let len = match osal_id(self, id)? {
+            Some(id) => self.request(&OSALNVLength::new(id))? as usize,
+            None => self.request(&NVLength::new(id))? as usize,
+        };
+        Ok(len)
+    }
+
+    fn nv_read(&mut self, id: NVID) -> Result<Vec<u8>, Error> {
+        let len = self.nv_len(id)?;
+        if len == 0 {
+            return Err(Error::Status(Status::NvItemUninit));
+        }
+
+        let osal = osal_id(self, id)?;
+        let mut ret = Vec::with_capacity(len);
+        while ret.len() < len {
+            let offset = ret.len();
+            let (status, chunk) = match osal {
+                None => {
+                    let chunk_len = (len - offset).min(NV_CHUNK_LEN) as u8;
+                    self.request(&NVRead::new(id, offset as u16, chunk_len))?
+                }
+                Some(id) if self.version() >= Version::new(3, 0, 0) => {
+                    self.request(&OSALNVReadExt::new(id, offset as u16))?
+                }
+                Some(id) => {
+                    let offset =
+                        u8::try_from(offset).map_err(|_| Error::Unsupported(self.version()))?;
+                    self.request(&OSALNVRead::new(id, offset))?
+                }
+            };
+            check(status)?;
+            if chunk.is_empty() {
+                return Err(Error::Status(Status::NvBadItemLen));
+            }
+            ret.extend(chunk);
+        }
+        ret.truncate(len);
+        Ok(ret)
+    }
+
+    /// Overwrites an existing item of the same length.
+    fn nv_write(&mut self, id: NVID, value: &[u8]) -> Result<(), Error> {
+        let len = self.nv_len(id)?;
+        if len == 0 {
+            return Err(Error::Status(Status::NvItemUninit));
+        }
+        if len != value.len() {
+            return Err(Error::Status(Status::NvBadItemLen));
+        }
+
+        let osal = osal_id(self, id)?;
+        for (i, chunk) in value.chunks(NV_CHUNK_LEN).enumerate() {
+            let offset = i * NV_CHUNK_LEN;
+            let chunk = chunk.to_vec();
+            let status = match osal {
+                None => self.request(
+                    &NVWrite::new(id, offset as u16, chunk).map_err(Error::Serialization)?,
+                )?,
+                Some(id) if self.version() >= Version::new(3, 0, 0) => self.request(
+                    &OSALNVWriteExt::new(id, offset as u16, chunk).map_err(Error::Serialization)?,
+                )?,
+                Some(id) => {
+                    let offset =
+                        u8::try_from(offset).map_err(|_| Error::Unsupported(self.version()))?;
+                    self.request(
+                        &OSALNVWrite::new(id, offset, chunk).map_err(Error::Serialization)?,
+                    )?
+                }
+            };
+            check(status)?;
+        }
+        Ok(())
+    }
+
+    /// Creates an item of `len` bytes. Returns whether it was newly created.
+    fn nv_create(&mut self, id: NVID, len: usize) -> Result<bool, Error> {
+        let status = match osal_id(self, id)? {
+            Some(id) => self.request(
+                &OSALNVItemInit::new(id, len as u16, vec![]).map_err(Error::Serialization)?,
+            )?,
+            None => self.request(&NVCreate::new(id, len as u32))?,
+        };
+        match status {
+            Status::NvItemUninit => Ok(true),
+            Status::Success => Ok(false),
+            status => Err(Error::Status(status)),
+        }
+    }
+
+    /// Deletes an item. Returns whether it existed.
+    fn nv_delete(&mut self, id: NVID) -> Result<bool, Error> {
+        let status = match osal_id(self, id)? {
+            Some(osal) => {
+                let len = self.nv_len(id)?;
+                if len == 0 {
+                    return Ok(false);
+                }
+                self.request(&OSALNVDelete::new(osal, len as u16))?
+            }
+            None => self.request(&NVDelete::new(id))?,
+        };
+        match status {
+            Status::Success => Ok(true),
+            Status::NvItemUninit => Ok(false),
+            status => Err(Error::Status(status)),
+        }
+    }
+
+    /// Writes `value`, creating the item or recreating it if its length
+    /// differs.
+    fn nv_store(&mut self, id: NVID, value: &[u8]) -> Result<(), Error> {
+        let len = self.nv_len(id)?;
+        if len != value.len() {
+            if len > 0 {
+                self.nv_delete(id)?;
+            }
+            self.nv_create(id, value.len())?;
+        }
+        self.nv_write(id, value)
+    }
+
+    /// Reads a typed item, laid out according to [`ZNP::align_structs`].
+    fn nv_get<T: NvStruct>(&mut self, item: Item<T>) -> Result<T, Error> {
+        let value = self.nv_read(item.id())?;
+        T::decode(&value, self.align_structs()).map_err(Error::Deserialization)
+    }
+
+    /// Stores a typed item, see [`NVRam::nv_store`].
+    fn nv_set<T: NvStruct>(&mut self, item: Item<T>, value: &T) -> Result<(), Error> {
+        let value = value.encode(self.align_structs());
+        self.nv_store(item.id(), &value)
+    }
+
+    /// Entries of `table` up to the first missing one.
+    fn nv_table<T: NvStruct>(&mut self, table: &Table<T>) -> Result<Vec<T>, Error> {
+        let extended = self.version() >= Version::new(3, 30, 0);
+        let mut ret = vec![];
+        for index in 0..=u16::MAX {
+            let Some(item) = table.entry(index, extended) else {
+                break;
+            };
+            if !self.nv_exists(item.id())? {
+                break;
+            }
+            ret.push(self.nv_get(item)?);
+        }
+        Ok(ret)
+    }
 }
+
+impl<T: ZNP> NVRam for T {}
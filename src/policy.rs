@@ -0,0 +1,79 @@
+use std::time::Duration;
+
+/// Time to wait for the SRSP of a request before giving up.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(6);
+
+/// Time to wait for `SYS_RESET_IND` after a reset.
+pub const RESET_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Time to wait for `AF_DATA_CONFIRM`, which may follow an APS
+/// acknowledgement and route discovery.
+pub const APS_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Retry policy for requests whose SRSP timed out.
+///
+/// Requests are resent as-is, so only enable retries if repeating a request
+/// is harmless for every command sent through the session.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// retries after the first attempt
+    pub retries: u32,
+    /// delay before the first retry
+    pub backoff: Duration,
+    /// upper bound of the delay, which doubles after every retry
+    pub max_backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// Never retry, the default.
+    pub fn none() -> Self { Self::new(0, Duration::ZERO) }
+
+    pub fn new(retries: u32, backoff: Duration) -> Self {
+        Self {
+            retries,
+            backoff,
+            max_backoff: backoff.saturating_mul(8),
+        }
+    }
+
+    pub fn with_max_backoff(mut self, max_backoff: Duration) -> Self {
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    /// Delay before the `attempt`-th retry, starting at 0.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 1u32.checked_shl(attempt).unwrap_or(u32::MAX);
+        self.backoff
+            .checked_mul(factor)
+            .unwrap_or(self.max_backoff)
+            .min(self.max_backoff)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self { Self::none() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RetryPolicy;
+
+    use std::time::Duration;
+
+    #[test]
+    fn backoff() {
+        let policy = RetryPolicy::new(5, Duration::from_millis(100));
+        let ret = (0..5).map(|e| policy.backoff(e)).collect::<Vec<_>>();
+        let expected = [100, 200, 400, 800, 800].map(Duration::from_millis);
+        assert_eq!(ret, expected);
+
+        let policy = policy.with_max_backoff(Duration::from_secs(1));
+        assert_eq!(policy.backoff(4), Duration::from_secs(1));
+        assert_eq!(policy.backoff(40), Duration::from_secs(1));
+
+        let policy = RetryPolicy::new(1, Duration::MAX);
+        assert_eq!(policy.max_backoff, Duration::MAX);
+        assert_eq!(policy.backoff(3), Duration::MAX);
+    }
+}
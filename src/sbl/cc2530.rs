@@ -0,0 +1,182 @@
+//! Client of the serial boot loader of CC2530 and CC2531 chips running
+//! Z-Stack Home 1.2.
+
+use super::SBL_TIMEOUT;
+use crate::{Error, Transport};
+
+use znp_types::command::sbl::{
+    SbEnable, SbEnableRsp, SbHandshake, SbHandshakeRsp, SbRead, SbReadRsp, SbStatus, SbWrite,
+    SbWriteRsp, SB_BLOCK_LEN,
+};
+use znp_types::command::{de, ser};
+use znp_types::packet::{self, Decoder, Packet};
+
+use std::io::ErrorKind;
+
+use log::debug;
+use serialport::{DataBits, SerialPort, StopBits};
+
+/// Sent instead of a frame, makes the bootloader start the firmware right
+/// away.
+pub const SKIP_BOOTLOADER: u8 = 0xEF;
+
+/// First address of the firmware, after the 8 KiB bootloader.
+pub const CC2530_IMAGE_START: u32 = 0x2000;
+
+/// Connection to the serial boot loader of a CC2530 or CC2531 chip.
+pub struct Cc2530Bootloader<T: Transport> {
+    transport: T,
+    decoder: Decoder,
+}
+
+impl Cc2530Bootloader<Box<dyn SerialPort>> {
+    /// Opens a tty with the bootloader waiting, as after plugging in a
+    /// CC2531 stick.
+    pub fn open(port: &str) -> Result<Self, Error> {
+        let tty = serialport::new(port, 115200)
+            .data_bits(DataBits::Eight)
+            .stop_bits(StopBits::One)
+            .open()
+            .map_err(Error::TTY)?;
+        let mut ret = Self::new(tty)?;
+        ret.handshake()?;
+        Ok(ret)
+    }
+}
+
+impl<T: Transport> Cc2530Bootloader<T> {
+    pub fn new(mut transport: T) -> Result<Self, Error> {
+        transport.set_timeout(SBL_TIMEOUT).map_err(Error::IO)?;
+        let ret = Self {
+            transport,
+            decoder: Decoder::new(),
+        };
+        Ok(ret)
+    }
+
+    pub fn handshake(&mut self) -> Result<(), Error> {
+        check(self.request::<SbHandshakeRsp>(&SbHandshake::default())?)
+    }
+
+    /// Writes a block at `address`, a multiple of 4 bytes.
+    pub fn write(&mut self, address: u32, block: [u8; SB_BLOCK_LEN]) -> Result<(), Error> {
+        check(self.request::<SbWriteRsp>(&SbWrite::new(address, block))?)
+    }
+
+    /// Reads the block at `address`, a multiple of 4 bytes.
+    pub fn read(&mut self, address: u32) -> Result<Vec<u8>, Error> {
+        let (status, data) = self.request::<SbReadRsp>(&SbRead::new(address))?;
+        check(status)?;
+        Ok(data)
+    }
+
+    /// Validates the written firmware and lets the bootloader start it.
+    pub fn enable(&mut self) -> Result<(), Error> {
+        check(self.request::<SbEnableRsp>(&SbEnable::default())?)
+    }
+
+    /// Starts the firmware, see [`SKIP_BOOTLOADER`].
+    pub fn skip(&mut self) -> Result<(), Error> {
+        self.transport
+            .write_all(&[SKIP_BOOTLOADER])
+            .map_err(Error::IO)
+    }
+
+    /// Writes `image` from `address` and reads it back, the bootloader
+    /// erasing pages as their first block is written.
+    ///
+    /// The image is padded with `0xFF` to whole blocks.
+    pub fn flash(&mut self, address: u32, image: &[u8]) -> Result<(), Error> {
+        let mut data = image.to_vec();
+        data.resize(data.len().next_multiple_of(SB_BLOCK_LEN), 0xFF);
+        for (i, block) in data.chunks(SB_BLOCK_LEN).enumerate() {
+            let block_address = address + (i * SB_BLOCK_LEN) as u32;
+            self.write(block_address, block.try_into().unwrap())?;
+        }
+        for (i, block) in data.chunks(SB_BLOCK_LEN).enumerate() {
+            let block_address = address + (i * SB_BLOCK_LEN) as u32;
+            if self.read(block_address)? != block {
+                return Err(Error::FlashMismatch(block_address));
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads `len` bytes from `address`, for backing up the firmware.
+    pub fn read_image(&mut self, address: u32, len: usize) -> Result<Vec<u8>, Error> {
+        let mut ret = Vec::with_capacity(len.next_multiple_of(SB_BLOCK_LEN));
+        while ret.len() < len {
+            ret.extend(self.read(address + ret.len() as u32)?);
+        }
+        ret.truncate(len);
+        Ok(ret)
+    }
+
+    /// Sends `command` and waits for the `R` response, skipping other
+    /// frames.
+    fn request<R>(&mut self, command: &impl ser::Command) -> Result<R::Output, Error>
+    where
+        R: de::Command + Default,
+    {
+        let packet = Packet::from_command(command).serialize();
+        self.transport.write_all(&packet).map_err(Error::IO)?;
+        loop {
+            let frame = match self.decoder.read_from(&mut self.transport) {
+                Ok(frame) => frame,
+                Err(packet::Error::IO(e)) if e.kind() == ErrorKind::TimedOut => {
+                    return Err(Error::Timeout)
+                }
+                Err(packet::Error::IO(e)) if e.kind() == ErrorKind::WouldBlock => {
+                    return Err(Error::Timeout)
+                }
+                Err(e) => return Err(Error::Packet(e)),
+            };
+            match R::default().deserialize(frame.command) {
+                Ok(ret) => return Ok(ret),
+                Err(e) => debug!("discarding unexpected frame: {:?}", e),
+            }
+        }
+    }
+}
+
+fn check(status: SbStatus) -> Result<(), Error> {
+    match status {
+        SbStatus::Success => Ok(()),
+        status => Err(Error::SerialBoot(status)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Cc2530Bootloader, CC2530_IMAGE_START};
+    use crate::sbl::sim::Cc2530Simulator;
+    use crate::Error;
+
+    use znp_types::command::sbl::{SbStatus, SB_BLOCK_LEN};
+
+    #[test]
+    fn flash() {
+        let (sim, pipe) = Cc2530Simulator::spawn(0x40000);
+        let mut sbl = Cc2530Bootloader::new(pipe).unwrap();
+        sbl.handshake().unwrap();
+
+        let image = (0..3000).map(|e| (e * 3) as u8).collect::<Vec<_>>();
+        sbl.flash(CC2530_IMAGE_START, &image).unwrap();
+        let ret = sbl.read_image(CC2530_IMAGE_START, image.len()).unwrap();
+        assert_eq!(ret, image);
+        // the page is erased again before rewriting it
+        sbl.flash(CC2530_IMAGE_START, &[0x00; 4]).unwrap();
+        let ret = sbl.read_image(CC2530_IMAGE_START + 64, 4).unwrap();
+        assert_eq!(ret, [0xFF; 4]);
+
+        // the bootloader itself is protected
+        let ret = sbl.write(0x0000, [0x00; SB_BLOCK_LEN]);
+        assert!(matches!(ret, Err(Error::SerialBoot(SbStatus::Failure))));
+
+        sbl.enable().unwrap();
+        assert!(sim.is_enabled());
+        sbl.skip().unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        assert!(sim.is_skipped());
+    }
+}
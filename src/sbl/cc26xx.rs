@@ -0,0 +1,314 @@
+//! Client of the CC13xx/CC26xx ROM serial bootloader.
+//! See CC13x2, CC26x2 SimpleLink Wireless MCU Technical Reference Manual,
+//! 10.2.
+
+use super::crc32;
+use crate::transport::reset_tty;
+use crate::{Error, Transport};
+
+use znp_types::packet;
+
+use std::io::ErrorKind;
+use std::time::Duration;
+
+use log::debug;
+use serialport::{DataBits, SerialPort, StopBits};
+
+/// Time to wait for an acknowledgement or response, sector erases taking
+/// the longest.
+pub const SBL_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Largest payload of `COMMAND_SEND_DATA`.
+pub const SEND_DATA_MAX: usize = 252;
+
+/// Flash sector size of CC13x2 and CC26x2 chips.
+pub const CC26X2_SECTOR_SIZE: u32 = 0x2000;
+
+const ACK: u8 = 0xCC;
+const NACK: u8 = 0x33;
+/// Sent twice for the bootloader to detect the baud rate.
+const SYNC: u8 = 0x55;
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy)]
+enum SblCommand {
+    Ping = 0x20,
+    Download = 0x21,
+    GetStatus = 0x23,
+    SendData = 0x24,
+    Reset = 0x25,
+    SectorErase = 0x26,
+    Crc32 = 0x27,
+    GetChipId = 0x28,
+    BankErase = 0x2C,
+}
+
+/// Result of the last command, from `COMMAND_GET_STATUS`.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SblStatus {
+    Success = 0x40,
+    UnknownCmd = 0x41,
+    InvalidCmd = 0x42,
+    InvalidAddr = 0x43,
+    FlashFail = 0x44,
+}
+
+impl SblStatus {
+    fn from_byte(status: u8) -> Option<Self> {
+        let ret = match status {
+            0x40 => Self::Success,
+            0x41 => Self::UnknownCmd,
+            0x42 => Self::InvalidCmd,
+            0x43 => Self::InvalidAddr,
+            0x44 => Self::FlashFail,
+            _ => return None,
+        };
+        Some(ret)
+    }
+}
+
+/// Connection to the ROM bootloader of a CC13xx or CC26xx chip.
+pub struct Cc26xxBootloader<T: Transport> {
+    transport: T,
+    sector_size: u32,
+}
+
+impl Cc26xxBootloader<Box<dyn SerialPort>> {
+    /// Opens a tty and enters the bootloader through the DTR and RTS lines,
+    /// as wired on launchpads and most coordinator sticks.
+    pub fn open(port: &str) -> Result<Self, Error> {
+        let mut tty = serialport::new(port, 115200)
+            .data_bits(DataBits::Eight)
+            .stop_bits(StopBits::One)
+            .open()
+            .map_err(Error::TTY)?;
+        reset_tty(tty.as_mut(), true).map_err(Error::TTY)?;
+        std::thread::sleep(Duration::from_millis(100));
+
+        let mut ret = Self::new(tty)?;
+        ret.sync()?;
+        Ok(ret)
+    }
+}
+
+impl<T: Transport> Cc26xxBootloader<T> {
+    /// Talks to a bootloader already running on `transport`, see
+    /// [`Cc26xxBootloader::sync`].
+    pub fn new(mut transport: T) -> Result<Self, Error> {
+        transport.set_timeout(SBL_TIMEOUT).map_err(Error::IO)?;
+        let ret = Self {
+            transport,
+            sector_size: CC26X2_SECTOR_SIZE,
+        };
+        Ok(ret)
+    }
+
+    /// Flash sector size, 4 KiB on CC13x0 and CC26x0 chips.
+    pub fn sector_size(mut self, sector_size: u32) -> Self {
+        self.sector_size = sector_size;
+        self
+    }
+
+    /// Lets the bootloader detect the baud rate, required once before any
+    /// command.
+    pub fn sync(&mut self) -> Result<(), Error> {
+        self.write(&[SYNC, SYNC])?;
+        self.wait_ack()
+    }
+
+    pub fn ping(&mut self) -> Result<(), Error> { self.command(SblCommand::Ping, &[]) }
+
+    /// Chip identification, the user id register of the chip.
+    pub fn chip_id(&mut self) -> Result<u32, Error> {
+        self.command(SblCommand::GetChipId, &[])?;
+        let rsp = self.recv_packet()?;
+        let rsp = rsp
+            .try_into()
+            .map_err(|_| Error::Packet(packet::Error::FrameCorrupted))?;
+        Ok(u32::from_be_bytes(rsp))
+    }
+
+    /// Result of the last command.
+    pub fn status(&mut self) -> Result<SblStatus, Error> {
+        self.command(SblCommand::GetStatus, &[])?;
+        match *self.recv_packet()? {
+            [status] => SblStatus::from_byte(status).ok_or(Error::Unknown),
+            _ => Err(Error::Packet(packet::Error::FrameCorrupted)),
+        }
+    }
+
+    /// Erases the sector holding `address`.
+    pub fn sector_erase(&mut self, address: u32) -> Result<(), Error> {
+        self.checked_command(SblCommand::SectorErase, &address.to_be_bytes())
+    }
+
+    /// Erases the whole flash, including the CCFG area.
+    pub fn bank_erase(&mut self) -> Result<(), Error> {
+        self.checked_command(SblCommand::BankErase, &[])
+    }
+
+    /// Prepares writing `size` bytes from `address`, both multiples of 4,
+    /// through [`Cc26xxBootloader::send_data`].
+    pub fn download(&mut self, address: u32, size: u32) -> Result<(), Error> {
+        let mut args = address.to_be_bytes().to_vec();
+        args.extend(size.to_be_bytes());
+        self.checked_command(SblCommand::Download, &args)
+    }
+
+    /// Writes up to [`SEND_DATA_MAX`] bytes after the previous ones.
+    pub fn send_data(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.checked_command(SblCommand::SendData, data)
+    }
+
+    /// CRC-32 of `size` bytes of memory from `address`.
+    pub fn crc32(&mut self, address: u32, size: u32) -> Result<u32, Error> {
+        let mut args = address.to_be_bytes().to_vec();
+        args.extend(size.to_be_bytes());
+        // read repeat count
+        args.extend(0u32.to_be_bytes());
+        self.command(SblCommand::Crc32, &args)?;
+        let rsp = self.recv_packet()?;
+        let rsp = rsp
+            .try_into()
+            .map_err(|_| Error::Packet(packet::Error::FrameCorrupted))?;
+        Ok(u32::from_be_bytes(rsp))
+    }
+
+    /// Leaves the bootloader, starting the firmware.
+    pub fn reset(&mut self) -> Result<(), Error> { self.command(SblCommand::Reset, &[]) }
+
+    /// Writes `image` at `address`, erasing every sector it touches, and
+    /// checks the CRC of the written flash.
+    ///
+    /// The image is padded with `0xFF` to a multiple of 4 bytes.
+    pub fn flash(&mut self, address: u32, image: &[u8]) -> Result<(), Error> {
+        let mut data = image.to_vec();
+        data.resize(data.len().next_multiple_of(4), 0xFF);
+        let size = data.len() as u32;
+
+        let start = address - address % self.sector_size;
+        for sector in (start..address + size).step_by(self.sector_size as usize) {
+            debug!("erasing sector {:#010x}", sector);
+            self.sector_erase(sector)?;
+        }
+        self.download(address, size)?;
+        for chunk in data.chunks(SEND_DATA_MAX) {
+            self.send_data(chunk)?;
+        }
+
+        if self.crc32(address, size)? != crc32(&data) {
+            return Err(Error::FlashMismatch(address));
+        }
+        Ok(())
+    }
+
+    /// Sends `command` and checks [`Cc26xxBootloader::status`].
+    fn checked_command(&mut self, command: SblCommand, args: &[u8]) -> Result<(), Error> {
+        self.command(command, args)?;
+        match self.status()? {
+            SblStatus::Success => Ok(()),
+            status => Err(Error::Bootloader(status)),
+        }
+    }
+
+    /// Sends `command` and waits for its acknowledgement.
+    fn command(&mut self, command: SblCommand, args: &[u8]) -> Result<(), Error> {
+        let mut data = vec![command as u8];
+        data.extend(args);
+        let mut packet = vec![data.len() as u8 + 2, checksum(&data)];
+        packet.extend(data);
+        self.write(&packet)?;
+        self.wait_ack()
+    }
+
+    fn wait_ack(&mut self) -> Result<(), Error> {
+        match self.read_nonzero()? {
+            ACK => Ok(()),
+            NACK => Err(Error::Nack),
+            _ => Err(Error::Packet(packet::Error::FrameCorrupted)),
+        }
+    }
+
+    /// Receives the response of a command and acknowledges it.
+    fn recv_packet(&mut self) -> Result<Vec<u8>, Error> {
+        let len = self.read_nonzero()?;
+        let sum = self.read_byte()?;
+        let data = (2..len)
+            .map(|_| self.read_byte())
+            .collect::<Result<Vec<_>, _>>()?;
+        if len < 2 || checksum(&data) != sum {
+            self.write(&[0x00, NACK])?;
+            return Err(Error::Packet(packet::Error::FrameCorrupted));
+        }
+        self.write(&[0x00, ACK])?;
+        Ok(data)
+    }
+
+    /// Next byte, skipping the zeros the bootloader pads with.
+    fn read_nonzero(&mut self) -> Result<u8, Error> {
+        loop {
+            match self.read_byte()? {
+                0x00 => {}
+                byte => return Ok(byte),
+            }
+        }
+    }
+
+    fn read_byte(&mut self) -> Result<u8, Error> {
+        let mut byte = [u8::MIN];
+        loop {
+            match self.transport.read(&mut byte) {
+                Ok(0) => return Err(Error::Packet(packet::Error::UnexpectedEOF)),
+                Ok(_) => return Ok(byte[0]),
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) if e.kind() == ErrorKind::TimedOut => return Err(Error::Timeout),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Err(Error::Timeout),
+                Err(e) => return Err(Error::IO(e)),
+            }
+        }
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.transport.write_all(data).map_err(Error::IO)
+    }
+}
+
+fn checksum(data: &[u8]) -> u8 { data.iter().fold(u8::MIN, |acc, &e| acc.wrapping_add(e)) }
+
+#[cfg(test)]
+mod tests {
+    use super::{Cc26xxBootloader, SblStatus};
+    use crate::sbl::sim::Cc26xxSimulator;
+    use crate::Error;
+
+    #[test]
+    fn flash() {
+        let (sim, pipe) = Cc26xxSimulator::spawn(0x58000, 0x3202_0000);
+        let mut sbl = Cc26xxBootloader::new(pipe).unwrap();
+        sbl.sync().unwrap();
+        sbl.ping().unwrap();
+        assert_eq!(sbl.chip_id().unwrap(), 0x3202_0000);
+
+        let image = (0..1000).map(|e| (e * 7) as u8).collect::<Vec<_>>();
+        sbl.flash(0x1FF0, &image).unwrap();
+        let flash = sim.flash();
+        assert_eq!(&flash[0x1FF0..0x1FF0 + 1000], image);
+        assert_eq!(flash[0x1FF0 + 1000], 0xFF);
+
+        // programming only clears bits
+        sbl.download(0x1FF0, 4).unwrap();
+        sbl.send_data(&[0xFF; 4]).unwrap();
+        let ret = sbl.crc32(0x1FF0, 4).unwrap();
+        assert_eq!(ret, crate::sbl::crc32(&image[..4]));
+
+        let ret = sbl.sector_erase(0x58000);
+        assert!(matches!(
+            ret,
+            Err(Error::Bootloader(SblStatus::InvalidAddr))
+        ));
+        assert!(!sim.is_reset());
+        sbl.reset().unwrap();
+        assert!(sim.is_reset());
+    }
+}
@@ -0,0 +1,33 @@
+//! Serial bootloaders of the chips running Z-Stack, for flashing firmware.
+
+mod cc2530;
+pub use cc2530::{Cc2530Bootloader, CC2530_IMAGE_START, SKIP_BOOTLOADER};
+mod cc26xx;
+pub use cc26xx::{Cc26xxBootloader, SblStatus, CC26X2_SECTOR_SIZE, SBL_TIMEOUT, SEND_DATA_MAX};
+#[cfg(any(test, feature = "sim"))]
+pub mod sim;
+
+/// CRC-32 of IEEE 802.3, as computed by the bootloaders.
+pub fn crc32(data: &[u8]) -> u32 {
+    let crc = data.iter().fold(u32::MAX, |mut crc, &e| {
+        crc ^= e as u32;
+        for _ in 0..8 {
+            crc = match crc & 1 {
+                0 => crc >> 1,
+                _ => crc >> 1 ^ 0xEDB8_8320,
+            };
+        }
+        crc
+    });
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::crc32;
+
+    #[test]
+    fn check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+}
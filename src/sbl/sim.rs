@@ -0,0 +1,314 @@
+//! In-process bootloaders over an in-memory [`Pipe`], for exercising the
+//! flashing code without hardware.
+
+use super::crc32;
+use super::{SblStatus, CC2530_IMAGE_START, SKIP_BOOTLOADER};
+use crate::{pipe, Pipe, Transport};
+
+use znp_types::command::sbl::{SbStatus, SB_BLOCK_LEN};
+use znp_types::command::{CommandType, Subsystem};
+use znp_types::packet::{Decoder, SOF};
+
+use std::io::{ErrorKind, Read, Write};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use log::debug;
+
+struct Cc26xxState {
+    flash: Vec<u8>,
+    chip_id: u32,
+    status: SblStatus,
+    /// address and remaining size of the pending download
+    download: Option<(usize, usize)>,
+    reset: bool,
+}
+
+/// Handle to a simulated CC26xx ROM bootloader running on its own thread.
+#[derive(Clone)]
+pub struct Cc26xxSimulator {
+    state: Arc<Mutex<Cc26xxState>>,
+}
+
+impl Cc26xxSimulator {
+    /// Starts a bootloader for a chip with `flash_size` bytes of erased
+    /// flash, returning it with the host end of its transport.
+    pub fn spawn(flash_size: usize, chip_id: u32) -> (Self, Pipe) {
+        let (host, device) = pipe();
+        let state = Arc::new(Mutex::new(Cc26xxState {
+            flash: vec![0xFF; flash_size],
+            chip_id,
+            status: SblStatus::Success,
+            download: None,
+            reset: false,
+        }));
+        let ret = Self { state };
+        let sim = ret.clone();
+        std::thread::spawn(move || sim.run(device));
+        (ret, host)
+    }
+
+    pub fn flash(&self) -> Vec<u8> { self.state.lock().unwrap().flash.clone() }
+
+    /// Whether the host left the bootloader with `COMMAND_RESET`.
+    pub fn is_reset(&self) -> bool { self.state.lock().unwrap().reset }
+
+    fn run(self, mut transport: Pipe) {
+        let _ = transport.set_timeout(Duration::from_millis(100));
+        let mut out = vec![];
+        let mut synced = false;
+        while let Some(len) = next_byte(&mut transport) {
+            out.clear();
+            let mut rsp = None;
+            match (synced, len) {
+                (false, 0x55) => {
+                    synced = next_byte(&mut transport) == Some(0x55);
+                    out.extend([0x00, 0xCC]);
+                }
+                (_, 0x00) | (false, _) => continue,
+                (true, len) => {
+                    let Some(sum) = next_byte(&mut transport) else {
+                        return;
+                    };
+                    let data = (2..len)
+                        .map(|_| next_byte(&mut transport))
+                        .collect::<Option<Vec<_>>>();
+                    let Some(data) = data else { return };
+                    let valid = checksum(&data) == sum;
+                    if !valid || data.is_empty() {
+                        out.extend([0x00, 0x33]);
+                    } else {
+                        out.extend([0x00, 0xCC]);
+                        rsp = self.handle(data[0], &data[1..]);
+                        if let Some(rsp) = &rsp {
+                            out.extend([rsp.len() as u8 + 2, checksum(rsp)]);
+                            out.extend(rsp);
+                        }
+                    }
+                }
+            }
+            if transport.write_all(&out).is_err() {
+                return;
+            }
+            // the host acknowledges responses
+            if rsp.is_some() {
+                loop {
+                    match next_byte(&mut transport) {
+                        None => return,
+                        Some(0x00) => {}
+                        Some(_) => break,
+                    }
+                }
+            }
+        }
+    }
+
+    /// Handles a command, returning its response if it has one.
+    fn handle(&self, command: u8, args: &[u8]) -> Option<Vec<u8>> {
+        let mut state = self.state.lock().unwrap();
+        let u32_at = |i: usize| {
+            let bytes = args.get(i..i + 4)?;
+            Some(u32::from_be_bytes(bytes.try_into().unwrap()) as usize)
+        };
+        let flash_size = state.flash.len();
+        let status = match command {
+            // COMMAND_PING
+            0x20 => SblStatus::Success,
+            // COMMAND_DOWNLOAD
+            0x21 => match (u32_at(0), u32_at(4)) {
+                (Some(address), Some(size)) if address + size <= flash_size => {
+                    state.download = Some((address, size));
+                    SblStatus::Success
+                }
+                _ => SblStatus::InvalidAddr,
+            },
+            // COMMAND_GET_STATUS
+            0x23 => return Some(vec![state.status as u8]),
+            // COMMAND_SEND_DATA
+            0x24 => match state.download {
+                Some((address, size)) if args.len() <= size => {
+                    for (i, e) in args.iter().enumerate() {
+                        state.flash[address + i] &= e;
+                    }
+                    let remaining = size - args.len();
+                    state.download = (remaining > 0).then_some((address + args.len(), remaining));
+                    SblStatus::Success
+                }
+                _ => SblStatus::InvalidCmd,
+            },
+            // COMMAND_RESET
+            0x25 => {
+                state.reset = true;
+                SblStatus::Success
+            }
+            // COMMAND_SECTOR_ERASE, 8 KiB sectors
+            0x26 => match u32_at(0) {
+                Some(address) if address < flash_size => {
+                    let start = address - address % 0x2000;
+                    let end = (start + 0x2000).min(flash_size);
+                    state.flash[start..end].fill(0xFF);
+                    SblStatus::Success
+                }
+                _ => SblStatus::InvalidAddr,
+            },
+            // COMMAND_CRC32
+            0x27 => {
+                return match (u32_at(0), u32_at(4)) {
+                    (Some(address), Some(size)) if address + size <= flash_size => {
+                        let crc = crc32(&state.flash[address..address + size]);
+                        Some(crc.to_be_bytes().to_vec())
+                    }
+                    _ => Some(vec![0x00; 4]),
+                };
+            }
+            // COMMAND_GET_CHIP_ID
+            0x28 => return Some(state.chip_id.to_be_bytes().to_vec()),
+            // COMMAND_BANK_ERASE
+            0x2C => {
+                state.flash.fill(0xFF);
+                SblStatus::Success
+            }
+            _ => {
+                debug!("bootloader ignoring command {:#04x}", command);
+                SblStatus::UnknownCmd
+            }
+        };
+        state.status = status;
+        None
+    }
+}
+
+/// Flash page of CC2530 chips, erased as its first block is written.
+const CC2530_PAGE_SIZE: usize = 0x800;
+
+struct Cc2530State {
+    flash: Vec<u8>,
+    enabled: bool,
+    skipped: bool,
+}
+
+/// Handle to a simulated CC2530 serial boot loader running on its own
+/// thread.
+#[derive(Clone)]
+pub struct Cc2530Simulator {
+    state: Arc<Mutex<Cc2530State>>,
+}
+
+impl Cc2530Simulator {
+    /// Starts a bootloader for a chip with `flash_size` bytes of erased
+    /// flash, returning it with the host end of its transport.
+    pub fn spawn(flash_size: usize) -> (Self, Pipe) {
+        let (host, device) = pipe();
+        let state = Arc::new(Mutex::new(Cc2530State {
+            flash: vec![0xFF; flash_size],
+            enabled: false,
+            skipped: false,
+        }));
+        let ret = Self { state };
+        let sim = ret.clone();
+        std::thread::spawn(move || sim.run(device));
+        (ret, host)
+    }
+
+    pub fn flash(&self) -> Vec<u8> { self.state.lock().unwrap().flash.clone() }
+
+    /// Whether the host marked the image bootable with `SB_ENABLE_CMD`.
+    pub fn is_enabled(&self) -> bool { self.state.lock().unwrap().enabled }
+
+    /// Whether the host started the firmware with [`SKIP_BOOTLOADER`].
+    pub fn is_skipped(&self) -> bool { self.state.lock().unwrap().skipped }
+
+    fn run(self, mut transport: Pipe) {
+        let _ = transport.set_timeout(Duration::from_millis(100));
+        let mut decoder = Decoder::new();
+        while let Some(byte) = next_byte(&mut transport) {
+            if byte == SKIP_BOOTLOADER && decoder.pending() == 0 {
+                self.state.lock().unwrap().skipped = true;
+                return;
+            }
+            decoder.extend(&[byte]);
+            let Some(frame) = decoder.decode() else {
+                continue;
+            };
+            let [subsystem, id] = frame.command_id();
+            if subsystem != Subsystem::SBL as u8 {
+                debug!("bootloader ignoring frame {:x?}", frame.command);
+                continue;
+            }
+            let data = self.handle(id, &frame.command[3..]);
+            let mut rsp = vec![
+                data.len() as u8,
+                Subsystem::SBL as u8 | CommandType::AREQ as u8,
+                id | 0x80,
+            ];
+            rsp.extend(data);
+            let fcs = rsp.iter().fold(u8::MIN, |acc, &e| acc ^ e);
+            let mut out = vec![SOF];
+            out.extend(rsp);
+            out.push(fcs);
+            if transport.write_all(&out).is_err() {
+                return;
+            }
+        }
+    }
+
+    /// Handles a command, returning the data of its response.
+    fn handle(&self, command: u8, args: &[u8]) -> Vec<u8> {
+        let mut state = self.state.lock().unwrap();
+        let address = match args {
+            [lo, hi, ..] => u16::from_le_bytes([*lo, *hi]) as usize * 4,
+            _ => 0,
+        };
+        let flash_size = state.flash.len();
+        let in_flash = address + SB_BLOCK_LEN <= flash_size;
+        match command {
+            // SB_WRITE_CMD, the bootloader itself being protected
+            0x01 => {
+                let block = &args[2.min(args.len())..];
+                let valid = in_flash
+                    && address >= CC2530_IMAGE_START as usize
+                    && block.len() == SB_BLOCK_LEN;
+                if !valid {
+                    return vec![SbStatus::Failure as u8];
+                }
+                if address % CC2530_PAGE_SIZE == 0 {
+                    state.flash[address..address + CC2530_PAGE_SIZE].fill(0xFF);
+                }
+                for (i, e) in block.iter().enumerate() {
+                    state.flash[address + i] &= e;
+                }
+                vec![SbStatus::Success as u8]
+            }
+            // SB_READ_CMD
+            0x02 if in_flash => {
+                let mut ret = vec![SbStatus::Success as u8];
+                ret.extend(&args[..2]);
+                ret.extend(&state.flash[address..address + SB_BLOCK_LEN]);
+                ret
+            }
+            // SB_ENABLE_CMD
+            0x03 => {
+                state.enabled = true;
+                vec![SbStatus::Success as u8]
+            }
+            // SB_HANDSHAKE_CMD
+            0x04 => vec![SbStatus::Success as u8],
+            _ => vec![SbStatus::Failure as u8],
+        }
+    }
+}
+
+/// Next byte from the host, `None` once it is gone.
+fn next_byte(transport: &mut Pipe) -> Option<u8> {
+    let mut byte = [u8::MIN];
+    loop {
+        match transport.read(&mut byte) {
+            Ok(0) => return None,
+            Ok(_) => return Some(byte[0]),
+            Err(e) if e.kind() == ErrorKind::TimedOut => {}
+            Err(_) => return None,
+        }
+    }
+}
+
+fn checksum(data: &[u8]) -> u8 { data.iter().fold(u8::MIN, |acc, &e| acc.wrapping_add(e)) }
@@ -0,0 +1,937 @@
+//! In-process ZNP simulator speaking MT frames over an in-memory [`Pipe`],
+//! for exercising the library without hardware.
+
+use crate::{pipe, Pipe, Transport};
+
+use znp_types::command::app_cnf::{CommissioningMode, CommissioningStatus};
+use znp_types::command::sys::{Capability, NVID};
+use znp_types::command::zdo::{DeviceState, StartupState, ZdpStatus};
+use znp_types::command::{CommandType, Status, Subsystem};
+use znp_types::nv::{
+    Item, Nib, NvStruct, NwkActiveKeyItems, NwkKeyDesc, StartupOption, APS_USE_EXT_PANID,
+    BDB_NODE_IS_ON_A_NETWORK, CHANLIST, EXTADDR, EXTENDED_PAN_ID, HAS_CONFIGURED_ZSTACK1,
+    HAS_CONFIGURED_ZSTACK3, LOGICAL_TYPE, NIB, NWK_ACTIVE_KEY_INFO, NWK_ALTERN_KEY_INFO, PANID,
+    PRECFGKEY, PRECFGKEYS_ENABLE, STARTUP_OPTION, ZDO_DIRECT_CB,
+};
+use znp_types::packet::{Decoder, Packet, SOF};
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::io::{ErrorKind, Read, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use enumflags2::BitFlags;
+use log::debug;
+
+/// IEEE address of a device whose `EXTADDR` was never written.
+const DEFAULT_EXTADDR: [u8; 8] = [0x01, 0x00, 0x00, 0x00, 0x00, 0x4B, 0x12, 0x00];
+
+/// Firmware generation, matching the variants [`crate::Builder`] detects.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Firmware {
+    /// Z-Stack Home 1.2, legacy NV only
+    ZStack12,
+    /// Z-Stack 3.0.x, legacy NV only
+    ZStack30,
+    /// Z-Stack 3.x.0, legacy and extended NV
+    ZStack3x0,
+}
+
+/// Behaviour of the simulated device.
+#[derive(Debug, Clone)]
+pub struct Profile {
+    pub firmware: Firmware,
+    pub capabilities: BitFlags<Capability>,
+    /// whether structs are padded to 32 bits, as on ARM based chips
+    pub align_structs: bool,
+}
+
+impl Profile {
+    /// CC2531 running Z-Stack Home 1.2.
+    pub fn zstack_1_2() -> Self {
+        Self {
+            firmware: Firmware::ZStack12,
+            capabilities: Capability::SYS
+                | Capability::MAC
+                | Capability::AF
+                | Capability::ZDO
+                | Capability::SAPI
+                | Capability::UTIL
+                | Capability::DEBUG
+                | Capability::APP,
+            align_structs: false,
+        }
+    }
+
+    /// CC2538 running Z-Stack 3.0.x.
+    pub fn zstack_3_0() -> Self {
+        Self {
+            firmware: Firmware::ZStack30,
+            align_structs: true,
+            ..Self::zstack_1_2()
+        }
+    }
+
+    /// CC2652 running Z-Stack 3.x.0.
+    pub fn zstack_3_30() -> Self {
+        Self {
+            firmware: Firmware::ZStack3x0,
+            align_structs: true,
+            ..Self::zstack_1_2()
+        }
+    }
+
+    fn has_ex_nv(&self) -> bool { self.firmware == Firmware::ZStack3x0 }
+}
+
+/// Fault applied to the next response sent by the simulator.
+#[derive(Debug, Clone)]
+pub enum Fault {
+    /// send the response with a wrong frame check sequence
+    CorruptFcs,
+    /// never send the response
+    DropResponse,
+    /// send these bytes before the response
+    Garbage(Vec<u8>),
+}
+
+struct State {
+    profile: Profile,
+    nv: BTreeMap<NVID, Vec<u8>>,
+    faults: VecDeque<Fault>,
+    device_state: DeviceState,
+    /// endpoints registered with AF_REGISTER
+    endpoints: BTreeSet<u8>,
+    /// source endpoint, cluster, transaction and payload of an
+    /// AF_DATA_REQUEST_EXT waiting for AF_DATA_STORE
+    stored: Option<(u8, u16, u8, Vec<u8>)>,
+    /// cluster and payload of every data request sent
+    sent: Vec<(u16, Vec<u8>)>,
+    /// end of the joining window opened with ZDO_MGMT_PERMIT_JOIN_REQ
+    permit_until: Option<Instant>,
+    /// devices and keys added with APP_CNF_BDB_ADD_INSTALLCODE
+    install_codes: Vec<([u8; 8], Vec<u8>)>,
+    /// callbacks sent once the host is idle
+    pending: VecDeque<Vec<u8>>,
+}
+
+impl State {
+    /// Status, length and value read from `offset`, either `len` bytes or as
+    /// many as fit in a frame.
+    fn read(&self, id: NVID, offset: usize, len: Option<usize>) -> Vec<u8> {
+        let Some(item) = self.nv.get(&id) else {
+            return vec![Status::NvItemUninit as u8, 0];
+        };
+        let len = len.unwrap_or(item.len().saturating_sub(offset).min(248));
+        if offset + len > item.len() {
+            return vec![Status::InvalidParameter as u8, 0];
+        }
+        let mut ret = vec![Status::Success as u8, len as u8];
+        ret.extend(&item[offset..offset + len]);
+        ret
+    }
+
+    fn write(&mut self, id: NVID, offset: usize, value: &[u8]) -> Status {
+        match self.nv.get_mut(&id) {
+            None => Status::NvItemUninit,
+            Some(item) if offset + value.len() > item.len() => Status::NvBadItemLen,
+            Some(item) => {
+                item[offset..offset + value.len()].copy_from_slice(value);
+                Status::Success
+            }
+        }
+    }
+
+    fn item<T: NvStruct>(&self, item: Item<T>) -> Option<T> {
+        let value = self.nv.get(&item.id())?;
+        T::decode(value, self.profile.align_structs).ok()
+    }
+
+    fn set_item<T: NvStruct>(&mut self, item: Item<T>, value: &T) {
+        let value = value.encode(self.profile.align_structs);
+        self.nv.insert(item.id(), value);
+    }
+
+    /// Restarts the stack, applying and clearing the startup option.
+    fn reset(&mut self) -> Vec<u8> {
+        let option = self.item(STARTUP_OPTION).unwrap_or_default();
+        let option = BitFlags::<StartupOption>::from_bits_truncate(option);
+        let mut cleared = vec![];
+        if option.contains(StartupOption::ClearState) {
+            cleared.extend([NIB.id(), NWK_ACTIVE_KEY_INFO.id(), NWK_ALTERN_KEY_INFO.id()]);
+            cleared.extend([HAS_CONFIGURED_ZSTACK1.id(), HAS_CONFIGURED_ZSTACK3.id()]);
+            cleared.push(BDB_NODE_IS_ON_A_NETWORK.id());
+        }
+        if option.contains(StartupOption::ClearConfig) {
+            cleared.extend([LOGICAL_TYPE.id(), PANID.id(), EXTENDED_PAN_ID.id()]);
+            cleared.extend([APS_USE_EXT_PANID.id(), CHANLIST.id(), PRECFGKEY.id()]);
+            cleared.extend([PRECFGKEYS_ENABLE.id(), ZDO_DIRECT_CB.id()]);
+        }
+        for id in cleared {
+            self.nv.remove(&id);
+        }
+        if !option.is_empty() {
+            self.set_item(STARTUP_OPTION, &0);
+        }
+        self.reset_ind()
+    }
+
+    /// Product id, major, minor and maintenance release and build date, as
+    /// reported by the firmware builds commonly flashed on these sticks.
+    fn version(&self) -> (u8, u8, u8, u8, u32) {
+        match self.profile.firmware {
+            Firmware::ZStack12 => (0, 2, 6, 3, 20190608),
+            Firmware::ZStack30 => (2, 2, 7, 1, 20190425),
+            Firmware::ZStack3x0 => (1, 2, 7, 1, 20210708),
+        }
+    }
+
+    /// SYS_RESET_IND after an external reset.
+    fn reset_ind(&mut self) -> Vec<u8> {
+        self.device_state = DeviceState::Hold;
+        let (product_id, major, minor, _, _) = self.version();
+        // reason, transport revision, product id, major, minor, hardware revision
+        let data = vec![0x01, 0x02, product_id, major, minor, 0x01];
+        areq(Subsystem::IFaceSYS as u8, 0x80, data)
+    }
+
+    /// Forms a network from the configuration in NV, returning the state
+    /// changes.
+    fn form(&mut self) -> Vec<Vec<u8>> {
+        let channel_mask = self.item(CHANLIST).unwrap_or(1 << 11);
+        let nib = Nib {
+            nwk_pan_id: self.item(PANID).unwrap_or(0x1A62),
+            extended_pan_id: self.item(EXTENDED_PAN_ID).unwrap_or([0xDD; 8]),
+            nwk_logical_channel: (11..=26).find(|e| channel_mask & 1 << e != 0).unwrap_or(11),
+            channel_list: channel_mask,
+            security_level: 5,
+            nwk_key_loaded: true,
+            ..Default::default()
+        };
+        let key = NwkActiveKeyItems {
+            active: NwkKeyDesc {
+                key_seq_num: 0,
+                key: self.item(PRECFGKEY).unwrap_or_default(),
+            },
+            frame_counter: 0,
+        };
+        self.set_item(NIB, &nib);
+        self.set_item(NWK_ACTIVE_KEY_INFO, &key);
+        if self.item(EXTADDR).is_none() {
+            self.set_item(EXTADDR, &DEFAULT_EXTADDR);
+        }
+        self.device_state = DeviceState::ZbCoord;
+        vec![
+            state_change(DeviceState::CoordStarting),
+            state_change(DeviceState::ZbCoord),
+        ]
+    }
+
+    /// Sends application data from `src_endpoint`, returning the status and
+    /// the AF_DATA_CONFIRM.
+    fn send_data(
+        &mut self,
+        src_endpoint: u8,
+        cluster_id: u16,
+        trans_id: u8,
+        data: Vec<u8>,
+    ) -> (Status, Option<Vec<u8>>) {
+        if !self.endpoints.contains(&src_endpoint) {
+            return (Status::InvalidParameter, None);
+        }
+        self.sent.push((cluster_id, data));
+        let data = vec![Status::Success as u8, src_endpoint, trans_id];
+        let confirm = areq(Subsystem::IFaceAF as u8, 0x80, data);
+        (Status::Success, Some(confirm))
+    }
+
+    fn create(&mut self, id: NVID, len: usize, init: &[u8]) -> Status {
+        if self.nv.contains_key(&id) {
+            return Status::Success;
+        }
+        let mut item = vec![u8::MIN; len];
+        let init_len = init.len().min(len);
+        item[..init_len].copy_from_slice(&init[..init_len]);
+        self.nv.insert(id, item);
+        Status::NvItemUninit
+    }
+}
+
+/// Handle to a simulated ZNP running on its own thread.
+///
+/// The simulator stops once the host end of the pipe is dropped.
+#[derive(Clone)]
+pub struct Simulator {
+    state: Arc<Mutex<State>>,
+}
+
+impl Simulator {
+    /// Starts a simulator, returning it with the host end of its transport.
+    pub fn spawn(profile: Profile) -> (Self, Pipe) {
+        let (host, device) = pipe();
+        let state = Arc::new(Mutex::new(State {
+            profile,
+            nv: BTreeMap::new(),
+            faults: VecDeque::new(),
+            device_state: DeviceState::Hold,
+            endpoints: BTreeSet::new(),
+            stored: None,
+            sent: vec![],
+            permit_until: None,
+            install_codes: vec![],
+            pending: VecDeque::new(),
+        }));
+        let ret = Self { state };
+        let sim = ret.clone();
+        std::thread::spawn(move || sim.run(device));
+        (ret, host)
+    }
+
+    /// Starts a simulator and connects to it with a short timeout.
+    #[cfg(test)]
+    pub(crate) fn connect(profile: Profile) -> (Self, impl crate::ZNP) {
+        let (sim, pipe) = Self::spawn(profile);
+        let znp = crate::Builder::from_transport(pipe)
+            .timeout(Duration::from_millis(200))
+            .connect()
+            .unwrap();
+        (sim, znp)
+    }
+
+    /// Opens another transport to the same device, as when the host
+    /// process restarts.
+    pub fn reconnect(&self) -> Pipe {
+        let (host, device) = pipe();
+        let sim = self.clone();
+        std::thread::spawn(move || sim.run(device));
+        host
+    }
+
+    pub fn profile(&self) -> Profile { self.state.lock().unwrap().profile.clone() }
+
+    /// Queues a fault for the next response.
+    pub fn inject(&self, fault: Fault) { self.state.lock().unwrap().faults.push_back(fault); }
+
+    /// NV item as stored by the device, legacy items live under
+    /// [`NVID::legacy`].
+    pub fn nv(&self, id: NVID) -> Option<Vec<u8>> {
+        self.state.lock().unwrap().nv.get(&id).cloned()
+    }
+
+    pub fn set_nv(&self, id: NVID, value: Vec<u8>) {
+        self.state.lock().unwrap().nv.insert(id, value);
+    }
+
+    /// Cluster and payload of every data request the device sent.
+    pub fn sent(&self) -> Vec<(u16, Vec<u8>)> { self.state.lock().unwrap().sent.clone() }
+
+    /// Devices and derived keys added as install codes.
+    pub fn install_codes(&self) -> Vec<([u8; 8], Vec<u8>)> {
+        self.state.lock().unwrap().install_codes.clone()
+    }
+
+    /// Lets a device join if the network is open, sending the trust center
+    /// indication and the announcement of the device.
+    pub fn join(&self, nwk_addr: u16, ieee_addr: [u8; 8]) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.permit_until.is_none_or(|e| e <= Instant::now()) {
+            return false;
+        }
+        const ZDO: u8 = Subsystem::IFaceZDO as u8;
+        // ZDO_TC_DEV_IND, joined through the coordinator
+        let mut data = nwk_addr.to_le_bytes().to_vec();
+        data.extend(ieee_addr);
+        data.extend(0x0000u16.to_le_bytes());
+        state.pending.push_back(areq(ZDO, 0xCA, data));
+        // ZDO_END_DEVICE_ANNCE_IND of a mains powered router
+        let mut data = nwk_addr.to_le_bytes().to_vec();
+        data.extend(nwk_addr.to_le_bytes());
+        data.extend(ieee_addr);
+        data.push(0x8E);
+        state.pending.push_back(areq(ZDO, 0xC1, data));
+        true
+    }
+
+    fn run(self, mut transport: Pipe) {
+        let mut decoder = Decoder::new();
+        let mut chunk = [u8::MIN; 256];
+        let _ = transport.set_timeout(Duration::from_millis(100));
+        loop {
+            while let Some(frame) = decoder.decode() {
+                for rsp in self.handle(&frame) {
+                    if self.send(&mut transport, rsp).is_err() {
+                        return;
+                    }
+                }
+            }
+            match transport.read(&mut chunk) {
+                Ok(0) => return,
+                Ok(len) => decoder.extend(&chunk[..len]),
+                Err(e) if e.kind() == ErrorKind::TimedOut => {
+                    let pending = std::mem::take(&mut self.state.lock().unwrap().pending);
+                    for command in pending {
+                        if self.send(&mut transport, command).is_err() {
+                            return;
+                        }
+                    }
+                }
+                Err(_) => return,
+            }
+        }
+    }
+
+    /// Serves an async transport instead of a [`Pipe`], without faults.
+    #[cfg(all(test, feature = "tokio"))]
+    pub(crate) async fn serve(self, mut transport: tokio::io::DuplexStream) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let mut decoder = Decoder::new();
+        let mut chunk = [u8::MIN; 256];
+        loop {
+            while let Some(frame) = decoder.decode() {
+                for rsp in self.handle(&frame) {
+                    if transport.write_all(&frame_of(&rsp)).await.is_err() {
+                        return;
+                    }
+                }
+            }
+            match transport.read(&mut chunk).await {
+                Ok(0) | Err(_) => return,
+                Ok(len) => decoder.extend(&chunk[..len]),
+            }
+        }
+    }
+
+    fn send(&self, transport: &mut Pipe, command: Vec<u8>) -> std::io::Result<()> {
+        let mut frame = frame_of(&command);
+        match self.state.lock().unwrap().faults.pop_front() {
+            None => {}
+            Some(Fault::CorruptFcs) => *frame.last_mut().unwrap() ^= 0xFF,
+            Some(Fault::DropResponse) => return Ok(()),
+            Some(Fault::Garbage(garbage)) => transport.write_all(&garbage)?,
+        }
+        transport.write_all(&frame)
+    }
+
+    /// Returns the commands sent in response to `frame`, the SRSP followed
+    /// by any callbacks.
+    fn handle(&self, frame: &Packet) -> Vec<Vec<u8>> {
+        // SYS_RESET_REQ
+        if frame.command_type() == CommandType::AREQ as u8 && frame.command_id() == [0x01, 0x00] {
+            return vec![self.state.lock().unwrap().reset()];
+        }
+        if frame.command_type() != CommandType::SREQ as u8 {
+            debug!("simulator ignoring frame {:x?}", frame.command);
+            return vec![];
+        }
+        let cmd = frame.command_id();
+        let data = &frame.command[3..];
+        let mut state = self.state.lock().unwrap();
+
+        const SYS: u8 = Subsystem::IFaceSYS as u8;
+        const UTIL: u8 = Subsystem::IFaceUTIL as u8;
+        const AF: u8 = Subsystem::IFaceAF as u8;
+        const ZDO: u8 = Subsystem::IFaceZDO as u8;
+        const APP_CNF: u8 = Subsystem::ConfigAPP as u8;
+        let mut callbacks = vec![];
+        let ex_nv = state.profile.has_ex_nv();
+        let osal_ext = state.profile.firmware != Firmware::ZStack12;
+        let rsp = match (cmd[0], cmd[1]) {
+            // SYS_PING
+            (SYS, 0x01) => state.profile.capabilities.bits().to_le_bytes().to_vec(),
+            // SYS_VERSION
+            (SYS, 0x02) => {
+                let (product_id, major, minor, maint, revision) = state.version();
+                let mut ret = vec![0x02, product_id, major, minor, maint];
+                ret.extend(revision.to_le_bytes());
+                ret
+            }
+            // SYS_OSAL_NV_ITEM_INIT
+            (SYS, 0x07) if data.len() >= 5 => {
+                let len = u16_at(data, 2) as usize;
+                vec![state.create(NVID::legacy(u16_at(data, 0)), len, &data[5..]) as u8]
+            }
+            // SYS_OSAL_NV_READ
+            (SYS, 0x08) if data.len() == 3 => {
+                state.read(NVID::legacy(u16_at(data, 0)), data[2] as usize, None)
+            }
+            // SYS_OSAL_NV_WRITE
+            (SYS, 0x09) if data.len() >= 4 => {
+                let id = NVID::legacy(u16_at(data, 0));
+                vec![state.write(id, data[2] as usize, &data[4..]) as u8]
+            }
+            // SYS_OSAL_NV_DELETE
+            (SYS, 0x12) if data.len() == 4 => {
+                let id = NVID::legacy(u16_at(data, 0));
+                let status = match state.nv.get(&id) {
+                    None => Status::NvItemUninit,
+                    Some(item) if item.len() != u16_at(data, 2) as usize => Status::NvBadItemLen,
+                    Some(_) => {
+                        state.nv.remove(&id);
+                        Status::Success
+                    }
+                };
+                vec![status as u8]
+            }
+            // SYS_OSAL_NV_LENGTH
+            (SYS, 0x13) if data.len() == 2 => {
+                let len = state
+                    .nv
+                    .get(&NVID::legacy(u16_at(data, 0)))
+                    .map_or(0, |e| e.len());
+                (len as u16).to_le_bytes().to_vec()
+            }
+            // SYS_OSAL_NV_READ_EXT
+            (SYS, 0x1C) if osal_ext && data.len() == 4 => state.read(
+                NVID::legacy(u16_at(data, 0)),
+                u16_at(data, 2) as usize,
+                None,
+            ),
+            // SYS_OSAL_NV_WRITE_EXT
+            (SYS, 0x1D) if osal_ext && data.len() >= 6 => {
+                let id = NVID::legacy(u16_at(data, 0));
+                vec![state.write(id, u16_at(data, 2) as usize, &data[6..]) as u8]
+            }
+            // SYS_NV_CREATE
+            (SYS, 0x30) if ex_nv && data.len() == 9 => {
+                let len = u32::from_le_bytes([data[5], data[6], data[7], data[8]]) as usize;
+                vec![state.create(nv_id(data), len, &[]) as u8]
+            }
+            // SYS_NV_DELETE
+            (SYS, 0x31) if ex_nv && data.len() == 5 => match state.nv.remove(&nv_id(data)) {
+                Some(_) => vec![Status::Success as u8],
+                None => vec![Status::NvItemUninit as u8],
+            },
+            // SYS_NV_LENGTH
+            (SYS, 0x32) if ex_nv && data.len() == 5 => {
+                let len = state.nv.get(&nv_id(data)).map_or(0, |e| e.len());
+                (len as u32).to_le_bytes().to_vec()
+            }
+            // SYS_NV_READ
+            (SYS, 0x33) if ex_nv && data.len() == 8 => state.read(
+                nv_id(data),
+                u16_at(data, 5) as usize,
+                Some(data[7] as usize),
+            ),
+            // SYS_NV_WRITE
+            (SYS, 0x34) if ex_nv && data.len() >= 8 => {
+                vec![state.write(nv_id(data), u16_at(data, 5) as usize, &data[8..]) as u8]
+            }
+            // SYS_NV_UPDATE
+            (SYS, 0x35) if ex_nv && data.len() >= 6 => {
+                state.nv.insert(nv_id(data), data[6..].to_vec());
+                vec![Status::Success as u8]
+            }
+            // AF_REGISTER
+            (AF, 0x00) if data.len() >= 9 => match state.endpoints.insert(data[0]) {
+                true => vec![Status::Success as u8],
+                false => vec![Status::ApsDuplicateEntry as u8],
+            },
+            // AF_DATA_REQUEST
+            (AF, 0x01) if data.len() >= 10 && data.len() == 10 + data[9] as usize => {
+                let payload = data[10..].to_vec();
+                let (status, confirm) = state.send_data(data[3], u16_at(data, 4), data[6], payload);
+                callbacks.extend(confirm);
+                vec![status as u8]
+            }
+            // AF_DATA_REQUEST_EXT
+            (AF, 0x02) if data.len() >= 20 => {
+                let len = u16_at(data, 18) as usize;
+                let (src_endpoint, cluster_id, trans_id) = (data[12], u16_at(data, 13), data[15]);
+                if data.len() == 20 && len > 0 {
+                    state.stored = Some((src_endpoint, cluster_id, trans_id, vec![0; len]));
+                    vec![Status::Success as u8]
+                } else {
+                    let payload = data[20..].to_vec();
+                    let (status, confirm) =
+                        state.send_data(src_endpoint, cluster_id, trans_id, payload);
+                    callbacks.extend(confirm);
+                    vec![status as u8]
+                }
+            }
+            // AF_DATA_REQUEST_SRC_RTG
+            (AF, 0x03) if data.len() >= 11 => {
+                let offset = 10 + 2 * data[9] as usize;
+                let payload = data[offset + 1..].to_vec();
+                let (status, confirm) = state.send_data(data[3], u16_at(data, 4), data[6], payload);
+                callbacks.extend(confirm);
+                vec![status as u8]
+            }
+            // AF_DATA_STORE
+            (AF, 0x11) if data.len() >= 3 => {
+                let (index, chunk) = (u16_at(data, 0) as usize, &data[3..]);
+                match state.stored.take() {
+                    None => vec![Status::Failure as u8],
+                    Some((src_endpoint, cluster_id, trans_id, payload)) if chunk.is_empty() => {
+                        let (status, confirm) =
+                            state.send_data(src_endpoint, cluster_id, trans_id, payload);
+                        callbacks.extend(confirm);
+                        vec![status as u8]
+                    }
+                    Some(mut stored) => {
+                        let status = match stored.3.get_mut(index..index + chunk.len()) {
+                            Some(dst) => {
+                                dst.copy_from_slice(chunk);
+                                Status::Success
+                            }
+                            None => Status::InvalidParameter,
+                        };
+                        state.stored = Some(stored);
+                        vec![status as u8]
+                    }
+                }
+            }
+            // UTIL_GET_DEVICE_INFO, no device associated
+            (UTIL, 0x00) => {
+                let mut ret = vec![Status::Success as u8];
+                ret.extend(state.item(EXTADDR).unwrap_or(DEFAULT_EXTADDR));
+                let short_addr: u16 = match state.device_state {
+                    DeviceState::ZbCoord => 0x0000,
+                    _ => 0xFFFE,
+                };
+                ret.extend(short_addr.to_le_bytes());
+                ret.extend([0x07, state.device_state as u8, 0x00]);
+                ret
+            }
+            // UTIL_ASSOC_FIND_DEVICE, no device associated
+            (UTIL, 0x49) => {
+                let len = match state.profile.firmware {
+                    // no end device configuration in associated_devices_t
+                    Firmware::ZStack12 => 18,
+                    _ if state.profile.align_structs => 36,
+                    _ => 28,
+                };
+                let mut ret = vec![u8::MIN; len];
+                // invalid short address
+                ret[..2].copy_from_slice(&0xFFFEu16.to_le_bytes());
+                ret
+            }
+            // ZDO_STARTUP_FROM_APP
+            (ZDO, 0x40) if data.len() == 2 => {
+                if state.nv.contains_key(&NIB.id()) {
+                    state.device_state = DeviceState::ZbCoord;
+                    callbacks.push(state_change(DeviceState::ZbCoord));
+                    vec![StartupState::RestoredNetworkState as u8]
+                } else if state.profile.firmware == Firmware::ZStack12 {
+                    callbacks.extend(state.form());
+                    vec![StartupState::NewNetworkState as u8]
+                } else {
+                    vec![StartupState::NewNetworkState as u8]
+                }
+            }
+            // ZDO_MGMT_PERMIT_JOIN_REQ, answered by the coordinator only
+            (ZDO, 0x36) if data.len() == 5 => {
+                let (status, src_addr) = match (data[0], u16_at(data, 1)) {
+                    (0x0F, _) | (0x02, 0x0000) => {
+                        let duration = Duration::from_secs(data[3].into());
+                        state.permit_until = Some(Instant::now() + duration);
+                        (ZdpStatus::Success, 0x0000)
+                    }
+                    (_, addr) => (ZdpStatus::DeviceNotFound, addr),
+                };
+                let mut rsp = src_addr.to_le_bytes().to_vec();
+                rsp.push(status as u8);
+                callbacks.push(areq(ZDO, 0xB6, rsp));
+                vec![Status::Success as u8]
+            }
+            // APP_CNF_SET_ALLOWREJOIN_TC_POLICY, APP_CNF_BDB_SET_CHANNEL,
+            // APP_CNF_BDB_SET_TC_REQUIRE_KEY_EXCHANGE
+            (APP_CNF, 0x03 | 0x08 | 0x09) if osal_ext => vec![Status::Success as u8],
+            // APP_CNF_BDB_ADD_INSTALLCODE, derived keys only
+            (APP_CNF, 0x04) if ex_nv && data.len() == 25 && data[0] == 0x02 => {
+                let ieee = data[1..9].try_into().unwrap();
+                state.install_codes.push((ieee, data[9..].to_vec()));
+                vec![Status::Success as u8]
+            }
+            // APP_CNF_BDB_START_COMMISSIONING
+            (APP_CNF, 0x05) if osal_ext && data.len() == 1 => {
+                let mode = data[0];
+                let notification = |status: CommissioningStatus| {
+                    areq(APP_CNF, 0x80, vec![status as u8, mode, 0x00])
+                };
+                let on_network = state.nv.contains_key(&NIB.id());
+                if mode & CommissioningMode::NwkFormation as u8 != 0 && on_network {
+                    callbacks.push(notification(CommissioningStatus::FormationFailure));
+                } else if mode & CommissioningMode::NwkFormation as u8 != 0 {
+                    callbacks.push(notification(CommissioningStatus::InProgress));
+                    callbacks.extend(state.form());
+                    callbacks.push(notification(CommissioningStatus::Success));
+                } else {
+                    callbacks.push(notification(CommissioningStatus::NoNetwork));
+                }
+                vec![Status::Success as u8]
+            }
+            _ => return vec![command_not_found(&frame.command)],
+        };
+
+        let mut ret = vec![rsp.len() as u8, cmd[0] | CommandType::SRSP as u8, cmd[1]];
+        ret.extend(rsp);
+        let mut ret = vec![ret];
+        ret.extend(callbacks);
+        ret
+    }
+}
+
+/// MT frame carrying `command`.
+fn frame_of(command: &[u8]) -> Vec<u8> {
+    let mut ret = vec![SOF];
+    ret.extend(command);
+    ret.push(command.iter().fold(u8::MIN, |acc, &e| acc ^ e));
+    ret
+}
+
+fn areq(subsystem: u8, id: u8, data: Vec<u8>) -> Vec<u8> {
+    let mut ret = vec![data.len() as u8, subsystem | CommandType::AREQ as u8, id];
+    ret.extend(data);
+    ret
+}
+
+fn state_change(state: DeviceState) -> Vec<u8> {
+    areq(Subsystem::IFaceZDO as u8, 0xC0, vec![state as u8])
+}
+
+fn u16_at(data: &[u8], i: usize) -> u16 { u16::from_le_bytes([data[i], data[i + 1]]) }
+
+fn nv_id(data: &[u8]) -> NVID { NVID::new(data[0], u16_at(data, 1), u16_at(data, 3)) }
+
+/// RPC error for an unsupported command, see Z-stack Monitor and Test API, 3.1.
+fn command_not_found(command: &[u8]) -> Vec<u8> {
+    const COMMAND_ID_INVALID: u8 = 0x02;
+    vec![
+        0x03,
+        CommandType::SRSP as u8,
+        0x00,
+        COMMAND_ID_INVALID,
+        command[1],
+        command[2],
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Fault, Profile, Simulator};
+    use crate::{Builder, Error, NVRam, RetryPolicy, Session, ZNP};
+
+    use znp_types::command::af::{Address, DataRequest, DataRequestExt, Register};
+    use znp_types::command::sys::{
+        ExNvIds, NVLength, NVRead, NvSysIds, Ping, Product, ResetReason, ResetType, NVID,
+    };
+    use znp_types::command::util::DeviceType;
+    use znp_types::command::zdo::DeviceState;
+    use znp_types::command::Status;
+    use znp_types::nv::{NwkActiveKeyItems, NwkKeyDesc, NWK_ACTIVE_KEY_INFO};
+
+    use std::time::{Duration, Instant};
+
+    use semver::Version;
+
+    #[test]
+    fn detect_firmware() {
+        let (_, znp) = Simulator::connect(Profile::zstack_3_30());
+        assert_eq!(znp.version(), Version::parse("3.30.0+2.7.1").unwrap());
+        assert_eq!(znp.firmware().product, Product::ZStack3x0);
+        assert!(znp.align_structs());
+
+        let (_, znp) = Simulator::connect(Profile::zstack_3_0());
+        assert_eq!(znp.version(), Version::parse("3.0.0+2.7.1").unwrap());
+        assert_eq!(znp.firmware().product, Product::ZStack30);
+
+        let (_, znp) = Simulator::connect(Profile::zstack_1_2());
+        assert_eq!(znp.version(), Version::parse("1.2.0+2.6.3").unwrap());
+        assert_eq!(znp.firmware().revision, Some(20190608));
+        assert!(!znp.align_structs());
+    }
+
+    #[test]
+    fn device_info() {
+        let (_, mut znp) = Simulator::connect(Profile::zstack_3_30());
+        let info = znp.device_info().unwrap();
+        assert_eq!(info.device_state, DeviceState::Hold);
+        assert_eq!(info.short_addr, 0xFFFE);
+        assert!(info.device_type.contains(DeviceType::Coordinator));
+    }
+
+    #[test]
+    fn reset() {
+        let (_, mut znp) = Simulator::connect(Profile::zstack_3_30());
+        let info = znp.reset(ResetType::Soft).unwrap();
+        assert_eq!(info.reason, ResetReason::External);
+        assert_eq!(info.product_id, 1);
+        assert!(znp.request(&Ping::default()).is_ok());
+    }
+
+    #[test]
+    fn send_aps() {
+        let (sim, mut znp) = Simulator::connect(Profile::zstack_3_30());
+        let request = |data| DataRequestExt::new(Address::Nwk(0x1234), 1, 1, 0x0006, data);
+        let ret = znp.send_aps(request(vec![0x01]));
+        assert!(matches!(ret, Err(Error::Status(Status::InvalidParameter))));
+
+        let register = Register::new(1, 0x0104, 0x0005)
+            .out_clusters(vec![0x0006])
+            .unwrap();
+        assert!(matches!(znp.request(&register).unwrap(), Status::Success));
+        assert!(matches!(
+            znp.request(&register).unwrap(),
+            Status::ApsDuplicateEntry
+        ));
+
+        // leaves its confirm for send_aps to skip
+        let other = DataRequest::new(0x1234, 1, 1, 0x0006, vec![0x02])
+            .unwrap()
+            .trans_id(0xF0);
+        assert!(matches!(znp.request(&other).unwrap(), Status::Success));
+        let tsn = znp.send_aps(request(vec![0x03])).unwrap();
+
+        let long = (0..600).map(|e| e as u8).collect::<Vec<_>>();
+        assert_eq!(znp.send_aps(request(long.clone())).unwrap(), tsn + 1);
+        let sent = sim.sent();
+        assert_eq!(sent.len(), 3);
+        assert_eq!(sent[2], (0x0006, long));
+    }
+
+    #[test]
+    fn nv_read() {
+        let (sim, mut znp) = Simulator::connect(Profile::zstack_3_30());
+        let id = NVID::new(NvSysIds::ZStack as u8, ExNvIds::TClkTable as u16, 0);
+        sim.set_nv(id, vec![0xAA; 20]);
+
+        assert_eq!(znp.request(&NVLength::new(id)).unwrap(), 20);
+        let (status, value) = znp.request(&NVRead::new(id, 4, 8)).unwrap();
+        assert!(matches!(status, Status::Success));
+        assert_eq!(value, vec![0xAA; 8]);
+
+        let missing = NVID::new(NvSysIds::ZStack as u8, ExNvIds::TClkTable as u16, 1);
+        let (status, _) = znp.request(&NVRead::new(missing, 0, 8)).unwrap();
+        assert!(matches!(status, Status::NvItemUninit));
+    }
+
+    #[test]
+    fn faults() {
+        let (sim, pipe) = Simulator::spawn(Profile::zstack_3_30());
+        let mut znp = Builder::from_transport(pipe)
+            .timeout(Duration::from_millis(100))
+            .retry_policy(RetryPolicy::new(1, Duration::from_millis(10)))
+            .connect()
+            .unwrap();
+
+        sim.inject(Fault::Garbage(vec![0xFE, 0x05, 0x00]));
+        assert!(znp.request(&Ping::default()).is_ok());
+
+        sim.inject(Fault::CorruptFcs);
+        assert!(znp.request(&Ping::default()).is_ok());
+
+        sim.inject(Fault::DropResponse);
+        sim.inject(Fault::DropResponse);
+        let ret = znp.request(&Ping::default());
+        assert!(matches!(ret, Err(Error::Timeout)));
+    }
+
+    fn connect_with(policy: RetryPolicy) -> (Simulator, impl ZNP) {
+        let (sim, pipe) = Simulator::spawn(Profile::zstack_3_30());
+        let znp = Builder::from_transport(pipe)
+            .timeout(Duration::from_millis(50))
+            .retry_policy(policy)
+            .connect()
+            .unwrap();
+        (sim, znp)
+    }
+
+    #[test]
+    fn retries() {
+        let (sim, mut znp) = connect_with(RetryPolicy::new(2, Duration::from_millis(10)));
+
+        sim.inject(Fault::DropResponse);
+        sim.inject(Fault::DropResponse);
+        assert!(znp.request(&Ping::default()).is_ok());
+
+        // three attempts consume all three faults, the next request gets through
+        (0..3).for_each(|_| sim.inject(Fault::DropResponse));
+        let ret = znp.request(&Ping::default());
+        assert!(matches!(ret, Err(Error::Timeout)));
+        assert!(znp.request(&Ping::default()).is_ok());
+    }
+
+    #[test]
+    fn retry_backoff() {
+        let (sim, mut znp) = connect_with(RetryPolicy::new(3, Duration::from_millis(40)));
+        (0..4).for_each(|_| sim.inject(Fault::DropResponse));
+        let start = Instant::now();
+        assert!(matches!(znp.request(&Ping::default()), Err(Error::Timeout)));
+        // 4 timeouts of 50ms plus backoffs of 40, 80 and 160ms
+        assert!(start.elapsed() >= Duration::from_millis(480));
+
+        let policy = RetryPolicy::new(3, Duration::from_millis(40))
+            .with_max_backoff(Duration::from_millis(40));
+        let (sim, mut znp) = connect_with(policy);
+        (0..4).for_each(|_| sim.inject(Fault::DropResponse));
+        let start = Instant::now();
+        assert!(matches!(znp.request(&Ping::default()), Err(Error::Timeout)));
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(320));
+        assert!(elapsed < Duration::from_millis(480));
+    }
+
+    #[test]
+    fn timeout() {
+        let (sim, mut znp) = connect_with(RetryPolicy::none());
+        sim.inject(Fault::DropResponse);
+        let start = Instant::now();
+        let ret = znp.request_timeout(&Ping::default(), Duration::from_millis(100));
+        assert!(matches!(ret, Err(Error::Timeout)));
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(100));
+        assert!(elapsed < Duration::from_millis(300));
+        assert!(znp.request(&Ping::default()).is_ok());
+    }
+
+    #[test]
+    fn nv_chunked() {
+        let value = (0..600).map(|e| e as u8).collect::<Vec<_>>();
+        let ex_id = NVID::new(NvSysIds::ZStack as u8, ExNvIds::AddrMgr as u16, 0);
+        for profile in [Profile::zstack_3_0(), Profile::zstack_3_30()] {
+            let (sim, mut znp) = Simulator::connect(profile);
+            let id = NVID::legacy(0x0101);
+            assert!(znp.nv_create(id, 300).unwrap());
+            assert!(!znp.nv_create(id, 300).unwrap());
+            let legacy_value = &value[..300];
+            znp.nv_write(id, legacy_value).unwrap();
+            assert_eq!(sim.nv(id).unwrap(), legacy_value);
+            assert_eq!(znp.nv_read(id).unwrap(), legacy_value);
+
+            assert!(znp.nv_delete(id).unwrap());
+            assert!(matches!(
+                znp.nv_read(id),
+                Err(Error::Status(Status::NvItemUninit))
+            ));
+
+            if znp.version() >= Version::new(3, 30, 0) {
+                znp.nv_store(ex_id, value.as_slice()).unwrap();
+                assert_eq!(znp.nv_read(ex_id).unwrap(), value);
+            } else {
+                assert!(matches!(znp.nv_len(ex_id), Err(Error::CommandNotFound)));
+            }
+        }
+    }
+
+    #[test]
+    fn nv_typed() {
+        let key = NwkActiveKeyItems {
+            active: NwkKeyDesc {
+                key_seq_num: 0,
+                key: [0x01; 16],
+            },
+            frame_counter: 1000,
+        };
+        for (align_structs, len) in [(false, 21), (true, 24)] {
+            let profile = Profile {
+                align_structs,
+                ..Profile::zstack_3_0()
+            };
+            let (sim, mut znp) = Simulator::connect(profile);
+            znp.nv_set(NWK_ACTIVE_KEY_INFO, &key).unwrap();
+            assert_eq!(sim.nv(NWK_ACTIVE_KEY_INFO.id()).unwrap().len(), len);
+            assert_eq!(znp.nv_get(NWK_ACTIVE_KEY_INFO).unwrap(), key);
+        }
+    }
+}
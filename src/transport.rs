@@ -0,0 +1,110 @@
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::sync::mpsc;
+use std::time::Duration;
+
+use serialport::SerialPort;
+
+/// Byte stream a ZNP is attached to.
+pub trait Transport: Read + Write + Send {
+    /// Sets how long a read blocks before failing with
+    /// [`ErrorKind::TimedOut`] or [`ErrorKind::WouldBlock`].
+    fn set_timeout(&mut self, timeout: Duration) -> io::Result<()>;
+}
+
+/// Resets the chip through the RTS line, holding the bootloader backdoor
+/// pin driven by DTR active if `bootloader` is set.
+pub(crate) fn reset_tty(tty: &mut dyn SerialPort, bootloader: bool) -> serialport::Result<()> {
+    for (dtr, rts) in [(bootloader, false), (bootloader, true), (bootloader, false)] {
+        tty.write_data_terminal_ready(dtr)?;
+        tty.write_request_to_send(rts)?;
+    }
+    if bootloader {
+        // the pin is sampled once the chip is out of reset
+        std::thread::sleep(Duration::from_millis(2));
+        tty.write_data_terminal_ready(false)?;
+    }
+    Ok(())
+}
+
+impl<T: Transport + ?Sized> Transport for Box<T> {
+    fn set_timeout(&mut self, timeout: Duration) -> io::Result<()> { (**self).set_timeout(timeout) }
+}
+
+impl Transport for Box<dyn SerialPort> {
+    fn set_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        SerialPort::set_timeout(self.as_mut(), timeout).map_err(io::Error::from)
+    }
+}
+
+// a zero timeout means blocking forever for sockets
+fn socket_timeout(timeout: Duration) -> Option<Duration> {
+    Some(timeout.max(Duration::from_millis(1)))
+}
+
+impl Transport for TcpStream {
+    fn set_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        self.set_read_timeout(socket_timeout(timeout))
+    }
+}
+
+#[cfg(unix)]
+impl Transport for std::os::unix::net::UnixStream {
+    fn set_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        self.set_read_timeout(socket_timeout(timeout))
+    }
+}
+
+/// One end of an in-memory [`pipe`].
+pub struct Pipe {
+    tx: mpsc::Sender<Vec<u8>>,
+    rx: mpsc::Receiver<Vec<u8>>,
+    buffer: Vec<u8>,
+    timeout: Duration,
+}
+
+/// Creates a pair of connected in-memory transports, mostly for tests.
+pub fn pipe() -> (Pipe, Pipe) {
+    let (tx_a, rx_a) = mpsc::channel();
+    let (tx_b, rx_b) = mpsc::channel();
+    let end = |tx, rx| Pipe {
+        tx,
+        rx,
+        buffer: vec![],
+        timeout: Duration::ZERO,
+    };
+    (end(tx_a, rx_b), end(tx_b, rx_a))
+}
+
+impl Read for Pipe {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.buffer.is_empty() {
+            self.buffer = match self.rx.recv_timeout(self.timeout) {
+                Ok(data) => data,
+                Err(mpsc::RecvTimeoutError::Timeout) => return Err(ErrorKind::TimedOut.into()),
+                Err(mpsc::RecvTimeoutError::Disconnected) => return Ok(0),
+            };
+        }
+        let len = buf.len().min(self.buffer.len());
+        buf[..len].copy_from_slice(&self.buffer[..len]);
+        self.buffer.drain(..len);
+        Ok(len)
+    }
+}
+
+impl Write for Pipe {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.tx
+            .send(buf.to_vec())
+            .map_err(|_| io::Error::from(ErrorKind::BrokenPipe))?;
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> { Ok(()) }
+}
+
+impl Transport for Pipe {
+    fn set_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        self.timeout = timeout;
+        Ok(())
+    }
+}
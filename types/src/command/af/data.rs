@@ -0,0 +1,501 @@
+use crate::command::{de, ser, Command, CommandID, CommandType, Status};
+use crate::packet::MAX_DATA_LEN;
+
+use znp_macros::Command;
+
+use enumflags2::BitFlags;
+use num_traits::FromPrimitive;
+
+use super::SUBSYS;
+
+/// Radius used unless set otherwise, twice the default maximum depth.
+pub const DEFAULT_RADIUS: u8 = 30;
+
+/// Longest payload [`DataRequestExt`] carries in its own frame, longer ones
+/// are sent with [`DataStore`].
+pub const DATA_REQUEST_EXT_MAX: usize = MAX_DATA_LEN as usize - 20;
+
+/// Longest chunk of a single [`DataStore`].
+pub const DATA_STORE_MAX: usize = MAX_DATA_LEN as usize - 3;
+
+/// Fails if a request of `len` bytes does not fit a frame.
+pub(super) fn check_len(len: usize) -> Result<(), ser::Error> {
+    match len <= MAX_DATA_LEN as usize {
+        true => Ok(()),
+        false => Err(ser::Error::TooLong(len)),
+    }
+}
+
+/// Transmit options of a data request.
+#[enumflags2::bitflags]
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TransmitOptions {
+    WildcardProfileId = 0x02,
+    ApsPreprocess = 0x04,
+    LimitConcentrator = 0x08,
+    /// request an APS acknowledgement, confirmed once it arrives
+    AckRequest = 0x10,
+    SuppressRouteDiscNetwork = 0x20,
+    EnableSecurity = 0x40,
+    SkipRouting = 0x80,
+}
+
+/// Destination or source of an extended data frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Address {
+    Group(u16),
+    Nwk(u16),
+    /// little-endian, as sent over the air
+    Ieee([u8; 8]),
+    /// one of the broadcast addresses `0xFFFC..=0xFFFF`
+    Broadcast(u16),
+}
+
+impl Address {
+    pub fn mode(&self) -> u8 {
+        match self {
+            Self::Group(_) => 0x01,
+            Self::Nwk(_) => 0x02,
+            Self::Ieee(_) => 0x03,
+            Self::Broadcast(_) => 0x0F,
+        }
+    }
+
+    /// Address mode followed by the address, padded to 8 bytes.
+    pub(crate) fn encode(&self) -> [u8; 9] {
+        let mut ret = [u8::MIN; 9];
+        ret[0] = self.mode();
+        match self {
+            Self::Group(addr) | Self::Nwk(addr) | Self::Broadcast(addr) => {
+                ret[1..3].copy_from_slice(&addr.to_le_bytes())
+            }
+            Self::Ieee(addr) => ret[1..].copy_from_slice(addr),
+        }
+        ret
+    }
+
+    pub(crate) fn decode(mode: u8, addr: &[u8]) -> Result<Self, de::Error> {
+        let [lo, hi, ..] = *addr else {
+            return Err(de::Error::UnexpectedEOF);
+        };
+        let short = u16::from_le_bytes([lo, hi]);
+        match mode {
+            0x01 => Ok(Self::Group(short)),
+            0x02 => Ok(Self::Nwk(short)),
+            0x03 => Ok(Self::Ieee(
+                addr.try_into().map_err(|_| de::Error::UnexpectedEOF)?,
+            )),
+            0x0F => Ok(Self::Broadcast(short)),
+            _ => Err(de::Error::Unknown),
+        }
+    }
+}
+
+/// Sends application data to an endpoint of a device.
+/// See Z-stack Monitor and Test API, 3.2.1.2.
+#[derive(Command, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0x01)]
+pub struct DataRequest {
+    dst_addr: u16,
+    dst_endpoint: u8,
+    src_endpoint: u8,
+    cluster_id: u16,
+    trans_id: u8,
+    options: BitFlags<TransmitOptions>,
+    radius: u8,
+    data: Vec<u8>,
+}
+
+impl DataRequest {
+    /// Fails with [`ser::Error::TooLong`] if the payload does not fit a
+    /// frame, at most 240 bytes.
+    pub fn new(
+        dst_addr: u16,
+        dst_endpoint: u8,
+        src_endpoint: u8,
+        cluster_id: u16,
+        data: Vec<u8>,
+    ) -> Result<Self, ser::Error> {
+        check_len(10 + data.len())?;
+        let ret = Self {
+            dst_addr,
+            dst_endpoint,
+            src_endpoint,
+            cluster_id,
+            trans_id: 0,
+            options: BitFlags::empty(),
+            radius: DEFAULT_RADIUS,
+            data,
+        };
+        Ok(ret)
+    }
+
+    /// Transaction sequence number echoed by [`DataConfirm`].
+    pub fn trans_id(mut self, trans_id: u8) -> Self {
+        self.trans_id = trans_id;
+        self
+    }
+
+    pub fn options(mut self, options: BitFlags<TransmitOptions>) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn radius(mut self, radius: u8) -> Self {
+        self.radius = radius;
+        self
+    }
+}
+
+impl ser::Command for DataRequest {
+    const REQUEST_TYPE: CommandType = CommandType::SREQ;
+    fn len(&self) -> u8 { 10 + self.data.len() as u8 }
+    fn data(&self) -> Vec<u8> {
+        let mut ret = self.dst_addr.to_le_bytes().to_vec();
+        ret.extend([self.dst_endpoint, self.src_endpoint]);
+        ret.extend(self.cluster_id.to_le_bytes());
+        ret.extend([self.trans_id, self.options.bits(), self.radius]);
+        ret.push(self.data.len() as u8);
+        ret.extend(&self.data);
+        ret
+    }
+}
+
+impl de::Command for DataRequest {
+    const RESPONSE_TYPE: CommandType = CommandType::SRSP;
+    type Output = Status;
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        Status::from_data_frame(data_frame)
+    }
+}
+
+/// Sends application data to any kind of address, possibly on another PAN.
+///
+/// Payloads longer than [`DATA_REQUEST_EXT_MAX`] are left out of the
+/// request and must follow in [`DataStore`] chunks.
+/// See Z-stack Monitor and Test API, 3.2.1.3.
+#[derive(Command, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0x02)]
+pub struct DataRequestExt {
+    dst: Address,
+    dst_endpoint: u8,
+    dst_pan_id: u16,
+    src_endpoint: u8,
+    cluster_id: u16,
+    trans_id: u8,
+    options: BitFlags<TransmitOptions>,
+    radius: u8,
+    data: Vec<u8>,
+}
+
+impl DataRequestExt {
+    pub fn new(
+        dst: Address,
+        dst_endpoint: u8,
+        src_endpoint: u8,
+        cluster_id: u16,
+        data: Vec<u8>,
+    ) -> Self {
+        Self {
+            dst,
+            dst_endpoint,
+            dst_pan_id: 0,
+            src_endpoint,
+            cluster_id,
+            trans_id: 0,
+            options: BitFlags::empty(),
+            radius: DEFAULT_RADIUS,
+            data,
+        }
+    }
+
+    /// PAN of the destination, `0` for the local one.
+    pub fn dst_pan_id(mut self, dst_pan_id: u16) -> Self {
+        self.dst_pan_id = dst_pan_id;
+        self
+    }
+
+    /// Transaction sequence number echoed by [`DataConfirm`].
+    pub fn trans_id(mut self, trans_id: u8) -> Self {
+        self.trans_id = trans_id;
+        self
+    }
+
+    pub fn options(mut self, options: BitFlags<TransmitOptions>) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn radius(mut self, radius: u8) -> Self {
+        self.radius = radius;
+        self
+    }
+
+    pub fn payload(&self) -> &[u8] { &self.data }
+
+    /// Whether the payload has to be sent with [`DataStore`].
+    pub fn is_stored(&self) -> bool { self.data.len() > DATA_REQUEST_EXT_MAX }
+}
+
+impl ser::Command for DataRequestExt {
+    const REQUEST_TYPE: CommandType = CommandType::SREQ;
+    fn len(&self) -> u8 {
+        match self.is_stored() {
+            true => 20,
+            false => 20 + self.data.len() as u8,
+        }
+    }
+    fn data(&self) -> Vec<u8> {
+        let mut ret = self.dst.encode().to_vec();
+        ret.push(self.dst_endpoint);
+        ret.extend(self.dst_pan_id.to_le_bytes());
+        ret.push(self.src_endpoint);
+        ret.extend(self.cluster_id.to_le_bytes());
+        ret.extend([self.trans_id, self.options.bits(), self.radius]);
+        ret.extend((self.data.len() as u16).to_le_bytes());
+        if !self.is_stored() {
+            ret.extend(&self.data);
+        }
+        ret
+    }
+}
+
+impl de::Command for DataRequestExt {
+    const RESPONSE_TYPE: CommandType = CommandType::SRSP;
+    type Output = Status;
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        Status::from_data_frame(data_frame)
+    }
+}
+
+/// Sends application data along the given relays.
+/// See Z-stack Monitor and Test API, 3.2.1.4.
+#[derive(Command, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0x03)]
+pub struct DataRequestSrcRtg {
+    dst_addr: u16,
+    dst_endpoint: u8,
+    src_endpoint: u8,
+    cluster_id: u16,
+    trans_id: u8,
+    options: BitFlags<TransmitOptions>,
+    radius: u8,
+    /// short addresses of the relays, from the destination towards us
+    relays: Vec<u16>,
+    data: Vec<u8>,
+}
+
+impl DataRequestSrcRtg {
+    /// Fails with [`ser::Error::TooLong`] if the relays and the payload do
+    /// not fit a frame, at most 239 bytes of which two per relay.
+    pub fn new(
+        dst_addr: u16,
+        dst_endpoint: u8,
+        src_endpoint: u8,
+        cluster_id: u16,
+        relays: Vec<u16>,
+        data: Vec<u8>,
+    ) -> Result<Self, ser::Error> {
+        check_len(11 + 2 * relays.len() + data.len())?;
+        let ret = Self {
+            dst_addr,
+            dst_endpoint,
+            src_endpoint,
+            cluster_id,
+            trans_id: 0,
+            options: BitFlags::empty(),
+            radius: DEFAULT_RADIUS,
+            relays,
+            data,
+        };
+        Ok(ret)
+    }
+
+    /// Transaction sequence number echoed by [`DataConfirm`].
+    pub fn trans_id(mut self, trans_id: u8) -> Self {
+        self.trans_id = trans_id;
+        self
+    }
+
+    pub fn options(mut self, options: BitFlags<TransmitOptions>) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn radius(mut self, radius: u8) -> Self {
+        self.radius = radius;
+        self
+    }
+}
+
+impl ser::Command for DataRequestSrcRtg {
+    const REQUEST_TYPE: CommandType = CommandType::SREQ;
+    fn len(&self) -> u8 { 11 + 2 * self.relays.len() as u8 + self.data.len() as u8 }
+    fn data(&self) -> Vec<u8> {
+        let mut ret = self.dst_addr.to_le_bytes().to_vec();
+        ret.extend([self.dst_endpoint, self.src_endpoint]);
+        ret.extend(self.cluster_id.to_le_bytes());
+        ret.extend([self.trans_id, self.options.bits(), self.radius]);
+        ret.push(self.relays.len() as u8);
+        ret.extend(self.relays.iter().flat_map(|e| e.to_le_bytes()));
+        ret.push(self.data.len() as u8);
+        ret.extend(&self.data);
+        ret
+    }
+}
+
+impl de::Command for DataRequestSrcRtg {
+    const RESPONSE_TYPE: CommandType = CommandType::SRSP;
+    type Output = Status;
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        Status::from_data_frame(data_frame)
+    }
+}
+
+/// Stores a chunk of the payload of a [`DataRequestExt`] at `index`, an
+/// empty chunk sends the request.
+/// See Z-stack Monitor and Test API, 3.2.1.6.
+#[derive(Command, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0x11)]
+pub struct DataStore {
+    index: u16,
+    data: Vec<u8>,
+}
+
+impl DataStore {
+    /// Fails with [`ser::Error::TooLong`] if the chunk is longer than
+    /// [`DATA_STORE_MAX`].
+    pub fn new(index: u16, data: Vec<u8>) -> Result<Self, ser::Error> {
+        check_len(3 + data.len())?;
+        Ok(Self { index, data })
+    }
+}
+
+impl ser::Command for DataStore {
+    const REQUEST_TYPE: CommandType = CommandType::SREQ;
+    fn len(&self) -> u8 { 3 + self.data.len() as u8 }
+    fn data(&self) -> Vec<u8> {
+        let mut ret = self.index.to_le_bytes().to_vec();
+        ret.push(self.data.len() as u8);
+        ret.extend(&self.data);
+        ret
+    }
+}
+
+impl de::Command for DataStore {
+    const RESPONSE_TYPE: CommandType = CommandType::SRSP;
+    type Output = Status;
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        Status::from_data_frame(data_frame)
+    }
+}
+
+/// Reads a chunk of an incoming message too long for
+/// [`super::IncomingMsgExt`], an empty read frees it.
+/// See Z-stack Monitor and Test API, 3.2.1.7.
+#[derive(Command, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0x12)]
+pub struct DataRetrieve {
+    /// timestamp of the incoming message
+    timestamp: u32,
+    index: u16,
+    length: u8,
+}
+
+impl DataRetrieve {
+    pub fn new(timestamp: u32, index: u16, length: u8) -> Self {
+        Self {
+            timestamp,
+            index,
+            length,
+        }
+    }
+}
+
+impl ser::Command for DataRetrieve {
+    const REQUEST_TYPE: CommandType = CommandType::SREQ;
+    fn len(&self) -> u8 { 7 }
+    fn data(&self) -> Vec<u8> {
+        let mut ret = self.timestamp.to_le_bytes().to_vec();
+        ret.extend(self.index.to_le_bytes());
+        ret.push(self.length);
+        ret
+    }
+}
+
+impl de::Command for DataRetrieve {
+    const RESPONSE_TYPE: CommandType = CommandType::SRSP;
+    type Output = (Status, Vec<u8>);
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        let [status, len, data @ ..] = data_frame.as_slice() else {
+            return Err(de::Error::UnexpectedEOF);
+        };
+        if data.len() != *len as usize {
+            return Err(de::Error::UnexpectedEOF);
+        }
+        let status = Status::from_u8(*status).ok_or(de::Error::Unknown)?;
+        Ok((status, data.to_vec()))
+    }
+}
+
+/// Payload of `AF_DATA_CONFIRM`.
+#[derive(Debug, Clone)]
+pub struct DataConfirmation {
+    pub status: Status,
+    pub endpoint: u8,
+    pub trans_id: u8,
+}
+
+/// Sent once a data request was delivered, or failed.
+/// See Z-stack Monitor and Test API, 3.2.2.1.
+#[derive(Command, Default, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0x80)]
+pub struct DataConfirm {}
+
+impl de::Command for DataConfirm {
+    const RESPONSE_TYPE: CommandType = CommandType::AREQ;
+    type Output = DataConfirmation;
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        let [status, endpoint, trans_id] = data_frame.as_slice() else {
+            return Err(de::Error::UnexpectedEOF);
+        };
+        let ret = DataConfirmation {
+            status: Status::from_u8(*status).ok_or(de::Error::Unknown)?,
+            endpoint: *endpoint,
+            trans_id: *trans_id,
+        };
+        Ok(ret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DataRequest, DataRequestSrcRtg, DataStore, DATA_STORE_MAX};
+    use crate::command::ser::{self, Command};
+    use crate::packet::MAX_DATA_LEN;
+
+    #[test]
+    fn data_fits_frame() {
+        let command = DataRequest::new(0x1234, 1, 1, 0x0006, vec![0xAA; 240]).unwrap();
+        assert_eq!(command.len(), MAX_DATA_LEN);
+        assert_eq!(command.data().len(), MAX_DATA_LEN as usize);
+        let command =
+            DataRequestSrcRtg::new(0x1234, 1, 1, 0x0006, vec![1, 2], vec![0xAA; 235]).unwrap();
+        assert_eq!(command.len(), MAX_DATA_LEN);
+        assert_eq!(command.data().len(), MAX_DATA_LEN as usize);
+        let command = DataStore::new(0, vec![0xAA; DATA_STORE_MAX]).unwrap();
+        assert_eq!(command.len(), MAX_DATA_LEN);
+    }
+
+    #[test]
+    fn data_too_long() {
+        let ret = DataRequest::new(0x1234, 1, 1, 0x0006, vec![0xAA; 241]);
+        assert_eq!(ret.unwrap_err(), ser::Error::TooLong(251));
+        // fits without the relays
+        let ret = DataRequestSrcRtg::new(0x1234, 1, 1, 0x0006, vec![1, 2], vec![0xAA; 236]);
+        assert_eq!(ret.unwrap_err(), ser::Error::TooLong(251));
+        let ret = DataStore::new(0, vec![0xAA; DATA_STORE_MAX + 1]);
+        assert_eq!(ret.unwrap_err(), ser::Error::TooLong(251));
+    }
+}
@@ -0,0 +1,145 @@
+use crate::command::{de, Command, CommandID, CommandType};
+
+use znp_macros::Command;
+
+use super::{Address, SUBSYS};
+
+/// Application data received on a registered endpoint.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IncomingMessage {
+    pub group_id: u16,
+    pub cluster_id: u16,
+    pub src_addr: Address,
+    pub src_endpoint: u8,
+    /// only reported by [`IncomingMsgExt`]
+    pub src_pan_id: Option<u16>,
+    pub dst_endpoint: u8,
+    pub was_broadcast: bool,
+    pub link_quality: u8,
+    pub security_use: bool,
+    pub timestamp: u32,
+    pub trans_seq: u8,
+    /// length of the payload, longer than `data` if the payload has to be
+    /// read with [`super::DataRetrieve`]
+    pub len: u16,
+    pub data: Vec<u8>,
+}
+
+fn u16_at(data: &[u8], index: usize) -> u16 { u16::from_le_bytes([data[index], data[index + 1]]) }
+
+fn u32_at(data: &[u8], index: usize) -> u32 {
+    u32::from_le_bytes(data[index..index + 4].try_into().unwrap())
+}
+
+/// Application data sent to one of our endpoints from a short address.
+/// See Z-stack Monitor and Test API, 3.2.2.3.
+#[derive(Command, Default, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0x81)]
+pub struct IncomingMsg {}
+
+impl de::Command for IncomingMsg {
+    const RESPONSE_TYPE: CommandType = CommandType::AREQ;
+    type Output = IncomingMessage;
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        const HEADER: usize = 17;
+        if data_frame.len() < HEADER {
+            return Err(de::Error::UnexpectedEOF);
+        }
+        let len = data_frame[HEADER - 1] as usize;
+        // Z-Stack 3 appends the MAC source address and the radius
+        let Some(data) = data_frame.get(HEADER..HEADER + len) else {
+            return Err(de::Error::UnexpectedEOF);
+        };
+        let ret = IncomingMessage {
+            group_id: u16_at(&data_frame, 0),
+            cluster_id: u16_at(&data_frame, 2),
+            src_addr: Address::Nwk(u16_at(&data_frame, 4)),
+            src_endpoint: data_frame[6],
+            src_pan_id: None,
+            dst_endpoint: data_frame[7],
+            was_broadcast: data_frame[8] != 0,
+            link_quality: data_frame[9],
+            security_use: data_frame[10] != 0,
+            timestamp: u32_at(&data_frame, 11),
+            trans_seq: data_frame[15],
+            len: len as u16,
+            data: data.to_vec(),
+        };
+        Ok(ret)
+    }
+}
+
+/// Application data sent to one of our endpoints from any kind of address,
+/// possibly too long to fit in the frame.
+/// See Z-stack Monitor and Test API, 3.2.2.4.
+#[derive(Command, Default, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0x82)]
+pub struct IncomingMsgExt {}
+
+impl de::Command for IncomingMsgExt {
+    const RESPONSE_TYPE: CommandType = CommandType::AREQ;
+    type Output = IncomingMessage;
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        const HEADER: usize = 27;
+        if data_frame.len() < HEADER {
+            return Err(de::Error::UnexpectedEOF);
+        }
+        let len = u16_at(&data_frame, HEADER - 2);
+        // the payload is either in the frame or retrieved separately
+        let data = &data_frame[HEADER..];
+        if !data.is_empty() && data.len() != len as usize {
+            return Err(de::Error::UnexpectedEOF);
+        }
+        let ret = IncomingMessage {
+            group_id: u16_at(&data_frame, 0),
+            cluster_id: u16_at(&data_frame, 2),
+            src_addr: Address::decode(data_frame[4], &data_frame[5..13])?,
+            src_endpoint: data_frame[13],
+            src_pan_id: Some(u16_at(&data_frame, 14)),
+            dst_endpoint: data_frame[16],
+            was_broadcast: data_frame[17] != 0,
+            link_quality: data_frame[18],
+            security_use: data_frame[19] != 0,
+            timestamp: u32_at(&data_frame, 20),
+            trans_seq: data_frame[24],
+            len,
+            data: data.to_vec(),
+        };
+        Ok(ret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{IncomingMsg, IncomingMsgExt};
+    use crate::command::af::Address;
+    use crate::command::de::Command;
+
+    #[test]
+    fn incoming_msg() {
+        let mut frame = vec![
+            0x00, 0x00, 0x06, 0x00, 0x34, 0x12, 0x01, 0x01, 0x00, 0xC8, 0x00, 0x10, 0x00, 0x00,
+            0x00, 0x2A, 0x03, 0x18, 0x2A, 0x0B,
+        ];
+        // MAC source address and radius
+        frame.extend([0x34, 0x12, 0x1E]);
+        let msg = IncomingMsg::default().to_output(frame).unwrap();
+        assert_eq!(msg.cluster_id, 0x0006);
+        assert_eq!(msg.src_addr, Address::Nwk(0x1234));
+        assert_eq!(msg.timestamp, 0x10);
+        assert_eq!(msg.data, vec![0x18, 0x2A, 0x0B]);
+
+        let mut frame = vec![0x00, 0x00, 0x06, 0x00, 0x03];
+        frame.extend([0xA0, 0xA1, 0xA2, 0xA3, 0xA4, 0xA5, 0xA6, 0xA7]);
+        frame.extend([0x01, 0x62, 0x1A, 0x01, 0x00, 0xC8, 0x00]);
+        frame.extend([0x10, 0x00, 0x00, 0x00, 0x2A, 0x2C, 0x01]);
+        let msg = IncomingMsgExt::default().to_output(frame).unwrap();
+        assert_eq!(
+            msg.src_addr,
+            Address::Ieee([0xA0, 0xA1, 0xA2, 0xA3, 0xA4, 0xA5, 0xA6, 0xA7])
+        );
+        assert_eq!(msg.src_pan_id, Some(0x1A62));
+        assert_eq!(msg.len, 300);
+        assert!(msg.data.is_empty());
+    }
+}
@@ -0,0 +1,13 @@
+mod data;
+mod incoming;
+mod register;
+
+pub use data::{
+    Address, DataConfirm, DataConfirmation, DataRequest, DataRequestExt, DataRequestSrcRtg,
+    DataRetrieve, DataStore, TransmitOptions, DATA_REQUEST_EXT_MAX, DATA_STORE_MAX, DEFAULT_RADIUS,
+};
+pub use incoming::{IncomingMessage, IncomingMsg, IncomingMsgExt};
+pub use register::{Latency, Register};
+
+use crate::command::Subsystem;
+const SUBSYS: Subsystem = Subsystem::IFaceAF;
@@ -0,0 +1,126 @@
+use crate::command::{de, ser, Command, CommandID, CommandType, Status};
+
+use znp_macros::Command;
+
+use super::{data::check_len, SUBSYS};
+
+/// Latency required by an endpoint.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Latency {
+    NoLatency = 0x00,
+    FastBeacons = 0x01,
+    SlowBeacons = 0x02,
+}
+
+/// Registers an endpoint with its simple descriptor, needed before sending
+/// or receiving application data on it.
+/// See Z-stack Monitor and Test API, 3.2.1.1.
+#[derive(Command, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0x00)]
+pub struct Register {
+    endpoint: u8,
+    profile_id: u16,
+    device_id: u16,
+    device_version: u8,
+    latency: Latency,
+    in_clusters: Vec<u16>,
+    out_clusters: Vec<u16>,
+}
+
+impl Register {
+    pub fn new(endpoint: u8, profile_id: u16, device_id: u16) -> Self {
+        Self {
+            endpoint,
+            profile_id,
+            device_id,
+            device_version: 0,
+            latency: Latency::NoLatency,
+            in_clusters: vec![],
+            out_clusters: vec![],
+        }
+    }
+
+    pub fn device_version(mut self, device_version: u8) -> Self {
+        self.device_version = device_version;
+        self
+    }
+
+    pub fn latency(mut self, latency: Latency) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    /// Server clusters of the endpoint.
+    ///
+    /// Fails with [`ser::Error::TooLong`] if the clusters do not fit a
+    /// frame, at most 120 together with the client clusters.
+    pub fn in_clusters(mut self, in_clusters: Vec<u16>) -> Result<Self, ser::Error> {
+        self.in_clusters = in_clusters;
+        self.check_len()?;
+        Ok(self)
+    }
+
+    /// Client clusters of the endpoint.
+    ///
+    /// Fails with [`ser::Error::TooLong`] if the clusters do not fit a
+    /// frame, at most 120 together with the server clusters.
+    pub fn out_clusters(mut self, out_clusters: Vec<u16>) -> Result<Self, ser::Error> {
+        self.out_clusters = out_clusters;
+        self.check_len()?;
+        Ok(self)
+    }
+
+    fn check_len(&self) -> Result<(), ser::Error> {
+        check_len(9 + 2 * (self.in_clusters.len() + self.out_clusters.len()))
+    }
+}
+
+impl ser::Command for Register {
+    const REQUEST_TYPE: CommandType = CommandType::SREQ;
+    fn len(&self) -> u8 { 9 + 2 * (self.in_clusters.len() + self.out_clusters.len()) as u8 }
+    fn data(&self) -> Vec<u8> {
+        let mut ret = vec![self.endpoint];
+        ret.extend(self.profile_id.to_le_bytes());
+        ret.extend(self.device_id.to_le_bytes());
+        ret.extend([self.device_version, self.latency as u8]);
+        for clusters in [&self.in_clusters, &self.out_clusters] {
+            ret.push(clusters.len() as u8);
+            ret.extend(clusters.iter().flat_map(|e| e.to_le_bytes()));
+        }
+        ret
+    }
+}
+
+impl de::Command for Register {
+    const RESPONSE_TYPE: CommandType = CommandType::SRSP;
+    type Output = Status;
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        Status::from_data_frame(data_frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Register;
+    use crate::command::ser::{self, Command};
+    use crate::packet::MAX_DATA_LEN;
+
+    #[test]
+    fn clusters_fit_frame() {
+        let command = Register::new(1, 0x0104, 0x0005)
+            .in_clusters(vec![0x0006; 60])
+            .and_then(|e| e.out_clusters(vec![0x0008; 60]))
+            .unwrap();
+        assert_eq!(command.len(), MAX_DATA_LEN - 1);
+        assert_eq!(command.data().len(), MAX_DATA_LEN as usize - 1);
+    }
+
+    #[test]
+    fn clusters_too_long() {
+        let ret = Register::new(1, 0x0104, 0x0005)
+            .in_clusters(vec![0x0006; 60])
+            .and_then(|e| e.out_clusters(vec![0x0008; 61]));
+        assert_eq!(ret.unwrap_err(), ser::Error::TooLong(251));
+    }
+}
@@ -0,0 +1,231 @@
+use crate::command::{de, ser, Command, CommandID, CommandType, Status};
+
+use znp_macros::Command;
+
+use enumflags2::BitFlags;
+use num_traits::FromPrimitive;
+
+use super::SUBSYS;
+
+/// Base device behavior commissioning steps.
+#[enumflags2::bitflags]
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CommissioningMode {
+    InitiatorTouchLink = 0x01,
+    NwkSteering = 0x02,
+    NwkFormation = 0x04,
+    FindingBinding = 0x08,
+    TouchLink = 0x10,
+    ParentLost = 0x20,
+}
+
+#[derive(num_derive::FromPrimitive)]
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommissioningStatus {
+    Success = 0x00,
+    InProgress = 0x01,
+    NoNetwork = 0x02,
+    TlTargetFailure = 0x03,
+    TlNotAaCapable = 0x04,
+    TlNoScanResponse = 0x05,
+    TlNotPermitted = 0x06,
+    TclkExFailure = 0x07,
+    FormationFailure = 0x08,
+    FbTargetInProgress = 0x09,
+    FbInitiatorInProgress = 0x0A,
+    FbNoIdentifyQueryResponse = 0x0B,
+    FbBindingTableFull = 0x0C,
+    NetworkRestored = 0x0D,
+    Failure = 0x0E,
+}
+
+/// Starts the given commissioning steps, reported through
+/// [`BdbCommissioningNotification`].
+#[derive(Command, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0x05)]
+pub struct BdbStartCommissioning {
+    mode: BitFlags<CommissioningMode>,
+}
+
+impl BdbStartCommissioning {
+    pub fn new(mode: BitFlags<CommissioningMode>) -> Self { Self { mode } }
+}
+
+impl ser::Command for BdbStartCommissioning {
+    const REQUEST_TYPE: CommandType = CommandType::SREQ;
+    fn len(&self) -> u8 { 1 }
+    fn data(&self) -> Vec<u8> { vec![self.mode.bits()] }
+}
+
+impl de::Command for BdbStartCommissioning {
+    const RESPONSE_TYPE: CommandType = CommandType::SRSP;
+    type Output = Status;
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        Status::from_data_frame(data_frame)
+    }
+}
+
+/// Form of the code passed to [`BdbAddInstallCode`].
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InstallCodeFormat {
+    /// install code followed by its CRC
+    InstallCodeCrc = 0x01,
+    /// link key already derived from the install code
+    DerivedKey = 0x02,
+}
+
+/// Adds the preconfigured trust center link key of the device `ieee_addr`.
+#[derive(Command, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0x04)]
+pub struct BdbAddInstallCode {
+    format: InstallCodeFormat,
+    ieee_addr: [u8; 8],
+    code: Vec<u8>,
+}
+
+impl BdbAddInstallCode {
+    pub fn new(format: InstallCodeFormat, ieee_addr: [u8; 8], code: Vec<u8>) -> Self {
+        Self {
+            format,
+            ieee_addr,
+            code,
+        }
+    }
+}
+
+impl ser::Command for BdbAddInstallCode {
+    const REQUEST_TYPE: CommandType = CommandType::SREQ;
+    fn len(&self) -> u8 { 9 + self.code.len() as u8 }
+    fn data(&self) -> Vec<u8> {
+        let mut ret = vec![self.format as u8];
+        ret.extend(self.ieee_addr);
+        ret.extend(&self.code);
+        ret
+    }
+}
+
+impl de::Command for BdbAddInstallCode {
+    const RESPONSE_TYPE: CommandType = CommandType::SRSP;
+    type Output = Status;
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        Status::from_data_frame(data_frame)
+    }
+}
+
+/// Sets the primary or secondary channel mask used for commissioning.
+#[derive(Command, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0x08)]
+pub struct BdbSetChannel {
+    is_primary: bool,
+    channel_mask: u32,
+}
+
+impl BdbSetChannel {
+    pub fn new(is_primary: bool, channel_mask: u32) -> Self {
+        Self {
+            is_primary,
+            channel_mask,
+        }
+    }
+}
+
+impl ser::Command for BdbSetChannel {
+    const REQUEST_TYPE: CommandType = CommandType::SREQ;
+    fn len(&self) -> u8 { 5 }
+    fn data(&self) -> Vec<u8> {
+        let mut ret = vec![self.is_primary as u8];
+        ret.extend(self.channel_mask.to_le_bytes());
+        ret
+    }
+}
+
+impl de::Command for BdbSetChannel {
+    const RESPONSE_TYPE: CommandType = CommandType::SRSP;
+    type Output = Status;
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        Status::from_data_frame(data_frame)
+    }
+}
+
+/// Whether joining devices must replace the default trust center link key.
+#[derive(Command, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0x09)]
+pub struct BdbSetTcRequireKeyExchange {
+    required: bool,
+}
+
+impl BdbSetTcRequireKeyExchange {
+    pub fn new(required: bool) -> Self { Self { required } }
+}
+
+impl ser::Command for BdbSetTcRequireKeyExchange {
+    const REQUEST_TYPE: CommandType = CommandType::SREQ;
+    fn len(&self) -> u8 { 1 }
+    fn data(&self) -> Vec<u8> { vec![self.required as u8] }
+}
+
+impl de::Command for BdbSetTcRequireKeyExchange {
+    const RESPONSE_TYPE: CommandType = CommandType::SRSP;
+    type Output = Status;
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        Status::from_data_frame(data_frame)
+    }
+}
+
+/// Whether the trust center lets devices rejoin with their link key.
+#[derive(Command, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0x03)]
+pub struct SetAllowRejoinTcPolicy {
+    allow: bool,
+}
+
+impl SetAllowRejoinTcPolicy {
+    pub fn new(allow: bool) -> Self { Self { allow } }
+}
+
+impl ser::Command for SetAllowRejoinTcPolicy {
+    const REQUEST_TYPE: CommandType = CommandType::SREQ;
+    fn len(&self) -> u8 { 1 }
+    fn data(&self) -> Vec<u8> { vec![self.allow as u8] }
+}
+
+impl de::Command for SetAllowRejoinTcPolicy {
+    const RESPONSE_TYPE: CommandType = CommandType::SRSP;
+    type Output = Status;
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        Status::from_data_frame(data_frame)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CommissioningNotification {
+    pub status: CommissioningStatus,
+    /// step the notification is about
+    pub mode: BitFlags<CommissioningMode>,
+    /// steps left to run
+    pub remaining_modes: BitFlags<CommissioningMode>,
+}
+
+/// Progress of a [`BdbStartCommissioning`].
+#[derive(Command, Default, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0x80)]
+pub struct BdbCommissioningNotification {}
+
+impl de::Command for BdbCommissioningNotification {
+    const RESPONSE_TYPE: CommandType = CommandType::AREQ;
+    type Output = CommissioningNotification;
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        let [status, mode, remaining_modes] = data_frame.as_slice() else {
+            return Err(de::Error::UnexpectedEOF);
+        };
+        let ret = CommissioningNotification {
+            status: CommissioningStatus::from_u8(*status).ok_or(de::Error::Unknown)?,
+            mode: BitFlags::from_bits_truncate(*mode),
+            remaining_modes: BitFlags::from_bits_truncate(*remaining_modes),
+        };
+        Ok(ret)
+    }
+}
@@ -0,0 +1,10 @@
+mod bdb;
+
+pub use bdb::{
+    BdbAddInstallCode, BdbCommissioningNotification, BdbSetChannel, BdbSetTcRequireKeyExchange,
+    BdbStartCommissioning, CommissioningMode, CommissioningNotification, CommissioningStatus,
+    InstallCodeFormat, SetAllowRejoinTcPolicy,
+};
+
+use crate::command::Subsystem;
+const SUBSYS: Subsystem = Subsystem::ConfigAPP;
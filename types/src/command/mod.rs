@@ -1,7 +1,11 @@
+pub mod af;
+pub mod app_cnf;
 mod reserved;
+pub mod sbl;
 mod status;
 pub mod sys;
 pub mod util;
+pub mod zdo;
 
 use log::debug;
 pub use status::Status;
@@ -31,6 +35,9 @@ fn deserialize_bincode<D: bincode::de::Decode>(data_frame: Vec<u8>) -> Result<D,
     Ok(ret)
 }
 
+/// Mask of the command type bits in the first command byte.
+pub const COMMAND_TYPE_FLAG: u8 = 0b11100000u8;
+
 /// Type of command, 3 bits.
 /// See Z-stack Monitor and Test API, 2.1.2.
 #[repr(u8)]
@@ -61,6 +68,8 @@ pub enum Subsystem {
     IFaceUTIL = 0x07,
     IFaceDEBUG = 0x08,
     IFaceAPP = 0x09,
+    /// serial boot loader of CC2530 chips
+    SBL = 0x0D,
 
     ConfigAPP = 0x0F,
 
@@ -84,6 +93,12 @@ pub trait Command {
 pub mod ser {
     use crate::command::CommandType;
 
+    #[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Error {
+        #[error("{0} bytes of data do not fit a frame")]
+        TooLong(usize),
+    }
+
     pub trait Command: super::Command {
         const REQUEST_TYPE: CommandType;
         fn is_empty(&self) -> bool { self.len() < 1 }
@@ -104,8 +119,8 @@ pub mod ser {
 
 pub mod de {
     use crate::command::reserved::{CommandNotFound, ErrorCode};
-    use crate::command::CommandType;
-    use log::{debug, error};
+    use crate::command::{CommandType, COMMAND_TYPE_FLAG};
+    use log::debug;
 
     #[derive(thiserror::Error, Debug)]
     pub enum Error {
@@ -151,7 +166,6 @@ pub mod de {
                 data_frame.len()
             );
 
-            const COMMAND_TYPE_FLAG: u8 = 0b11100000u8;
             let command_type = cmd[0] & COMMAND_TYPE_FLAG;
             cmd[0] &= !COMMAND_TYPE_FLAG;
             if command_type == CommandNotFound::RESPONSE_TYPE as u8
@@ -2,9 +2,13 @@ use crate::command::{de, deserialize_bincode, Command, CommandID, CommandType};
 
 use znp_macros::Command;
 
+use num_traits::FromPrimitive;
+
 use super::SUBSYS;
 
-#[derive(bincode::Decode, Debug, Clone, Copy)]
+#[derive(num_derive::FromPrimitive)]
+#[repr(u8)]
+#[derive(Debug, Clone, Copy)]
 pub enum ErrorCode {
     Subsystem = 0x01,
     CommandID = 0x02,
@@ -22,10 +26,10 @@ impl de::Command for CommandNotFound {
     fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
         #[derive(bincode::Decode)]
         struct Rsp {
-            error_code: ErrorCode,
-            command_header: u16,
+            error_code: u8,
+            _command_header: u16,
         }
         let rsp: Rsp = deserialize_bincode(data_frame)?;
-        Ok(rsp.error_code)
+        ErrorCode::from_u8(rsp.error_code).ok_or(de::Error::Unknown)
     }
 }
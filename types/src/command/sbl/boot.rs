@@ -0,0 +1,177 @@
+//! Serial boot loader of CC2530 and CC2531 chips, framed like MT commands.
+//! Responses carry the id of their request with the high bit set.
+
+use crate::command::{de, ser, Command, CommandID, CommandType};
+
+use znp_macros::{Command, EmptyReq};
+
+use num_traits::FromPrimitive;
+
+use super::SUBSYS;
+
+/// Bytes read or written at once.
+pub const SB_BLOCK_LEN: usize = 64;
+
+#[derive(num_derive::FromPrimitive)]
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SbStatus {
+    Success = 0x00,
+    Failure = 0x01,
+    InvalidFcs = 0x02,
+    InvalidFile = 0x03,
+    FilesystemError = 0x04,
+    AlreadyStarted = 0x05,
+    NoResponse = 0x06,
+    ValidateFailed = 0x07,
+    Canceled = 0x08,
+}
+
+impl SbStatus {
+    fn from_data_frame(data_frame: &[u8]) -> Result<Self, de::Error> {
+        let status = *data_frame.first().ok_or(de::Error::UnexpectedEOF)?;
+        Self::from_u8(status).ok_or(de::Error::Unknown)
+    }
+}
+
+/// Checks the bootloader is listening, answered by [`SbHandshakeRsp`].
+#[derive(Command, EmptyReq, Default, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0x04)]
+#[req(kind = "CommandType::AREQ")]
+pub struct SbHandshake {}
+
+#[derive(Command, Default, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0x84)]
+pub struct SbHandshakeRsp {}
+
+impl de::Command for SbHandshakeRsp {
+    const RESPONSE_TYPE: CommandType = CommandType::AREQ;
+    type Output = SbStatus;
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        SbStatus::from_data_frame(&data_frame)
+    }
+}
+
+/// Writes a block of flash, answered by [`SbWriteRsp`]. The bootloader
+/// erases a page before writing its first block.
+#[derive(Command, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0x01)]
+pub struct SbWrite {
+    /// in 32-bit words
+    address: u16,
+    data: [u8; SB_BLOCK_LEN],
+}
+
+impl SbWrite {
+    /// `address` in bytes, a multiple of 4.
+    pub fn new(address: u32, data: [u8; SB_BLOCK_LEN]) -> Self {
+        Self {
+            address: (address / 4) as u16,
+            data,
+        }
+    }
+}
+
+impl ser::Command for SbWrite {
+    const REQUEST_TYPE: CommandType = CommandType::AREQ;
+    fn len(&self) -> u8 { 2 + SB_BLOCK_LEN as u8 }
+    fn data(&self) -> Vec<u8> {
+        let mut ret = self.address.to_le_bytes().to_vec();
+        ret.extend(self.data);
+        ret
+    }
+}
+
+#[derive(Command, Default, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0x81)]
+pub struct SbWriteRsp {}
+
+impl de::Command for SbWriteRsp {
+    const RESPONSE_TYPE: CommandType = CommandType::AREQ;
+    type Output = SbStatus;
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        SbStatus::from_data_frame(&data_frame)
+    }
+}
+
+/// Reads a block of flash, answered by [`SbReadRsp`].
+#[derive(Command, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0x02)]
+pub struct SbRead {
+    /// in 32-bit words
+    address: u16,
+}
+
+impl SbRead {
+    /// `address` in bytes, a multiple of 4.
+    pub fn new(address: u32) -> Self {
+        Self {
+            address: (address / 4) as u16,
+        }
+    }
+}
+
+impl ser::Command for SbRead {
+    const REQUEST_TYPE: CommandType = CommandType::AREQ;
+    fn len(&self) -> u8 { 2 }
+    fn data(&self) -> Vec<u8> { self.address.to_le_bytes().to_vec() }
+}
+
+/// Returns the status with the block read, empty on failure.
+#[derive(Command, Default, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0x82)]
+pub struct SbReadRsp {}
+
+impl de::Command for SbReadRsp {
+    const RESPONSE_TYPE: CommandType = CommandType::AREQ;
+    type Output = (SbStatus, Vec<u8>);
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        let status = SbStatus::from_data_frame(&data_frame)?;
+        match data_frame.len() {
+            1 => Ok((status, vec![])),
+            // status, address in words and the block
+            len if len == 3 + SB_BLOCK_LEN => Ok((status, data_frame[3..].to_vec())),
+            _ => Err(de::Error::Parse(data_frame)),
+        }
+    }
+}
+
+/// Validates the image and marks it bootable, answered by [`SbEnableRsp`].
+#[derive(Command, EmptyReq, Default, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0x03)]
+#[req(kind = "CommandType::AREQ")]
+pub struct SbEnable {}
+
+#[derive(Command, Default, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0x83)]
+pub struct SbEnableRsp {}
+
+impl de::Command for SbEnableRsp {
+    const RESPONSE_TYPE: CommandType = CommandType::AREQ;
+    type Output = SbStatus;
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        SbStatus::from_data_frame(&data_frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SbRead, SbReadRsp, SbStatus, SB_BLOCK_LEN};
+    use crate::command::de::Command as _;
+    use crate::command::ser::Command as _;
+
+    #[test]
+    fn read() {
+        assert_eq!(
+            SbRead::new(0x2000).serialize(),
+            [0x02, 0x4D, 0x02, 0x00, 0x08]
+        );
+        let mut frame = vec![0x00, 0x00, 0x08];
+        frame.extend([0xAB; SB_BLOCK_LEN]);
+        let (status, data) = SbReadRsp::default().to_output(frame).unwrap();
+        assert_eq!(status, SbStatus::Success);
+        assert_eq!(data, [0xAB; SB_BLOCK_LEN]);
+        let ret = SbReadRsp::default().to_output(vec![0x01]).unwrap();
+        assert_eq!(ret, (SbStatus::Failure, vec![]));
+    }
+}
@@ -0,0 +1,9 @@
+mod boot;
+
+pub use boot::{
+    SbEnable, SbEnableRsp, SbHandshake, SbHandshakeRsp, SbRead, SbReadRsp, SbStatus, SbWrite,
+    SbWriteRsp, SB_BLOCK_LEN,
+};
+
+use crate::command::Subsystem;
+const SUBSYS: Subsystem = Subsystem::SBL;
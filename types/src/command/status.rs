@@ -1,3 +1,7 @@
+use crate::command::de;
+
+use num_traits::FromPrimitive;
+
 #[derive(num_derive::FromPrimitive)]
 #[repr(u8)]
 #[derive(Debug, Clone, Copy)]
@@ -116,3 +120,13 @@ pub enum Status {
     MacAutoackPendingAllOn = 0xFE,
     MacAutoackPendingAllOff = 0xFF,
 }
+
+impl Status {
+    /// Decodes a response consisting of a single status byte.
+    pub(crate) fn from_data_frame(data_frame: Vec<u8>) -> Result<Self, de::Error> {
+        let [status] = data_frame.as_slice() else {
+            return Err(de::Error::UnexpectedEOF);
+        };
+        Status::from_u8(*status).ok_or(de::Error::Unknown)
+    }
+}
@@ -1,8 +1,16 @@
 mod nv;
 mod ping;
+mod reset;
+mod version;
 
-pub use nv::{ExNvIds, NVLength, NVRead, NvSysIds, NVID};
+pub use nv::{
+    ExNvIds, NVCompact, NVCreate, NVDelete, NVLength, NVRead, NVUpdate, NVWrite, NvSysIds,
+    OSALNVDelete, OSALNVItemInit, OSALNVLength, OSALNVRead, OSALNVReadExt, OSALNVWrite,
+    OSALNVWriteExt, NVID,
+};
 pub use ping::{Capability, Ping};
+pub use reset::{ResetInd, ResetInfo, ResetReason, ResetReq, ResetType};
+pub use version::{Product, SysVersion, VersionInfo};
 
 use crate::command::Subsystem;
 const SUBSYS: Subsystem = Subsystem::IFaceSYS;
@@ -8,9 +8,11 @@ use num_traits::FromPrimitive;
 
 use log::debug;
 
+use crate::packet::MAX_DATA_LEN;
+
 use super::SUBSYS;
 
-#[derive(bincode::Encode, Debug, Clone, Copy)]
+#[derive(bincode::Encode, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct NVID {
     sys_id: u8,
     item_id: u16,
@@ -18,13 +20,58 @@ pub struct NVID {
 }
 
 impl NVID {
-    pub fn new(sys_id: u8, item_id: u16, sub_id: u16) -> Self {
+    pub const fn new(sys_id: u8, item_id: u16, sub_id: u16) -> Self {
         Self {
             sys_id,
             item_id,
             sub_id,
         }
     }
+
+    /// Legacy OSAL item, exposed by Z-Stack 3.x.0 under [`ExNvIds::Legacy`].
+    pub const fn legacy(id: u16) -> Self {
+        Self::new(NvSysIds::ZStack as u8, ExNvIds::Legacy as u16, id)
+    }
+
+    pub fn sys_id(&self) -> u8 { self.sys_id }
+    pub fn item_id(&self) -> u16 { self.item_id }
+    pub fn sub_id(&self) -> u16 { self.sub_id }
+
+    /// OSAL id of a legacy item.
+    pub fn legacy_id(&self) -> Option<u16> {
+        let is_legacy =
+            self.sys_id == NvSysIds::ZStack as u8 && self.item_id == ExNvIds::Legacy as u16;
+        is_legacy.then_some(self.sub_id)
+    }
+}
+
+/// Fails if `value` does not fit a frame after a `header` bytes long prefix.
+fn check_len(header: u8, value: &[u8]) -> Result<(), ser::Error> {
+    match value.len() <= (MAX_DATA_LEN - header) as usize {
+        true => Ok(()),
+        false => Err(ser::Error::TooLong(value.len())),
+    }
+}
+
+/// Decodes a status followed by a length-prefixed value.
+fn deserialize_value(mut data_frame: Vec<u8>) -> Result<(Status, Vec<u8>), de::Error> {
+    if data_frame.len() < 2 {
+        return Err(de::Error::UnexpectedEOF);
+    }
+    let Some(status) = Status::from_u8(data_frame[0]) else {
+        return Err(de::Error::Unknown);
+    };
+    let len = data_frame[1] as usize;
+    let data_frame = data_frame.drain(2..).collect::<Vec<_>>();
+    if data_frame.len() != len {
+        debug!(
+            "nv read frame length mismatch, expected={:?}, actual={:?}",
+            len,
+            data_frame.len()
+        );
+        return Err(de::Error::UnexpectedEOF);
+    }
+    Ok((status, data_frame))
 }
 
 #[repr(u8)]
@@ -103,23 +150,446 @@ impl ser::Command for NVRead {
 impl de::Command for NVRead {
     const RESPONSE_TYPE: CommandType = CommandType::SRSP;
     type Output = (Status, Vec<u8>);
-    fn to_output(&self, mut data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
-        if data_frame.len() < 2 {
-            return Err(de::Error::UnexpectedEOF);
-        }
-        let Some(status) = Status::from_u8(data_frame[0]) else {
-            return Err(de::Error::Unknown);
-        };
-        let len = data_frame[1] as usize;
-        let data_frame = data_frame.drain(2..).collect::<Vec<_>>();
-        if data_frame.len() != len {
-            debug!(
-                "nv read frame length mismatch, expected={:?}, actual={:?}",
-                len,
-                data_frame.len()
-            );
-            return Err(de::Error::UnexpectedEOF);
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        deserialize_value(data_frame)
+    }
+}
+
+#[derive(Command, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0x34)]
+pub struct NVWrite {
+    id: NVID,
+    offset: u16,
+    value: Vec<u8>,
+}
+
+impl NVWrite {
+    /// Fails with [`ser::Error::TooLong`] if the value does not fit a frame,
+    /// at most 242 bytes.
+    pub fn new(id: NVID, offset: u16, value: Vec<u8>) -> Result<Self, ser::Error> {
+        check_len(8, &value)?;
+        Ok(Self { id, offset, value })
+    }
+}
+
+impl ser::Command for NVWrite {
+    const REQUEST_TYPE: CommandType = CommandType::SREQ;
+    fn len(&self) -> u8 { 8 + self.value.len() as u8 }
+    fn data(&self) -> Vec<u8> {
+        let mut ret = serialize_bincode((self.id, self.offset));
+        ret.push(self.value.len() as u8);
+        ret.extend(&self.value);
+        ret
+    }
+}
+
+impl de::Command for NVWrite {
+    const RESPONSE_TYPE: CommandType = CommandType::SRSP;
+    type Output = Status;
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        Status::from_data_frame(data_frame)
+    }
+}
+
+#[derive(Command, bincode::Encode, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0x30)]
+pub struct NVCreate {
+    id: NVID,
+    length: u32,
+}
+
+impl NVCreate {
+    pub fn new(id: NVID, length: u32) -> Self { Self { id, length } }
+}
+
+impl ser::Command for NVCreate {
+    const REQUEST_TYPE: CommandType = CommandType::SREQ;
+    fn len(&self) -> u8 { 9 }
+    fn data(&self) -> Vec<u8> { serialize_bincode(self) }
+}
+
+impl de::Command for NVCreate {
+    const RESPONSE_TYPE: CommandType = CommandType::SRSP;
+    /// either `NvItemUninit` or `Success` when the item exists afterwards
+    type Output = Status;
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        Status::from_data_frame(data_frame)
+    }
+}
+
+#[derive(Command, bincode::Encode, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0x31)]
+pub struct NVDelete {
+    id: NVID,
+}
+
+impl NVDelete {
+    pub fn new(id: NVID) -> Self { Self { id } }
+}
+
+impl ser::Command for NVDelete {
+    const REQUEST_TYPE: CommandType = CommandType::SREQ;
+    fn len(&self) -> u8 { 5 }
+    fn data(&self) -> Vec<u8> { serialize_bincode(self) }
+}
+
+impl de::Command for NVDelete {
+    const RESPONSE_TYPE: CommandType = CommandType::SRSP;
+    type Output = Status;
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        Status::from_data_frame(data_frame)
+    }
+}
+
+/// Replaces the whole item, creating it if needed.
+#[derive(Command, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0x35)]
+pub struct NVUpdate {
+    id: NVID,
+    value: Vec<u8>,
+}
+
+impl NVUpdate {
+    /// Fails with [`ser::Error::TooLong`] if the value does not fit a frame,
+    /// at most 244 bytes.
+    pub fn new(id: NVID, value: Vec<u8>) -> Result<Self, ser::Error> {
+        check_len(6, &value)?;
+        Ok(Self { id, value })
+    }
+}
+
+impl ser::Command for NVUpdate {
+    const REQUEST_TYPE: CommandType = CommandType::SREQ;
+    fn len(&self) -> u8 { 6 + self.value.len() as u8 }
+    fn data(&self) -> Vec<u8> {
+        let mut ret = serialize_bincode(self.id);
+        ret.push(self.value.len() as u8);
+        ret.extend(&self.value);
+        ret
+    }
+}
+
+impl de::Command for NVUpdate {
+    const RESPONSE_TYPE: CommandType = CommandType::SRSP;
+    type Output = Status;
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        Status::from_data_frame(data_frame)
+    }
+}
+
+#[derive(Command, bincode::Encode, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0x36)]
+pub struct NVCompact {
+    /// compaction occurs when fewer bytes than this are free
+    threshold: u16,
+}
+
+impl NVCompact {
+    pub fn new(threshold: u16) -> Self { Self { threshold } }
+}
+
+impl ser::Command for NVCompact {
+    const REQUEST_TYPE: CommandType = CommandType::SREQ;
+    fn len(&self) -> u8 { 2 }
+    fn data(&self) -> Vec<u8> { serialize_bincode(self) }
+}
+
+impl de::Command for NVCompact {
+    const RESPONSE_TYPE: CommandType = CommandType::SRSP;
+    type Output = Status;
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        Status::from_data_frame(data_frame)
+    }
+}
+
+/// See Z-stack Monitor and Test API, 3.8.1.10.
+#[derive(Command, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0x07)]
+pub struct OSALNVItemInit {
+    id: u16,
+    item_len: u16,
+    init_data: Vec<u8>,
+}
+
+impl OSALNVItemInit {
+    /// Fails with [`ser::Error::TooLong`] if the value does not fit a frame,
+    /// at most 245 bytes.
+    pub fn new(id: u16, item_len: u16, init_data: Vec<u8>) -> Result<Self, ser::Error> {
+        check_len(5, &init_data)?;
+        Ok(Self {
+            id,
+            item_len,
+            init_data,
+        })
+    }
+}
+
+impl ser::Command for OSALNVItemInit {
+    const REQUEST_TYPE: CommandType = CommandType::SREQ;
+    fn len(&self) -> u8 { 5 + self.init_data.len() as u8 }
+    fn data(&self) -> Vec<u8> {
+        let mut ret = serialize_bincode((self.id, self.item_len));
+        ret.push(self.init_data.len() as u8);
+        ret.extend(&self.init_data);
+        ret
+    }
+}
+
+impl de::Command for OSALNVItemInit {
+    const RESPONSE_TYPE: CommandType = CommandType::SRSP;
+    /// `NvItemUninit` if the item was created, `Success` if it already existed
+    type Output = Status;
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        Status::from_data_frame(data_frame)
+    }
+}
+
+/// See Z-stack Monitor and Test API, 3.8.1.8.
+#[derive(Command, bincode::Encode, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0x08)]
+pub struct OSALNVRead {
+    id: u16,
+    offset: u8,
+}
+
+impl OSALNVRead {
+    pub fn new(id: u16, offset: u8) -> Self { Self { id, offset } }
+}
+
+impl ser::Command for OSALNVRead {
+    const REQUEST_TYPE: CommandType = CommandType::SREQ;
+    fn len(&self) -> u8 { 3 }
+    fn data(&self) -> Vec<u8> { serialize_bincode(self) }
+}
+
+impl de::Command for OSALNVRead {
+    const RESPONSE_TYPE: CommandType = CommandType::SRSP;
+    type Output = (Status, Vec<u8>);
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        deserialize_value(data_frame)
+    }
+}
+
+/// See Z-stack Monitor and Test API, 3.8.1.9.
+#[derive(Command, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0x09)]
+pub struct OSALNVWrite {
+    id: u16,
+    offset: u8,
+    value: Vec<u8>,
+}
+
+impl OSALNVWrite {
+    /// Fails with [`ser::Error::TooLong`] if the value does not fit a frame,
+    /// at most 246 bytes.
+    pub fn new(id: u16, offset: u8, value: Vec<u8>) -> Result<Self, ser::Error> {
+        check_len(4, &value)?;
+        Ok(Self { id, offset, value })
+    }
+}
+
+impl ser::Command for OSALNVWrite {
+    const REQUEST_TYPE: CommandType = CommandType::SREQ;
+    fn len(&self) -> u8 { 4 + self.value.len() as u8 }
+    fn data(&self) -> Vec<u8> {
+        let mut ret = serialize_bincode((self.id, self.offset));
+        ret.push(self.value.len() as u8);
+        ret.extend(&self.value);
+        ret
+    }
+}
+
+impl de::Command for OSALNVWrite {
+    const RESPONSE_TYPE: CommandType = CommandType::SRSP;
+    type Output = Status;
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        Status::from_data_frame(data_frame)
+    }
+}
+
+/// Like [`OSALNVRead`] with a 16-bit offset, not available on Z-Stack 1.2.
+/// See Z-stack Monitor and Test API, 3.8.1.34.
+#[derive(Command, bincode::Encode, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0x1C)]
+pub struct OSALNVReadExt {
+    id: u16,
+    offset: u16,
+}
+
+impl OSALNVReadExt {
+    pub fn new(id: u16, offset: u16) -> Self { Self { id, offset } }
+}
+
+impl ser::Command for OSALNVReadExt {
+    const REQUEST_TYPE: CommandType = CommandType::SREQ;
+    fn len(&self) -> u8 { 4 }
+    fn data(&self) -> Vec<u8> { serialize_bincode(self) }
+}
+
+impl de::Command for OSALNVReadExt {
+    const RESPONSE_TYPE: CommandType = CommandType::SRSP;
+    type Output = (Status, Vec<u8>);
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        deserialize_value(data_frame)
+    }
+}
+
+/// Like [`OSALNVWrite`] with a 16-bit offset, not available on Z-Stack 1.2.
+/// See Z-stack Monitor and Test API, 3.8.1.35.
+#[derive(Command, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0x1D)]
+pub struct OSALNVWriteExt {
+    id: u16,
+    offset: u16,
+    value: Vec<u8>,
+}
+
+impl OSALNVWriteExt {
+    /// Fails with [`ser::Error::TooLong`] if the value does not fit a frame,
+    /// at most 244 bytes.
+    pub fn new(id: u16, offset: u16, value: Vec<u8>) -> Result<Self, ser::Error> {
+        check_len(6, &value)?;
+        Ok(Self { id, offset, value })
+    }
+}
+
+impl ser::Command for OSALNVWriteExt {
+    const REQUEST_TYPE: CommandType = CommandType::SREQ;
+    fn len(&self) -> u8 { 6 + self.value.len() as u8 }
+    fn data(&self) -> Vec<u8> {
+        let mut ret = serialize_bincode((self.id, self.offset));
+        ret.extend((self.value.len() as u16).to_le_bytes());
+        ret.extend(&self.value);
+        ret
+    }
+}
+
+impl de::Command for OSALNVWriteExt {
+    const RESPONSE_TYPE: CommandType = CommandType::SRSP;
+    type Output = Status;
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        Status::from_data_frame(data_frame)
+    }
+}
+
+/// See Z-stack Monitor and Test API, 3.8.1.12.
+#[derive(Command, bincode::Encode, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0x13)]
+pub struct OSALNVLength {
+    id: u16,
+}
+
+impl OSALNVLength {
+    pub fn new(id: u16) -> Self { Self { id } }
+}
+
+impl ser::Command for OSALNVLength {
+    const REQUEST_TYPE: CommandType = CommandType::SREQ;
+    fn len(&self) -> u8 { 2 }
+    fn data(&self) -> Vec<u8> { serialize_bincode(self) }
+}
+
+impl de::Command for OSALNVLength {
+    const RESPONSE_TYPE: CommandType = CommandType::SRSP;
+    /// 0 if the item does not exist
+    type Output = u16;
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        #[derive(bincode::Decode)]
+        struct Rsp {
+            length: u16,
         }
-        Ok((status, data_frame))
+        let rsp: Rsp = deserialize_bincode(data_frame)?;
+        Ok(rsp.length)
+    }
+}
+
+/// See Z-stack Monitor and Test API, 3.8.1.11.
+#[derive(Command, bincode::Encode, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0x12)]
+pub struct OSALNVDelete {
+    id: u16,
+    /// must match the length of the item
+    item_len: u16,
+}
+
+impl OSALNVDelete {
+    pub fn new(id: u16, item_len: u16) -> Self { Self { id, item_len } }
+}
+
+impl ser::Command for OSALNVDelete {
+    const REQUEST_TYPE: CommandType = CommandType::SREQ;
+    fn len(&self) -> u8 { 4 }
+    fn data(&self) -> Vec<u8> { serialize_bincode(self) }
+}
+
+impl de::Command for OSALNVDelete {
+    const RESPONSE_TYPE: CommandType = CommandType::SRSP;
+    /// `Success` if deleted, `NvItemUninit` if the item did not exist
+    type Output = Status;
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        Status::from_data_frame(data_frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::command::ser::{self, Command};
+    use crate::command::sys::{
+        ExNvIds, NVUpdate, NVWrite, NvSysIds, OSALNVItemInit, OSALNVWrite, OSALNVWriteExt, NVID,
+    };
+    use crate::packet::MAX_DATA_LEN;
+
+    #[test]
+    fn nv_write_request() {
+        let id = NVID::new(NvSysIds::ZStack as u8, ExNvIds::TClkTable as u16, 0x0102);
+        let command = NVWrite::new(id, 0x0010, vec![0xAA, 0xBB]).unwrap();
+        let expected = vec![
+            0x0A, 0x21, 0x34, 0x01, 0x04, 0x00, 0x02, 0x01, 0x10, 0x00, 0x02, 0xAA, 0xBB,
+        ];
+        assert_eq!(command.serialize(), expected);
+    }
+
+    #[test]
+    fn osal_nv_write_ext_request() {
+        let command = OSALNVWriteExt::new(0x0062, 0x0100, vec![0xAA]).unwrap();
+        let expected = vec![0x07, 0x21, 0x1D, 0x62, 0x00, 0x00, 0x01, 0x01, 0x00, 0xAA];
+        assert_eq!(command.serialize(), expected);
+    }
+
+    #[test]
+    fn value_fits_frame() {
+        let id = NVID::new(NvSysIds::ZStack as u8, ExNvIds::TClkTable as u16, 0);
+        let command = NVWrite::new(id, 0, vec![0xAA; 242]).unwrap();
+        assert_eq!(command.len(), MAX_DATA_LEN);
+        assert_eq!(command.data().len(), MAX_DATA_LEN as usize);
+        assert_eq!(
+            NVUpdate::new(id, vec![0xAA; 244]).unwrap().len(),
+            MAX_DATA_LEN
+        );
+        assert_eq!(
+            OSALNVItemInit::new(1, 245, vec![0xAA; 245]).unwrap().len(),
+            MAX_DATA_LEN
+        );
+        assert_eq!(
+            OSALNVWrite::new(1, 0, vec![0xAA; 246]).unwrap().len(),
+            MAX_DATA_LEN
+        );
+        assert_eq!(
+            OSALNVWriteExt::new(1, 0, vec![0xAA; 244]).unwrap().len(),
+            MAX_DATA_LEN
+        );
+    }
+
+    #[test]
+    fn value_too_long() {
+        let id = NVID::new(NvSysIds::ZStack as u8, ExNvIds::TClkTable as u16, 0);
+        assert_eq!(
+            NVWrite::new(id, 0, vec![0xAA; 243]).unwrap_err(),
+            ser::Error::TooLong(243)
+        );
+        assert_eq!(
+            OSALNVWrite::new(1, 0, vec![0xAA; 256]).unwrap_err(),
+            ser::Error::TooLong(256)
+        );
     }
 }
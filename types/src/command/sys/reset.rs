@@ -0,0 +1,83 @@
+use crate::command::{de, ser, Command, CommandID, CommandType};
+
+use znp_macros::Command;
+
+use num_traits::FromPrimitive;
+
+use super::SUBSYS;
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResetType {
+    /// resets the chip, USB devices re-enumerate
+    Hard = 0x00,
+    /// restarts the stack only
+    Soft = 0x01,
+}
+
+/// Resets the device, which then sends `SYS_RESET_IND`.
+/// See Z-stack Monitor and Test API, 3.8.1.1.
+#[derive(Command, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0x00)]
+pub struct ResetReq {
+    kind: ResetType,
+}
+
+impl ResetReq {
+    pub fn new(kind: ResetType) -> Self { Self { kind } }
+}
+
+impl ser::Command for ResetReq {
+    const REQUEST_TYPE: CommandType = CommandType::AREQ;
+    fn len(&self) -> u8 { 1 }
+    fn data(&self) -> Vec<u8> { vec![self.kind as u8] }
+}
+
+/// Cause of the last reset.
+#[derive(num_derive::FromPrimitive)]
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResetReason {
+    PowerUp = 0x00,
+    /// reset pin or `SYS_RESET_REQ`
+    External = 0x01,
+    WatchDog = 0x02,
+}
+
+/// Payload of `SYS_RESET_IND`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResetInfo {
+    pub reason: ResetReason,
+    pub transport_rev: u8,
+    pub product_id: u8,
+    pub major_rel: u8,
+    pub minor_rel: u8,
+    pub hw_rev: u8,
+}
+
+/// Sent by the device once it is up again after a reset.
+/// See Z-stack Monitor and Test API, 3.8.2.1.
+#[derive(Command, Default, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0x80)]
+pub struct ResetInd {}
+
+impl de::Command for ResetInd {
+    const RESPONSE_TYPE: CommandType = CommandType::AREQ;
+    type Output = ResetInfo;
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        let [reason, transport_rev, product_id, major_rel, minor_rel, hw_rev] =
+            data_frame.as_slice()
+        else {
+            return Err(de::Error::UnexpectedEOF);
+        };
+        let ret = ResetInfo {
+            reason: ResetReason::from_u8(*reason).ok_or(de::Error::Unknown)?,
+            transport_rev: *transport_rev,
+            product_id: *product_id,
+            major_rel: *major_rel,
+            minor_rel: *minor_rel,
+            hw_rev: *hw_rev,
+        };
+        Ok(ret)
+    }
+}
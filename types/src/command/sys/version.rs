@@ -0,0 +1,90 @@
+use crate::command::{de, ser, Command, CommandID, CommandType};
+
+use znp_macros::{Command, EmptyReq};
+
+use num_traits::FromPrimitive;
+
+use super::SUBSYS;
+
+/// Z-Stack generation the firmware is built from.
+#[derive(num_derive::FromPrimitive)]
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Product {
+    /// Z-Stack Home 1.2
+    #[default]
+    ZStack12 = 0x00,
+    /// Z-Stack 3.x.0, with extended NV
+    ZStack3x0 = 0x01,
+    /// Z-Stack 3.0.x
+    ZStack30 = 0x02,
+}
+
+/// Firmware version reported by `SYS_VERSION`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct VersionInfo {
+    pub transport_rev: u8,
+    pub product: Product,
+    pub major_rel: u8,
+    pub minor_rel: u8,
+    pub maint_rel: u8,
+    /// build date code such as `20210708`, missing on older firmware
+    pub revision: Option<u32>,
+}
+
+/// See Z-stack Monitor and Test API, 3.8.1.3.
+#[derive(Command, EmptyReq, Default, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0x02)]
+#[req(kind = "CommandType::SREQ")]
+pub struct SysVersion {}
+
+impl de::Command for SysVersion {
+    const RESPONSE_TYPE: CommandType = CommandType::SRSP;
+    type Output = VersionInfo;
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        let (head, revision) = match data_frame.len() {
+            5 => (data_frame.as_slice(), None),
+            9 => {
+                let (head, tail) = data_frame.split_at(5);
+                (head, Some(u32::from_le_bytes(tail.try_into().unwrap())))
+            }
+            _ => return Err(de::Error::UnexpectedEOF),
+        };
+        let ret = VersionInfo {
+            transport_rev: head[0],
+            product: Product::from_u8(head[1]).ok_or(de::Error::Unknown)?,
+            major_rel: head[2],
+            minor_rel: head[3],
+            maint_rel: head[4],
+            revision,
+        };
+        Ok(ret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Product, SysVersion};
+    use crate::command::de::Command;
+
+    #[test]
+    fn sys_version() {
+        let ret = SysVersion::default()
+            .to_output(vec![0x02, 0x01, 0x02, 0x07, 0x01, 0xAC, 0x63, 0x34, 0x01])
+            .unwrap();
+        assert_eq!(ret.product, Product::ZStack3x0);
+        assert_eq!(ret.revision, Some(20210604));
+
+        let ret = SysVersion::default()
+            .to_output(vec![0x02, 0x02, 0x02, 0x07, 0x01, 0xD9, 0x14, 0x34, 0x01])
+            .unwrap();
+        assert_eq!(ret.product, Product::ZStack30);
+        assert_eq!(ret.revision, Some(20190425));
+
+        let ret = SysVersion::default()
+            .to_output(vec![0x02, 0x00, 0x02, 0x06, 0x03])
+            .unwrap();
+        assert_eq!(ret.product, Product::ZStack12);
+        assert_eq!(ret.revision, None);
+    }
+}
@@ -0,0 +1,63 @@
+use crate::command::zdo::DeviceState;
+use crate::command::{de, ser, Command, CommandID, CommandType, Status};
+
+use znp_macros::{Command, EmptyReq};
+
+use enumflags2::BitFlags;
+use num_traits::FromPrimitive;
+
+use super::SUBSYS;
+
+/// Logical types the firmware is able to run as.
+#[enumflags2::bitflags]
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DeviceType {
+    Coordinator = 0x01,
+    Router = 0x02,
+    EndDevice = 0x04,
+}
+
+/// Response of [`GetDeviceInfo`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceInfo {
+    /// little-endian, as sent over the air
+    pub ieee_addr: [u8; 8],
+    pub short_addr: u16,
+    pub device_type: BitFlags<DeviceType>,
+    pub device_state: DeviceState,
+    /// short addresses of the associated devices
+    pub assoc_devices: Vec<u16>,
+}
+
+/// See Z-stack Monitor and Test API, 3.10.1.1.
+#[derive(Command, EmptyReq, Default, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0x00)]
+#[req(kind = "CommandType::SREQ")]
+pub struct GetDeviceInfo {}
+
+impl de::Command for GetDeviceInfo {
+    const RESPONSE_TYPE: CommandType = CommandType::SRSP;
+    type Output = (Status, DeviceInfo);
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        if data_frame.len() < 14 {
+            return Err(de::Error::UnexpectedEOF);
+        }
+        let (head, assoc) = data_frame.split_at(14);
+        if assoc.len() != head[13] as usize * 2 {
+            return Err(de::Error::UnexpectedEOF);
+        }
+        let status = Status::from_u8(head[0]).ok_or(de::Error::Unknown)?;
+        let ret = DeviceInfo {
+            ieee_addr: head[1..9].try_into().unwrap(),
+            short_addr: u16::from_le_bytes([head[9], head[10]]),
+            device_type: BitFlags::from_bits_truncate(head[11]),
+            device_state: DeviceState::from_u8(head[12]).ok_or(de::Error::Unknown)?,
+            assoc_devices: assoc
+                .chunks(2)
+                .map(|e| u16::from_le_bytes([e[0], e[1]]))
+                .collect(),
+        };
+        Ok((status, ret))
+    }
+}
@@ -1,6 +1,8 @@
 mod assoc_find_device;
+mod device_info;
 
 pub use assoc_find_device::AssocFindDevice;
+pub use device_info::{DeviceInfo, DeviceType, GetDeviceInfo};
 
 use crate::command::Subsystem;
 const SUBSYS: Subsystem = Subsystem::IFaceUTIL;
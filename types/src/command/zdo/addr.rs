@@ -0,0 +1,183 @@
+use crate::command::{de, ser, Command, CommandID, CommandType, Status};
+use crate::nv::Reader;
+
+use znp_macros::Command;
+
+use super::{ZdpStatus, SUBSYS};
+
+/// Whether address responses list the associated devices too.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddrReqType {
+    Single = 0x00,
+    Extended = 0x01,
+}
+
+/// Asks for the short address of the device with the given IEEE address,
+/// answered by [`NwkAddrRsp`].
+/// See Z-stack Monitor and Test API, 3.12.1.1.
+#[derive(Command, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0x00)]
+pub struct NwkAddrReq {
+    ieee_addr: [u8; 8],
+    req_type: AddrReqType,
+    start_index: u8,
+}
+
+impl NwkAddrReq {
+    pub fn new(ieee_addr: [u8; 8], req_type: AddrReqType, start_index: u8) -> Self {
+        Self {
+            ieee_addr,
+            req_type,
+            start_index,
+        }
+    }
+}
+
+impl ser::Command for NwkAddrReq {
+    const REQUEST_TYPE: CommandType = CommandType::SREQ;
+    fn len(&self) -> u8 { 10 }
+    fn data(&self) -> Vec<u8> {
+        let mut ret = self.ieee_addr.to_vec();
+        ret.extend([self.req_type as u8, self.start_index]);
+        ret
+    }
+}
+
+impl de::Command for NwkAddrReq {
+    const RESPONSE_TYPE: CommandType = CommandType::SRSP;
+    type Output = Status;
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        Status::from_data_frame(data_frame)
+    }
+}
+
+/// Asks a device for its IEEE address, answered by [`IeeeAddrRsp`].
+/// See Z-stack Monitor and Test API, 3.12.1.2.
+#[derive(Command, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0x01)]
+pub struct IeeeAddrReq {
+    nwk_addr: u16,
+    req_type: AddrReqType,
+    start_index: u8,
+}
+
+impl IeeeAddrReq {
+    pub fn new(nwk_addr: u16, req_type: AddrReqType, start_index: u8) -> Self {
+        Self {
+            nwk_addr,
+            req_type,
+            start_index,
+        }
+    }
+}
+
+impl ser::Command for IeeeAddrReq {
+    const REQUEST_TYPE: CommandType = CommandType::SREQ;
+    fn len(&self) -> u8 { 4 }
+    fn data(&self) -> Vec<u8> {
+        let mut ret = self.nwk_addr.to_le_bytes().to_vec();
+        ret.extend([self.req_type as u8, self.start_index]);
+        ret
+    }
+}
+
+impl de::Command for IeeeAddrReq {
+    const RESPONSE_TYPE: CommandType = CommandType::SRSP;
+    type Output = Status;
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        Status::from_data_frame(data_frame)
+    }
+}
+
+/// Payload of [`NwkAddrRsp`] and [`IeeeAddrRsp`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AddrResponse {
+    pub status: ZdpStatus,
+    pub ieee_addr: [u8; 8],
+    pub nwk_addr: u16,
+    pub start_index: u8,
+    /// short addresses of associated devices, from `start_index` on
+    pub assoc_devices: Vec<u16>,
+}
+
+fn decode_addr_rsp(data_frame: &[u8]) -> Result<AddrResponse, de::Error> {
+    let mut reader = Reader::new(data_frame, false);
+    let status = ZdpStatus::from_byte(reader.u8()?)?;
+    let ieee_addr = reader.bytes()?;
+    let nwk_addr = reader.u16()?;
+    // single requests leave out the associated devices entirely
+    let (start_index, count) = match reader.is_empty() {
+        true => (0, 0),
+        false => (reader.u8()?, reader.u8()?),
+    };
+    let assoc_devices = (0..count).map(|_| reader.u16()).collect::<Result<_, _>>()?;
+    reader.finish()?;
+    let ret = AddrResponse {
+        status,
+        ieee_addr,
+        nwk_addr,
+        start_index,
+        assoc_devices,
+    };
+    Ok(ret)
+}
+
+/// See Z-stack Monitor and Test API, 3.12.2.1.
+#[derive(Command, Default, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0x80)]
+pub struct NwkAddrRsp {}
+
+impl de::Command for NwkAddrRsp {
+    const RESPONSE_TYPE: CommandType = CommandType::AREQ;
+    type Output = AddrResponse;
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        decode_addr_rsp(&data_frame)
+    }
+}
+
+/// See Z-stack Monitor and Test API, 3.12.2.2.
+#[derive(Command, Default, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0x81)]
+pub struct IeeeAddrRsp {}
+
+impl de::Command for IeeeAddrRsp {
+    const RESPONSE_TYPE: CommandType = CommandType::AREQ;
+    type Output = AddrResponse;
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        decode_addr_rsp(&data_frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{IeeeAddrRsp, NwkAddrRsp};
+    use crate::command::de::Command;
+    use crate::command::zdo::ZdpStatus;
+
+    #[test]
+    fn addr_rsp() {
+        let mut frame = vec![0x00];
+        frame.extend([0xA0, 0xA1, 0xA2, 0xA3, 0xA4, 0xA5, 0xA6, 0xA7]);
+        frame.extend([0x34, 0x12]);
+        let rsp = NwkAddrRsp::default().to_output(frame.clone()).unwrap();
+        assert_eq!(rsp.status, ZdpStatus::Success);
+        assert_eq!(
+            rsp.ieee_addr,
+            [0xA0, 0xA1, 0xA2, 0xA3, 0xA4, 0xA5, 0xA6, 0xA7]
+        );
+        assert_eq!(rsp.nwk_addr, 0x1234);
+        assert_eq!(rsp.start_index, 0);
+        assert!(rsp.assoc_devices.is_empty());
+
+        frame.extend([0x01, 0x02, 0x78, 0x56, 0xBC, 0x9A]);
+        let rsp = IeeeAddrRsp::default().to_output(frame.clone()).unwrap();
+        assert_eq!(rsp.nwk_addr, 0x1234);
+        assert_eq!(rsp.start_index, 1);
+        assert_eq!(rsp.assoc_devices, vec![0x5678, 0x9ABC]);
+
+        // the count promises more devices than the frame holds
+        frame.pop();
+        assert!(IeeeAddrRsp::default().to_output(frame).is_err());
+    }
+}
@@ -0,0 +1,192 @@
+use crate::command::af::Address;
+use crate::command::{de, ser, Command, CommandID, CommandType, Status};
+
+use znp_macros::Command;
+
+use super::{StatusResponse, SUBSYS};
+
+fn encode_bind(
+    dst_addr: u16,
+    src_addr: &[u8; 8],
+    src_endpoint: u8,
+    cluster_id: u16,
+    dst: &Address,
+    dst_endpoint: u8,
+) -> Vec<u8> {
+    let mut ret = dst_addr.to_le_bytes().to_vec();
+    ret.extend(src_addr);
+    ret.push(src_endpoint);
+    ret.extend(cluster_id.to_le_bytes());
+    ret.extend(dst.encode());
+    ret.push(dst_endpoint);
+    ret
+}
+
+/// Asks `dst_addr` to add a binding from its cluster to a group or an
+/// endpoint of another device, answered by [`BindRsp`].
+/// See Z-stack Monitor and Test API, 3.12.1.14.
+#[derive(Command, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0x21)]
+pub struct BindReq {
+    dst_addr: u16,
+    src_addr: [u8; 8],
+    src_endpoint: u8,
+    cluster_id: u16,
+    /// either [`Address::Group`] or [`Address::Ieee`]
+    dst: Address,
+    /// ignored for groups
+    dst_endpoint: u8,
+}
+
+impl BindReq {
+    pub fn new(
+        dst_addr: u16,
+        src_addr: [u8; 8],
+        src_endpoint: u8,
+        cluster_id: u16,
+        dst: Address,
+        dst_endpoint: u8,
+    ) -> Self {
+        Self {
+            dst_addr,
+            src_addr,
+            src_endpoint,
+            cluster_id,
+            dst,
+            dst_endpoint,
+        }
+    }
+}
+
+impl ser::Command for BindReq {
+    const REQUEST_TYPE: CommandType = CommandType::SREQ;
+    fn len(&self) -> u8 { 23 }
+    fn data(&self) -> Vec<u8> {
+        encode_bind(
+            self.dst_addr,
+            &self.src_addr,
+            self.src_endpoint,
+            self.cluster_id,
+            &self.dst,
+            self.dst_endpoint,
+        )
+    }
+}
+
+impl de::Command for BindReq {
+    const RESPONSE_TYPE: CommandType = CommandType::SRSP;
+    type Output = Status;
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        Status::from_data_frame(data_frame)
+    }
+}
+
+/// Asks `dst_addr` to remove a binding, answered by [`UnbindRsp`].
+/// See Z-stack Monitor and Test API, 3.12.1.15.
+#[derive(Command, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0x22)]
+pub struct UnbindReq {
+    dst_addr: u16,
+    src_addr: [u8; 8],
+    src_endpoint: u8,
+    cluster_id: u16,
+    dst: Address,
+    dst_endpoint: u8,
+}
+
+impl UnbindReq {
+    pub fn new(
+        dst_addr: u16,
+        src_addr: [u8; 8],
+        src_endpoint: u8,
+        cluster_id: u16,
+        dst: Address,
+        dst_endpoint: u8,
+    ) -> Self {
+        Self {
+            dst_addr,
+            src_addr,
+            src_endpoint,
+            cluster_id,
+            dst,
+            dst_endpoint,
+        }
+    }
+}
+
+impl ser::Command for UnbindReq {
+    const REQUEST_TYPE: CommandType = CommandType::SREQ;
+    fn len(&self) -> u8 { 23 }
+    fn data(&self) -> Vec<u8> {
+        encode_bind(
+            self.dst_addr,
+            &self.src_addr,
+            self.src_endpoint,
+            self.cluster_id,
+            &self.dst,
+            self.dst_endpoint,
+        )
+    }
+}
+
+impl de::Command for UnbindReq {
+    const RESPONSE_TYPE: CommandType = CommandType::SRSP;
+    type Output = Status;
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        Status::from_data_frame(data_frame)
+    }
+}
+
+/// See Z-stack Monitor and Test API, 3.12.2.13.
+#[derive(Command, Default, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0xA1)]
+pub struct BindRsp {}
+
+impl de::Command for BindRsp {
+    const RESPONSE_TYPE: CommandType = CommandType::AREQ;
+    type Output = StatusResponse;
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        StatusResponse::decode(&data_frame)
+    }
+}
+
+/// See Z-stack Monitor and Test API, 3.12.2.14.
+#[derive(Command, Default, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0xA2)]
+pub struct UnbindRsp {}
+
+impl de::Command for UnbindRsp {
+    const RESPONSE_TYPE: CommandType = CommandType::AREQ;
+    type Output = StatusResponse;
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        StatusResponse::decode(&data_frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BindReq;
+    use crate::command::af::Address;
+    use crate::command::ser::Command;
+
+    const IEEE: [u8; 8] = [0xA0, 0xA1, 0xA2, 0xA3, 0xA4, 0xA5, 0xA6, 0xA7];
+
+    #[test]
+    fn bind_req() {
+        let command = BindReq::new(0x1234, IEEE, 0x01, 0x0006, Address::Group(0x0002), 0x01);
+        let mut expected = vec![0x17, 0x25, 0x21, 0x34, 0x12];
+        expected.extend(IEEE);
+        expected.extend([0x01, 0x06, 0x00, 0x01, 0x02, 0x00]);
+        expected.extend([0x00; 6]);
+        expected.push(0x01);
+        assert_eq!(command.serialize(), expected);
+
+        let dst = [0xB0, 0xB1, 0xB2, 0xB3, 0xB4, 0xB5, 0xB6, 0xB7];
+        let command = BindReq::new(0x1234, IEEE, 0x01, 0x0006, Address::Ieee(dst), 0x02);
+        let data = command.data();
+        assert_eq!(data.len(), command.len() as usize);
+        assert_eq!(data[13], 0x03);
+        assert_eq!(data[14..22], dst);
+        assert_eq!(data[22], 0x02);
+    }
+}
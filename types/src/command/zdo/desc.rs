@@ -0,0 +1,498 @@
+use crate::command::{de, ser, Command, CommandID, CommandType, Status};
+use crate::nv::Reader;
+use crate::packet::MAX_DATA_LEN;
+
+use znp_macros::Command;
+
+use super::{ZdpStatus, SUBSYS};
+
+/// Asks `dst_addr` for the node descriptor of `nwk_addr`, answered by
+/// [`NodeDescRsp`].
+/// See Z-stack Monitor and Test API, 3.12.1.3.
+#[derive(Command, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0x02)]
+pub struct NodeDescReq {
+    dst_addr: u16,
+    nwk_addr: u16,
+}
+
+impl NodeDescReq {
+    pub fn new(dst_addr: u16, nwk_addr: u16) -> Self { Self { dst_addr, nwk_addr } }
+}
+
+impl ser::Command for NodeDescReq {
+    const REQUEST_TYPE: CommandType = CommandType::SREQ;
+    fn len(&self) -> u8 { 4 }
+    fn data(&self) -> Vec<u8> {
+        [self.dst_addr, self.nwk_addr]
+            .map(u16::to_le_bytes)
+            .concat()
+    }
+}
+
+impl de::Command for NodeDescReq {
+    const RESPONSE_TYPE: CommandType = CommandType::SRSP;
+    type Output = Status;
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        Status::from_data_frame(data_frame)
+    }
+}
+
+/// Asks `dst_addr` for the power descriptor of `nwk_addr`, answered by
+/// [`PowerDescRsp`].
+/// See Z-stack Monitor and Test API, 3.12.1.4.
+#[derive(Command, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0x03)]
+pub struct PowerDescReq {
+    dst_addr: u16,
+    nwk_addr: u16,
+}
+
+impl PowerDescReq {
+    pub fn new(dst_addr: u16, nwk_addr: u16) -> Self { Self { dst_addr, nwk_addr } }
+}
+
+impl ser::Command for PowerDescReq {
+    const REQUEST_TYPE: CommandType = CommandType::SREQ;
+    fn len(&self) -> u8 { 4 }
+    fn data(&self) -> Vec<u8> {
+        [self.dst_addr, self.nwk_addr]
+            .map(u16::to_le_bytes)
+            .concat()
+    }
+}
+
+impl de::Command for PowerDescReq {
+    const RESPONSE_TYPE: CommandType = CommandType::SRSP;
+    type Output = Status;
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        Status::from_data_frame(data_frame)
+    }
+}
+
+/// Asks `dst_addr` for the simple descriptor of an endpoint of `nwk_addr`,
+/// answered by [`SimpleDescRsp`].
+/// See Z-stack Monitor and Test API, 3.12.1.5.
+#[derive(Command, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0x04)]
+pub struct SimpleDescReq {
+    dst_addr: u16,
+    nwk_addr: u16,
+    endpoint: u8,
+}
+
+impl SimpleDescReq {
+    pub fn new(dst_addr: u16, nwk_addr: u16, endpoint: u8) -> Self {
+        Self {
+            dst_addr,
+            nwk_addr,
+            endpoint,
+        }
+    }
+}
+
+impl ser::Command for SimpleDescReq {
+    const REQUEST_TYPE: CommandType = CommandType::SREQ;
+    fn len(&self) -> u8 { 5 }
+    fn data(&self) -> Vec<u8> {
+        let mut ret = [self.dst_addr, self.nwk_addr]
+            .map(u16::to_le_bytes)
+            .concat();
+        ret.push(self.endpoint);
+        ret
+    }
+}
+
+impl de::Command for SimpleDescReq {
+    const RESPONSE_TYPE: CommandType = CommandType::SRSP;
+    type Output = Status;
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        Status::from_data_frame(data_frame)
+    }
+}
+
+/// Asks `dst_addr` for the active endpoints of `nwk_addr`, answered by
+/// [`ActiveEpRsp`].
+/// See Z-stack Monitor and Test API, 3.12.1.6.
+#[derive(Command, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0x05)]
+pub struct ActiveEpReq {
+    dst_addr: u16,
+    nwk_addr: u16,
+}
+
+impl ActiveEpReq {
+    pub fn new(dst_addr: u16, nwk_addr: u16) -> Self { Self { dst_addr, nwk_addr } }
+}
+
+impl ser::Command for ActiveEpReq {
+    const REQUEST_TYPE: CommandType = CommandType::SREQ;
+    fn len(&self) -> u8 { 4 }
+    fn data(&self) -> Vec<u8> {
+        [self.dst_addr, self.nwk_addr]
+            .map(u16::to_le_bytes)
+            .concat()
+    }
+}
+
+impl de::Command for ActiveEpReq {
+    const RESPONSE_TYPE: CommandType = CommandType::SRSP;
+    type Output = Status;
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        Status::from_data_frame(data_frame)
+    }
+}
+
+/// Asks for endpoints of `nwk_addr` matching a profile and clusters,
+/// answered by [`MatchDescRsp`].
+/// See Z-stack Monitor and Test API, 3.12.1.7.
+#[derive(Command, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0x06)]
+pub struct MatchDescReq {
+    dst_addr: u16,
+    nwk_addr: u16,
+    profile_id: u16,
+    in_clusters: Vec<u16>,
+    out_clusters: Vec<u16>,
+}
+
+impl MatchDescReq {
+    /// Fails with [`ser::Error::TooLong`] if the clusters do not fit a
+    /// frame, at most 121 together.
+    pub fn new(
+        dst_addr: u16,
+        nwk_addr: u16,
+        profile_id: u16,
+        in_clusters: Vec<u16>,
+        out_clusters: Vec<u16>,
+    ) -> Result<Self, ser::Error> {
+        let len = 8 + 2 * (in_clusters.len() + out_clusters.len());
+        if len > MAX_DATA_LEN as usize {
+            return Err(ser::Error::TooLong(len));
+        }
+        let ret = Self {
+            dst_addr,
+            nwk_addr,
+            profile_id,
+            in_clusters,
+            out_clusters,
+        };
+        Ok(ret)
+    }
+}
+
+impl ser::Command for MatchDescReq {
+    const REQUEST_TYPE: CommandType = CommandType::SREQ;
+    fn len(&self) -> u8 { 8 + 2 * (self.in_clusters.len() + self.out_clusters.len()) as u8 }
+    fn data(&self) -> Vec<u8> {
+        let mut ret = [self.dst_addr, self.nwk_addr, self.profile_id]
+            .map(u16::to_le_bytes)
+            .concat();
+        for clusters in [&self.in_clusters, &self.out_clusters] {
+            ret.push(clusters.len() as u8);
+            ret.extend(clusters.iter().flat_map(|e| e.to_le_bytes()));
+        }
+        ret
+    }
+}
+
+impl de::Command for MatchDescReq {
+    const RESPONSE_TYPE: CommandType = CommandType::SRSP;
+    type Output = Status;
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        Status::from_data_frame(data_frame)
+    }
+}
+
+/// Capabilities of a node.
+/// See Zigbee Specification, 2.3.2.3.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeDescriptor {
+    /// 0 for a coordinator, 1 for a router, 2 for an end device
+    pub logical_type: u8,
+    pub complex_desc_available: bool,
+    pub user_desc_available: bool,
+    pub aps_flags: u8,
+    pub frequency_band: u8,
+    pub mac_capabilities: u8,
+    pub manufacturer_code: u16,
+    pub max_buffer_size: u8,
+    pub max_in_transfer_size: u16,
+    pub server_mask: u16,
+    pub max_out_transfer_size: u16,
+    pub descriptor_capabilities: u8,
+}
+
+/// Payload of [`NodeDescRsp`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeDescResponse {
+    pub src_addr: u16,
+    pub status: ZdpStatus,
+    pub nwk_addr: u16,
+    /// missing unless `status` is [`ZdpStatus::Success`]
+    pub descriptor: Option<NodeDescriptor>,
+}
+
+/// See Z-stack Monitor and Test API, 3.12.2.3.
+#[derive(Command, Default, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0x82)]
+pub struct NodeDescRsp {}
+
+impl de::Command for NodeDescRsp {
+    const RESPONSE_TYPE: CommandType = CommandType::AREQ;
+    type Output = NodeDescResponse;
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        let mut reader = Reader::new(&data_frame, false);
+        let src_addr = reader.u16()?;
+        let status = ZdpStatus::from_byte(reader.u8()?)?;
+        let nwk_addr = reader.u16()?;
+        let descriptor = match status {
+            ZdpStatus::Success => {
+                let flags = reader.u8()?;
+                let band = reader.u8()?;
+                Some(NodeDescriptor {
+                    logical_type: flags & 0x07,
+                    complex_desc_available: flags & 0x08 != 0,
+                    user_desc_available: flags & 0x10 != 0,
+                    aps_flags: band & 0x07,
+                    frequency_band: band >> 3,
+                    mac_capabilities: reader.u8()?,
+                    manufacturer_code: reader.u16()?,
+                    max_buffer_size: reader.u8()?,
+                    max_in_transfer_size: reader.u16()?,
+                    server_mask: reader.u16()?,
+                    max_out_transfer_size: reader.u16()?,
+                    descriptor_capabilities: reader.u8()?,
+                })
+            }
+            _ => None,
+        };
+        // failed responses may still carry a zeroed descriptor
+        if descriptor.is_some() {
+            reader.finish()?;
+        }
+        let ret = NodeDescResponse {
+            src_addr,
+            status,
+            nwk_addr,
+            descriptor,
+        };
+        Ok(ret)
+    }
+}
+
+/// Power supply of a node.
+/// See Zigbee Specification, 2.3.2.4.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PowerDescriptor {
+    pub current_power_mode: u8,
+    /// bit mask of the available power sources
+    pub available_power_sources: u8,
+    pub current_power_source: u8,
+    pub current_power_source_level: u8,
+}
+
+/// Payload of [`PowerDescRsp`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PowerDescResponse {
+    pub src_addr: u16,
+    pub status: ZdpStatus,
+    pub nwk_addr: u16,
+    pub descriptor: PowerDescriptor,
+}
+
+/// See Z-stack Monitor and Test API, 3.12.2.4.
+#[derive(Command, Default, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0x83)]
+pub struct PowerDescRsp {}
+
+impl de::Command for PowerDescRsp {
+    const RESPONSE_TYPE: CommandType = CommandType::AREQ;
+    type Output = PowerDescResponse;
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        let mut reader = Reader::new(&data_frame, false);
+        let src_addr = reader.u16()?;
+        let status = ZdpStatus::from_byte(reader.u8()?)?;
+        let nwk_addr = reader.u16()?;
+        let [mode, source] = reader.bytes()?;
+        reader.finish()?;
+        let ret = PowerDescResponse {
+            src_addr,
+            status,
+            nwk_addr,
+            descriptor: PowerDescriptor {
+                current_power_mode: mode & 0x0F,
+                available_power_sources: mode >> 4,
+                current_power_source: source & 0x0F,
+                current_power_source_level: source >> 4,
+            },
+        };
+        Ok(ret)
+    }
+}
+
+/// Application profile and clusters of an endpoint.
+/// See Zigbee Specification, 2.3.2.5.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimpleDescriptor {
+    pub endpoint: u8,
+    pub profile_id: u16,
+    pub device_id: u16,
+    pub device_version: u8,
+    pub in_clusters: Vec<u16>,
+    pub out_clusters: Vec<u16>,
+}
+
+/// Payload of [`SimpleDescRsp`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimpleDescResponse {
+    pub src_addr: u16,
+    pub status: ZdpStatus,
+    pub nwk_addr: u16,
+    /// missing unless `status` is [`ZdpStatus::Success`]
+    pub descriptor: Option<SimpleDescriptor>,
+}
+
+fn read_clusters(reader: &mut Reader) -> Result<Vec<u16>, de::Error> {
+    let count = reader.u8()?;
+    (0..count).map(|_| reader.u16()).collect()
+}
+
+fn read_list(reader: &mut Reader) -> Result<Vec<u8>, de::Error> {
+    let count = reader.u8()?;
+    (0..count).map(|_| reader.u8()).collect()
+}
+
+/// See Z-stack Monitor and Test API, 3.12.2.5.
+#[derive(Command, Default, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0x84)]
+pub struct SimpleDescRsp {}
+
+impl de::Command for SimpleDescRsp {
+    const RESPONSE_TYPE: CommandType = CommandType::AREQ;
+    type Output = SimpleDescResponse;
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        let mut reader = Reader::new(&data_frame, false);
+        let src_addr = reader.u16()?;
+        let status = ZdpStatus::from_byte(reader.u8()?)?;
+        let nwk_addr = reader.u16()?;
+        let len = reader.u8()?;
+        let descriptor = match len {
+            0 => None,
+            _ => Some(SimpleDescriptor {
+                endpoint: reader.u8()?,
+                profile_id: reader.u16()?,
+                device_id: reader.u16()?,
+                device_version: reader.u8()?,
+                in_clusters: read_clusters(&mut reader)?,
+                out_clusters: read_clusters(&mut reader)?,
+            }),
+        };
+        reader.finish()?;
+        let ret = SimpleDescResponse {
+            src_addr,
+            status,
+            nwk_addr,
+            descriptor,
+        };
+        Ok(ret)
+    }
+}
+
+/// Payload of [`ActiveEpRsp`] and [`MatchDescRsp`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EndpointsResponse {
+    pub src_addr: u16,
+    pub status: ZdpStatus,
+    pub nwk_addr: u16,
+    pub endpoints: Vec<u8>,
+}
+
+fn decode_endpoints_rsp(data_frame: &[u8]) -> Result<EndpointsResponse, de::Error> {
+    let mut reader = Reader::new(data_frame, false);
+    let ret = EndpointsResponse {
+        src_addr: reader.u16()?,
+        status: ZdpStatus::from_byte(reader.u8()?)?,
+        nwk_addr: reader.u16()?,
+        endpoints: read_list(&mut reader)?,
+    };
+    reader.finish()?;
+    Ok(ret)
+}
+
+/// See Z-stack Monitor and Test API, 3.12.2.6.
+#[derive(Command, Default, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0x85)]
+pub struct ActiveEpRsp {}
+
+impl de::Command for ActiveEpRsp {
+    const RESPONSE_TYPE: CommandType = CommandType::AREQ;
+    type Output = EndpointsResponse;
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        decode_endpoints_rsp(&data_frame)
+    }
+}
+
+/// See Z-stack Monitor and Test API, 3.12.2.7.
+#[derive(Command, Default, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0x86)]
+pub struct MatchDescRsp {}
+
+impl de::Command for MatchDescRsp {
+    const RESPONSE_TYPE: CommandType = CommandType::AREQ;
+    type Output = EndpointsResponse;
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        decode_endpoints_rsp(&data_frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MatchDescReq, NodeDescRsp, SimpleDescRsp};
+    use crate::command::de::Command;
+    use crate::command::ser::{self, Command as _};
+    use crate::command::zdo::ZdpStatus;
+    use crate::packet::MAX_DATA_LEN;
+
+    #[test]
+    fn descriptors() {
+        let frame = vec![
+            0x34, 0x12, 0x00, 0x34, 0x12, 0x01, 0x40, 0x8E, 0x7C, 0x11, 0x52, 0x52, 0x00, 0x00,
+            0x2C, 0x52, 0x00, 0x00,
+        ];
+        let rsp = NodeDescRsp::default().to_output(frame).unwrap();
+        let descriptor = rsp.descriptor.unwrap();
+        assert_eq!(descriptor.logical_type, 1);
+        assert_eq!(descriptor.frequency_band, 0x08);
+        assert_eq!(descriptor.manufacturer_code, 0x117C);
+
+        let rsp = NodeDescRsp::default()
+            .to_output(vec![0x34, 0x12, 0x81, 0x34, 0x12])
+            .unwrap();
+        assert_eq!(rsp.status, ZdpStatus::DeviceNotFound);
+        assert_eq!(rsp.descriptor, None);
+
+        let frame = vec![
+            0x34, 0x12, 0x00, 0x34, 0x12, 0x0E, 0x01, 0x04, 0x01, 0x00, 0x01, 0x01, 0x02, 0x00,
+            0x00, 0x06, 0x00, 0x01, 0x19, 0x00,
+        ];
+        let rsp = SimpleDescRsp::default().to_output(frame).unwrap();
+        let descriptor = rsp.descriptor.unwrap();
+        assert_eq!(descriptor.profile_id, 0x0104);
+        assert_eq!(descriptor.in_clusters, vec![0x0000, 0x0006]);
+        assert_eq!(descriptor.out_clusters, vec![0x0019]);
+    }
+
+    #[test]
+    fn clusters_fit_frame() {
+        let command = MatchDescReq::new(0, 0, 0x0104, vec![0x0006; 61], vec![0x0008; 60]).unwrap();
+        assert_eq!(command.len(), MAX_DATA_LEN);
+        assert_eq!(command.data().len(), MAX_DATA_LEN as usize);
+    }
+
+    #[test]
+    fn clusters_too_long() {
+        let ret = MatchDescReq::new(0, 0, 0x0104, vec![0x0006; 61], vec![0x0008; 61]);
+        assert_eq!(ret.unwrap_err(), ser::Error::TooLong(252));
+    }
+}
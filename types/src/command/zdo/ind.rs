@@ -0,0 +1,169 @@
+use crate::command::{de, Command, CommandID, CommandType};
+use crate::nv::Reader;
+
+use znp_macros::Command;
+
+use enumflags2::BitFlags;
+
+use super::SUBSYS;
+
+/// MAC capabilities a device announces.
+/// See IEEE 802.15.4, 7.3.1.2.
+#[enumflags2::bitflags]
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MacCapability {
+    AlternatePanCoordinator = 0x01,
+    Router = 0x02,
+    MainsPowered = 0x04,
+    RxOnWhenIdle = 0x08,
+    SecurityCapable = 0x40,
+    AllocateAddress = 0x80,
+}
+
+/// Payload of [`EndDeviceAnnceInd`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceAnnouncement {
+    pub src_addr: u16,
+    pub nwk_addr: u16,
+    pub ieee_addr: [u8; 8],
+    pub capabilities: BitFlags<MacCapability>,
+}
+
+/// Sent when a device announces itself after joining or rejoining.
+/// See Z-stack Monitor and Test API, 3.12.2.23.
+#[derive(Command, Default, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0xC1)]
+pub struct EndDeviceAnnceInd {}
+
+impl de::Command for EndDeviceAnnceInd {
+    const RESPONSE_TYPE: CommandType = CommandType::AREQ;
+    type Output = DeviceAnnouncement;
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        let mut reader = Reader::new(&data_frame, false);
+        let ret = DeviceAnnouncement {
+            src_addr: reader.u16()?,
+            nwk_addr: reader.u16()?,
+            ieee_addr: reader.bytes()?,
+            capabilities: BitFlags::from_bits_truncate(reader.u8()?),
+        };
+        reader.finish()?;
+        Ok(ret)
+    }
+}
+
+/// Payload of [`TcDevInd`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TcDevice {
+    pub nwk_addr: u16,
+    pub ieee_addr: [u8; 8],
+    pub parent_addr: u16,
+}
+
+/// Sent by the trust center when a device joins the network.
+/// See Z-stack Monitor and Test API, 3.12.2.32.
+#[derive(Command, Default, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0xCA)]
+pub struct TcDevInd {}
+
+impl de::Command for TcDevInd {
+    const RESPONSE_TYPE: CommandType = CommandType::AREQ;
+    type Output = TcDevice;
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        let mut reader = Reader::new(&data_frame, false);
+        let ret = TcDevice {
+            nwk_addr: reader.u16()?,
+            ieee_addr: reader.bytes()?,
+            parent_addr: reader.u16()?,
+        };
+        reader.finish()?;
+        Ok(ret)
+    }
+}
+
+/// Payload of [`LeaveInd`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Leave {
+    pub src_addr: u16,
+    pub ieee_addr: [u8; 8],
+    /// whether this is a request to leave rather than an indication
+    pub request: bool,
+    pub remove_children: bool,
+    pub rejoin: bool,
+}
+
+/// Sent when a device left the network, or is asked to.
+/// See Z-stack Monitor and Test API, 3.12.2.30.
+#[derive(Command, Default, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0xC9)]
+pub struct LeaveInd {}
+
+impl de::Command for LeaveInd {
+    const RESPONSE_TYPE: CommandType = CommandType::AREQ;
+    type Output = Leave;
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        let mut reader = Reader::new(&data_frame, false);
+        let ret = Leave {
+            src_addr: reader.u16()?,
+            ieee_addr: reader.bytes()?,
+            request: reader.bool()?,
+            remove_children: reader.bool()?,
+            rejoin: reader.bool()?,
+        };
+        reader.finish()?;
+        Ok(ret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EndDeviceAnnceInd, LeaveInd, MacCapability, TcDevInd};
+    use crate::command::de::Command;
+
+    const IEEE: [u8; 8] = [0xA0, 0xA1, 0xA2, 0xA3, 0xA4, 0xA5, 0xA6, 0xA7];
+
+    #[test]
+    fn end_device_annce_ind() {
+        let mut frame = vec![0x34, 0x12, 0x34, 0x12];
+        frame.extend(IEEE);
+        frame.push(0x8E);
+        let ind = EndDeviceAnnceInd::default().to_output(frame).unwrap();
+        assert_eq!(ind.src_addr, 0x1234);
+        assert_eq!(ind.nwk_addr, 0x1234);
+        assert_eq!(ind.ieee_addr, IEEE);
+        assert_eq!(
+            ind.capabilities,
+            MacCapability::Router
+                | MacCapability::MainsPowered
+                | MacCapability::RxOnWhenIdle
+                | MacCapability::AllocateAddress
+        );
+    }
+
+    #[test]
+    fn tc_dev_ind() {
+        let mut frame = vec![0x34, 0x12];
+        frame.extend(IEEE);
+        frame.extend([0x00, 0x00]);
+        let ind = TcDevInd::default().to_output(frame.clone()).unwrap();
+        assert_eq!(ind.nwk_addr, 0x1234);
+        assert_eq!(ind.ieee_addr, IEEE);
+        assert_eq!(ind.parent_addr, 0x0000);
+
+        frame.push(0x00);
+        assert!(TcDevInd::default().to_output(frame).is_err());
+    }
+
+    #[test]
+    fn leave_ind() {
+        let mut frame = vec![0x34, 0x12];
+        frame.extend(IEEE);
+        frame.extend([0x00, 0x01, 0x00]);
+        let ind = LeaveInd::default().to_output(frame).unwrap();
+        assert_eq!(ind.src_addr, 0x1234);
+        assert_eq!(ind.ieee_addr, IEEE);
+        assert!(!ind.request);
+        assert!(ind.remove_children);
+        assert!(!ind.rejoin);
+    }
+}
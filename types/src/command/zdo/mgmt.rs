@@ -0,0 +1,536 @@
+use crate::command::{de, ser, Command, CommandID, CommandType, Status};
+use crate::nv::Reader;
+
+use znp_macros::Command;
+
+use enumflags2::BitFlags;
+use num_traits::FromPrimitive;
+
+use super::{StatusResponse, ZdpStatus, SUBSYS};
+
+/// Asks `dst_addr` for its neighbor table from `start_index` on, answered by
+/// [`MgmtLqiRsp`].
+/// See Z-stack Monitor and Test API, 3.12.1.17.
+#[derive(Command, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0x31)]
+pub struct MgmtLqiReq {
+    dst_addr: u16,
+    start_index: u8,
+}
+
+impl MgmtLqiReq {
+    pub fn new(dst_addr: u16, start_index: u8) -> Self {
+        Self {
+            dst_addr,
+            start_index,
+        }
+    }
+}
+
+impl ser::Command for MgmtLqiReq {
+    const REQUEST_TYPE: CommandType = CommandType::SREQ;
+    fn len(&self) -> u8 { 3 }
+    fn data(&self) -> Vec<u8> {
+        let mut ret = self.dst_addr.to_le_bytes().to_vec();
+        ret.push(self.start_index);
+        ret
+    }
+}
+
+impl de::Command for MgmtLqiReq {
+    const RESPONSE_TYPE: CommandType = CommandType::SRSP;
+    type Output = Status;
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        Status::from_data_frame(data_frame)
+    }
+}
+
+/// Asks `dst_addr` for its routing table from `start_index` on, answered by
+/// [`MgmtRtgRsp`].
+/// See Z-stack Monitor and Test API, 3.12.1.18.
+#[derive(Command, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0x32)]
+pub struct MgmtRtgReq {
+    dst_addr: u16,
+    start_index: u8,
+}
+
+impl MgmtRtgReq {
+    pub fn new(dst_addr: u16, start_index: u8) -> Self {
+        Self {
+            dst_addr,
+            start_index,
+        }
+    }
+}
+
+impl ser::Command for MgmtRtgReq {
+    const REQUEST_TYPE: CommandType = CommandType::SREQ;
+    fn len(&self) -> u8 { 3 }
+    fn data(&self) -> Vec<u8> {
+        let mut ret = self.dst_addr.to_le_bytes().to_vec();
+        ret.push(self.start_index);
+        ret
+    }
+}
+
+impl de::Command for MgmtRtgReq {
+    const RESPONSE_TYPE: CommandType = CommandType::SRSP;
+    type Output = Status;
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        Status::from_data_frame(data_frame)
+    }
+}
+
+/// Options of [`MgmtLeaveReq`].
+#[enumflags2::bitflags]
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LeaveOptions {
+    Rejoin = 0x01,
+    RemoveChildren = 0x02,
+}
+
+/// Asks `dst_addr` to make a device, possibly itself, leave the network,
+/// answered by [`MgmtLeaveRsp`].
+/// See Z-stack Monitor and Test API, 3.12.1.20.
+#[derive(Command, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0x34)]
+pub struct MgmtLeaveReq {
+    dst_addr: u16,
+    device_addr: [u8; 8],
+    options: BitFlags<LeaveOptions>,
+}
+
+impl MgmtLeaveReq {
+    pub fn new(dst_addr: u16, device_addr: [u8; 8], options: BitFlags<LeaveOptions>) -> Self {
+        Self {
+            dst_addr,
+            device_addr,
+            options,
+        }
+    }
+}
+
+impl ser::Command for MgmtLeaveReq {
+    const REQUEST_TYPE: CommandType = CommandType::SREQ;
+    fn len(&self) -> u8 { 11 }
+    fn data(&self) -> Vec<u8> {
+        let mut ret = self.dst_addr.to_le_bytes().to_vec();
+        ret.extend(self.device_addr);
+        ret.push(self.options.bits());
+        ret
+    }
+}
+
+impl de::Command for MgmtLeaveReq {
+    const RESPONSE_TYPE: CommandType = CommandType::SRSP;
+    type Output = Status;
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        Status::from_data_frame(data_frame)
+    }
+}
+
+/// Opens or closes joining on `dst_addr`, or on every router when it is a
+/// broadcast address, answered by [`MgmtPermitJoinRsp`].
+/// See Z-stack Monitor and Test API, 3.12.1.22.
+#[derive(Command, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0x36)]
+pub struct MgmtPermitJoinReq {
+    dst_addr: u16,
+    /// in seconds, `0` closes joining and `0xFF` keeps it open
+    duration: u8,
+    tc_significance: bool,
+}
+
+impl MgmtPermitJoinReq {
+    pub fn new(dst_addr: u16, duration: u8) -> Self {
+        Self {
+            dst_addr,
+            duration,
+            tc_significance: true,
+        }
+    }
+
+    /// Whether the trust center authentication policy is affected too.
+    pub fn tc_significance(mut self, tc_significance: bool) -> Self {
+        self.tc_significance = tc_significance;
+        self
+    }
+}
+
+impl ser::Command for MgmtPermitJoinReq {
+    const REQUEST_TYPE: CommandType = CommandType::SREQ;
+    fn len(&self) -> u8 { 5 }
+    fn data(&self) -> Vec<u8> {
+        // afAddrBroadcast or afAddr16Bit
+        let addr_mode = match self.dst_addr {
+            0xFFF8.. => 0x0F,
+            _ => 0x02,
+        };
+        let mut ret = vec![addr_mode];
+        ret.extend(self.dst_addr.to_le_bytes());
+        ret.extend([self.duration, self.tc_significance as u8]);
+        ret
+    }
+}
+
+impl de::Command for MgmtPermitJoinReq {
+    const RESPONSE_TYPE: CommandType = CommandType::SRSP;
+    type Output = Status;
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        Status::from_data_frame(data_frame)
+    }
+}
+
+/// Scan duration of [`MgmtNwkUpdateReq`] moving the network to the channel
+/// in the mask.
+pub const NWK_UPDATE_CHANGE_CHANNEL: u8 = 0xFE;
+/// Scan duration of [`MgmtNwkUpdateReq`] setting the channel mask and the
+/// network manager.
+pub const NWK_UPDATE_SET_MANAGER: u8 = 0xFF;
+
+/// Asks `dst_addr` to scan channels, answered by [`MgmtNwkUpdateNotify`],
+/// or to change channel or network manager.
+/// See Z-stack Monitor and Test API, 3.12.1.23.
+#[derive(Command, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0x37)]
+pub struct MgmtNwkUpdateReq {
+    dst_addr: u16,
+    channel_mask: u32,
+    /// exponent of the scan time per channel, up to 5, or
+    /// [`NWK_UPDATE_CHANGE_CHANNEL`] or [`NWK_UPDATE_SET_MANAGER`]
+    scan_duration: u8,
+    scan_count: u8,
+    nwk_manager_addr: u16,
+}
+
+impl MgmtNwkUpdateReq {
+    pub fn new(dst_addr: u16, channel_mask: u32, scan_duration: u8, scan_count: u8) -> Self {
+        Self {
+            dst_addr,
+            channel_mask,
+            scan_duration,
+            scan_count,
+            nwk_manager_addr: 0x0000,
+        }
+    }
+
+    /// Moves the whole network to `channel`.
+    pub fn change_channel(channel: u8) -> Self {
+        Self::new(0xFFFD, 1 << channel, NWK_UPDATE_CHANGE_CHANNEL, 0)
+    }
+
+    pub fn nwk_manager_addr(mut self, nwk_manager_addr: u16) -> Self {
+        self.nwk_manager_addr = nwk_manager_addr;
+        self
+    }
+}
+
+impl ser::Command for MgmtNwkUpdateReq {
+    const REQUEST_TYPE: CommandType = CommandType::SREQ;
+    fn len(&self) -> u8 { 11 }
+    fn data(&self) -> Vec<u8> {
+        let addr_mode = match self.dst_addr {
+            0xFFF8.. => 0x0F,
+            _ => 0x02,
+        };
+        let mut ret = self.dst_addr.to_le_bytes().to_vec();
+        ret.push(addr_mode);
+        ret.extend(self.channel_mask.to_le_bytes());
+        ret.extend([self.scan_duration, self.scan_count]);
+        ret.extend(self.nwk_manager_addr.to_le_bytes());
+        ret
+    }
+}
+
+impl de::Command for MgmtNwkUpdateReq {
+    const RESPONSE_TYPE: CommandType = CommandType::SRSP;
+    type Output = Status;
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        Status::from_data_frame(data_frame)
+    }
+}
+
+/// Entry of a neighbor table.
+/// See Zigbee Specification, 2.4.4.3.2.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Neighbor {
+    pub extended_pan_id: [u8; 8],
+    pub ieee_addr: [u8; 8],
+    pub nwk_addr: u16,
+    /// 0 for a coordinator, 1 for a router, 2 for an end device, 3 unknown
+    pub device_type: u8,
+    /// 0 off, 1 on, 2 unknown
+    pub rx_on_when_idle: u8,
+    /// 0 parent, 1 child, 2 sibling, 3 none, 4 previous child
+    pub relationship: u8,
+    /// 0 no, 1 yes, 2 unknown
+    pub permit_joining: u8,
+    pub depth: u8,
+    pub lqi: u8,
+}
+
+/// Payload of [`MgmtLqiRsp`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LqiResponse {
+    pub src_addr: u16,
+    pub status: ZdpStatus,
+    /// entries in the whole table
+    pub total: u8,
+    pub start_index: u8,
+    pub neighbors: Vec<Neighbor>,
+}
+
+/// See Z-stack Monitor and Test API, 3.12.2.16.
+#[derive(Command, Default, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0xB1)]
+pub struct MgmtLqiRsp {}
+
+impl de::Command for MgmtLqiRsp {
+    const RESPONSE_TYPE: CommandType = CommandType::AREQ;
+    type Output = LqiResponse;
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        let mut reader = Reader::new(&data_frame, false);
+        let src_addr = reader.u16()?;
+        let status = ZdpStatus::from_byte(reader.u8()?)?;
+        let total = reader.u8()?;
+        let start_index = reader.u8()?;
+        let count = reader.u8()?;
+        let mut neighbors = vec![];
+        for _ in 0..count {
+            let extended_pan_id = reader.bytes()?;
+            let ieee_addr = reader.bytes()?;
+            let nwk_addr = reader.u16()?;
+            let flags = reader.u8()?;
+            neighbors.push(Neighbor {
+                extended_pan_id,
+                ieee_addr,
+                nwk_addr,
+                device_type: flags & 0x03,
+                rx_on_when_idle: (flags >> 2) & 0x03,
+                relationship: (flags >> 4) & 0x07,
+                permit_joining: reader.u8()? & 0x03,
+                depth: reader.u8()?,
+                lqi: reader.u8()?,
+            });
+        }
+        reader.finish()?;
+        let ret = LqiResponse {
+            src_addr,
+            status,
+            total,
+            start_index,
+            neighbors,
+        };
+        Ok(ret)
+    }
+}
+
+/// Status of a route.
+#[derive(num_derive::FromPrimitive)]
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RouteStatus {
+    Active = 0x00,
+    DiscoveryUnderway = 0x01,
+    DiscoveryFailed = 0x02,
+    Inactive = 0x03,
+    ValidationUnderway = 0x04,
+}
+
+/// Entry of a routing table.
+/// See Zigbee Specification, 2.4.4.3.3.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Route {
+    pub dst_addr: u16,
+    pub status: RouteStatus,
+    pub memory_constrained: bool,
+    pub many_to_one: bool,
+    pub route_record_required: bool,
+    pub next_hop: u16,
+}
+
+/// Payload of [`MgmtRtgRsp`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RtgResponse {
+    pub src_addr: u16,
+    pub status: ZdpStatus,
+    /// entries in the whole table
+    pub total: u8,
+    pub start_index: u8,
+    pub routes: Vec<Route>,
+}
+
+/// See Z-stack Monitor and Test API, 3.12.2.17.
+#[derive(Command, Default, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0xB2)]
+pub struct MgmtRtgRsp {}
+
+impl de::Command for MgmtRtgRsp {
+    const RESPONSE_TYPE: CommandType = CommandType::AREQ;
+    type Output = RtgResponse;
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        let mut reader = Reader::new(&data_frame, false);
+        let src_addr = reader.u16()?;
+        let status = ZdpStatus::from_byte(reader.u8()?)?;
+        let total = reader.u8()?;
+        let start_index = reader.u8()?;
+        let count = reader.u8()?;
+        let mut routes = vec![];
+        for _ in 0..count {
+            let dst_addr = reader.u16()?;
+            let flags = reader.u8()?;
+            routes.push(Route {
+                dst_addr,
+                status: RouteStatus::from_u8(flags & 0x07).ok_or(de::Error::Unknown)?,
+                memory_constrained: flags & 0x08 != 0,
+                many_to_one: flags & 0x10 != 0,
+                route_record_required: flags & 0x20 != 0,
+                next_hop: reader.u16()?,
+            });
+        }
+        reader.finish()?;
+        let ret = RtgResponse {
+            src_addr,
+            status,
+            total,
+            start_index,
+            routes,
+        };
+        Ok(ret)
+    }
+}
+
+/// See Z-stack Monitor and Test API, 3.12.2.19.
+#[derive(Command, Default, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0xB4)]
+pub struct MgmtLeaveRsp {}
+
+impl de::Command for MgmtLeaveRsp {
+    const RESPONSE_TYPE: CommandType = CommandType::AREQ;
+    type Output = StatusResponse;
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        StatusResponse::decode(&data_frame)
+    }
+}
+
+/// See Z-stack Monitor and Test API, 3.12.2.21.
+#[derive(Command, Default, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0xB6)]
+pub struct MgmtPermitJoinRsp {}
+
+impl de::Command for MgmtPermitJoinRsp {
+    const RESPONSE_TYPE: CommandType = CommandType::AREQ;
+    type Output = StatusResponse;
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        StatusResponse::decode(&data_frame)
+    }
+}
+
+/// Payload of [`MgmtNwkUpdateNotify`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NwkUpdateNotification {
+    pub src_addr: u16,
+    pub status: ZdpStatus,
+    pub scanned_channels: u32,
+    pub total_transmissions: u16,
+    pub transmission_failures: u16,
+    /// energy detected on each scanned channel, in ascending order
+    pub energy_values: Vec<u8>,
+}
+
+/// Result of a channel scan requested with [`MgmtNwkUpdateReq`].
+#[derive(Command, Default, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0xB8)]
+pub struct MgmtNwkUpdateNotify {}
+
+impl de::Command for MgmtNwkUpdateNotify {
+    const RESPONSE_TYPE: CommandType = CommandType::AREQ;
+    type Output = NwkUpdateNotification;
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        let mut reader = Reader::new(&data_frame, false);
+        let src_addr = reader.u16()?;
+        let status = ZdpStatus::from_byte(reader.u8()?)?;
+        let scanned_channels = reader.u32()?;
+        let total_transmissions = reader.u16()?;
+        let transmission_failures = reader.u16()?;
+        let count = reader.u8()?;
+        let energy_values = (0..count).map(|_| reader.u8()).collect::<Result<_, _>>()?;
+        reader.finish()?;
+        let ret = NwkUpdateNotification {
+            src_addr,
+            status,
+            scanned_channels,
+            total_transmissions,
+            transmission_failures,
+            energy_values,
+        };
+        Ok(ret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MgmtLqiRsp, MgmtNwkUpdateNotify, MgmtRtgRsp, RouteStatus};
+    use crate::command::de::Command;
+    use crate::command::zdo::ZdpStatus;
+
+    #[test]
+    fn lqi_rsp() {
+        let mut frame = vec![0x00, 0x00, 0x00, 0x02, 0x00, 0x01];
+        frame.extend([0xDD; 8]);
+        frame.extend([0xA0, 0xA1, 0xA2, 0xA3, 0xA4, 0xA5, 0xA6, 0xA7]);
+        frame.extend([0x34, 0x12, 0x25, 0x02, 0x01, 0xC8]);
+        let rsp = MgmtLqiRsp::default().to_output(frame).unwrap();
+        assert_eq!(rsp.total, 2);
+        let neighbor = &rsp.neighbors[0];
+        assert_eq!(neighbor.nwk_addr, 0x1234);
+        assert_eq!(neighbor.device_type, 1);
+        assert_eq!(neighbor.rx_on_when_idle, 1);
+        assert_eq!(neighbor.relationship, 2);
+        assert_eq!(neighbor.lqi, 0xC8);
+    }
+
+    #[test]
+    fn rtg_rsp() {
+        let frame = vec![
+            0x34, 0x12, 0x00, 0x05, 0x02, 0x02, 0x78, 0x56, 0x10, 0x34, 0x12, 0xBC, 0x9A, 0x2B,
+            0xFF, 0xFF,
+        ];
+        let rsp = MgmtRtgRsp::default().to_output(frame).unwrap();
+        assert_eq!(rsp.src_addr, 0x1234);
+        assert_eq!(rsp.status, ZdpStatus::Success);
+        assert_eq!(rsp.total, 5);
+        assert_eq!(rsp.start_index, 2);
+        let route = &rsp.routes[0];
+        assert_eq!(route.dst_addr, 0x5678);
+        assert_eq!(route.status, RouteStatus::Active);
+        assert!(route.many_to_one);
+        assert!(!route.memory_constrained);
+        assert_eq!(route.next_hop, 0x1234);
+        let route = &rsp.routes[1];
+        assert_eq!(route.status, RouteStatus::Inactive);
+        assert!(route.memory_constrained);
+        assert!(route.route_record_required);
+        assert!(!route.many_to_one);
+
+        let frame = vec![
+            0x34, 0x12, 0x00, 0x01, 0x00, 0x01, 0x78, 0x56, 0x07, 0x34, 0x12,
+        ];
+        assert!(MgmtRtgRsp::default().to_output(frame).is_err());
+    }
+
+    #[test]
+    fn nwk_update_notify() {
+        let frame = vec![
+            0x34, 0x12, 0x00, 0x00, 0x08, 0x00, 0x00, 0x64, 0x00, 0x03, 0x00, 0x02, 0x4A, 0xB0,
+        ];
+        let rsp = MgmtNwkUpdateNotify::default().to_output(frame).unwrap();
+        assert_eq!(rsp.src_addr, 0x1234);
+        assert_eq!(rsp.scanned_channels, 0x0000_0800);
+        assert_eq!(rsp.total_transmissions, 100);
+        assert_eq!(rsp.transmission_failures, 3);
+        assert_eq!(rsp.energy_values, vec![0x4A, 0xB0]);
+    }
+}
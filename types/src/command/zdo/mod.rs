@@ -0,0 +1,29 @@
+mod addr;
+mod bind;
+mod desc;
+mod ind;
+mod mgmt;
+mod startup;
+mod status;
+
+pub use addr::{AddrReqType, AddrResponse, IeeeAddrReq, IeeeAddrRsp, NwkAddrReq, NwkAddrRsp};
+pub use bind::{BindReq, BindRsp, UnbindReq, UnbindRsp};
+pub use desc::{
+    ActiveEpReq, ActiveEpRsp, EndpointsResponse, MatchDescReq, MatchDescRsp, NodeDescReq,
+    NodeDescResponse, NodeDescRsp, NodeDescriptor, PowerDescReq, PowerDescResponse, PowerDescRsp,
+    PowerDescriptor, SimpleDescReq, SimpleDescResponse, SimpleDescRsp, SimpleDescriptor,
+};
+pub use ind::{
+    DeviceAnnouncement, EndDeviceAnnceInd, Leave, LeaveInd, MacCapability, TcDevInd, TcDevice,
+};
+pub use mgmt::{
+    LeaveOptions, LqiResponse, MgmtLeaveReq, MgmtLeaveRsp, MgmtLqiReq, MgmtLqiRsp,
+    MgmtNwkUpdateNotify, MgmtNwkUpdateReq, MgmtPermitJoinReq, MgmtPermitJoinRsp, MgmtRtgReq,
+    MgmtRtgRsp, Neighbor, NwkUpdateNotification, Route, RouteStatus, RtgResponse,
+    NWK_UPDATE_CHANGE_CHANNEL, NWK_UPDATE_SET_MANAGER,
+};
+pub use startup::{DeviceState, StartupFromApp, StartupState, StateChangeInd};
+pub use status::{StatusResponse, ZdpStatus};
+
+use crate::command::Subsystem;
+const SUBSYS: Subsystem = Subsystem::IFaceZDO;
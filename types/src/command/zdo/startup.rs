@@ -0,0 +1,85 @@
+use crate::command::{de, ser, serialize_bincode, Command, CommandID, CommandType};
+
+use znp_macros::Command;
+
+use num_traits::FromPrimitive;
+
+use super::SUBSYS;
+
+/// Result of [`StartupFromApp`].
+/// See Z-stack Monitor and Test API, 3.12.1.26.
+#[derive(num_derive::FromPrimitive)]
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StartupState {
+    RestoredNetworkState = 0x00,
+    NewNetworkState = 0x01,
+    LeaveAndNotStarted = 0x02,
+}
+
+/// Starts the stack, restoring the network from NV if there is one.
+#[derive(Command, bincode::Encode, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0x40)]
+pub struct StartupFromApp {
+    /// delay before starting, in ms
+    start_delay: u16,
+}
+
+impl StartupFromApp {
+    pub fn new(start_delay: u16) -> Self { Self { start_delay } }
+}
+
+impl ser::Command for StartupFromApp {
+    const REQUEST_TYPE: CommandType = CommandType::SREQ;
+    fn len(&self) -> u8 { 2 }
+    fn data(&self) -> Vec<u8> { serialize_bincode(self) }
+}
+
+impl de::Command for StartupFromApp {
+    const RESPONSE_TYPE: CommandType = CommandType::SRSP;
+    type Output = StartupState;
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        match data_frame.as_slice() {
+            [state] => StartupState::from_u8(*state).ok_or(de::Error::Unknown),
+            _ => Err(de::Error::UnexpectedEOF),
+        }
+    }
+}
+
+/// State of the device, `devStates_t`.
+/// See Z-stack Monitor and Test API, 3.12.2.22.
+#[derive(num_derive::FromPrimitive)]
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceState {
+    Hold = 0x00,
+    Init = 0x01,
+    NwkDisc = 0x02,
+    NwkJoining = 0x03,
+    NwkRejoin = 0x04,
+    EndDeviceUnauth = 0x05,
+    EndDevice = 0x06,
+    Router = 0x07,
+    CoordStarting = 0x08,
+    ZbCoord = 0x09,
+    NwkOrphan = 0x0A,
+    NwkKaRejoin = 0x0B,
+    NwkBackoff = 0x0C,
+    NwkRejoinAfterBackoff = 0x0D,
+}
+
+/// Sent by the device whenever its [`DeviceState`] changes.
+#[derive(Command, Default, Debug, Clone)]
+#[cmd(subsys = "SUBSYS", id = 0xC0)]
+pub struct StateChangeInd {}
+
+impl de::Command for StateChangeInd {
+    const RESPONSE_TYPE: CommandType = CommandType::AREQ;
+    type Output = DeviceState;
+    fn to_output(&self, data_frame: Vec<u8>) -> Result<Self::Output, de::Error> {
+        match data_frame.as_slice() {
+            [state] => DeviceState::from_u8(*state).ok_or(de::Error::Unknown),
+            _ => Err(de::Error::UnexpectedEOF),
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use crate::command::de;
+
+use num_traits::FromPrimitive;
+
+/// Status of a ZDO response, as sent over the air.
+/// See Zigbee Specification, 2.4.5.
+#[derive(num_derive::FromPrimitive)]
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ZdpStatus {
+    Success = 0x00,
+    InvRequestType = 0x80,
+    DeviceNotFound = 0x81,
+    InvalidEp = 0x82,
+    NotActive = 0x83,
+    NotSupported = 0x84,
+    Timeout = 0x85,
+    NoMatch = 0x86,
+    NoEntry = 0x88,
+    NoDescriptor = 0x89,
+    InsufficientSpace = 0x8A,
+    NotPermitted = 0x8B,
+    TableFull = 0x8C,
+    NotAuthorized = 0x8D,
+    BindingTableFull = 0x8E,
+}
+
+impl ZdpStatus {
+    pub(crate) fn from_byte(status: u8) -> Result<Self, de::Error> {
+        Self::from_u8(status).ok_or(de::Error::Unknown)
+    }
+}
+
+/// Payload of responses carrying only a status.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatusResponse {
+    pub src_addr: u16,
+    pub status: ZdpStatus,
+}
+
+impl StatusResponse {
+    pub(crate) fn decode(data_frame: &[u8]) -> Result<Self, de::Error> {
+        let [lo, hi, status] = *data_frame else {
+            return Err(de::Error::UnexpectedEOF);
+        };
+        let ret = Self {
+            src_addr: u16::from_le_bytes([lo, hi]),
+            status: ZdpStatus::from_byte(status)?,
+        };
+        Ok(ret)
+    }
+}
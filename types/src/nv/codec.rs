@@ -0,0 +1,192 @@
+use crate::command::de;
+
+/// Encodes C structs the way Z-Stack stores them in NV memory.
+///
+/// With `align` set, every field is aligned to its own size and structs are
+/// padded to their largest field, as compilers for 32-bit chips do. On 8051
+/// based chips structs are packed.
+pub struct Writer {
+    buf: Vec<u8>,
+    align: bool,
+}
+
+impl Writer {
+    pub fn new(align: bool) -> Self { Self { buf: vec![], align } }
+
+    fn pad(&mut self, size: usize) {
+        if self.align {
+            self.buf
+                .resize(self.buf.len().next_multiple_of(size), u8::MIN);
+        }
+    }
+
+    pub fn u8(&mut self, val: u8) -> &mut Self {
+        self.buf.push(val);
+        self
+    }
+
+    pub fn bool(&mut self, val: bool) -> &mut Self { self.u8(val as u8) }
+
+    pub fn u16(&mut self, val: u16) -> &mut Self {
+        self.pad(2);
+        self.buf.extend(val.to_le_bytes());
+        self
+    }
+
+    pub fn u32(&mut self, val: u32) -> &mut Self {
+        self.pad(4);
+        self.buf.extend(val.to_le_bytes());
+        self
+    }
+
+    /// Byte array, aligned to 1.
+    pub fn bytes(&mut self, val: &[u8]) -> &mut Self {
+        self.buf.extend(val);
+        self
+    }
+
+    pub fn nested<T: NvStruct>(&mut self, val: &T) -> &mut Self {
+        self.pad(T::ALIGN);
+        val.write(self);
+        self.pad(T::ALIGN);
+        self
+    }
+
+    pub fn finish(self) -> Vec<u8> { self.buf }
+}
+
+/// Decodes structs written by [`Writer`].
+pub struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    align: bool,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8], align: bool) -> Self {
+        Self {
+            data,
+            pos: 0,
+            align,
+        }
+    }
+
+    fn pad(&mut self, size: usize) {
+        if self.align {
+            self.pos = self.pos.next_multiple_of(size);
+        }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], de::Error> {
+        let ret = self
+            .data
+            .get(self.pos..self.pos + len)
+            .ok_or(de::Error::UnexpectedEOF)?;
+        self.pos += len;
+        Ok(ret)
+    }
+
+    pub fn is_empty(&self) -> bool { self.pos >= self.data.len() }
+
+    pub fn u8(&mut self) -> Result<u8, de::Error> { Ok(self.take(1)?[0]) }
+
+    pub fn bool(&mut self) -> Result<bool, de::Error> { Ok(self.u8()? != 0) }
+
+    pub fn u16(&mut self) -> Result<u16, de::Error> {
+        self.pad(2);
+        Ok(u16::from_le_bytes(self.bytes()?))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, de::Error> {
+        self.pad(4);
+        Ok(u32::from_le_bytes(self.bytes()?))
+    }
+
+    pub fn bytes<const N: usize>(&mut self) -> Result<[u8; N], de::Error> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    pub fn nested<T: NvStruct>(&mut self) -> Result<T, de::Error> {
+        self.pad(T::ALIGN);
+        let ret = T::read(self)?;
+        self.pad(T::ALIGN);
+        Ok(ret)
+    }
+
+    /// Fails unless the whole input was consumed.
+    pub fn finish(self) -> Result<(), de::Error> {
+        match self.pos == self.data.len() {
+            true => Ok(()),
+            false => Err(de::Error::Unknown),
+        }
+    }
+}
+
+/// Value stored in NV memory, whose layout may depend on struct alignment.
+pub trait NvStruct: Sized {
+    /// Alignment of the struct when aligned, i.e. of its largest field.
+    const ALIGN: usize;
+
+    fn write(&self, writer: &mut Writer);
+    fn read(reader: &mut Reader) -> Result<Self, de::Error>;
+
+    fn encode(&self, align: bool) -> Vec<u8> {
+        let mut writer = Writer::new(align);
+        writer.nested(self);
+        writer.finish()
+    }
+
+    fn decode(data: &[u8], align: bool) -> Result<Self, de::Error> {
+        let mut reader = Reader::new(data, align);
+        let ret = reader.nested()?;
+        reader.finish()?;
+        Ok(ret)
+    }
+}
+
+impl NvStruct for u8 {
+    const ALIGN: usize = 1;
+    fn write(&self, writer: &mut Writer) { writer.u8(*self); }
+    fn read(reader: &mut Reader) -> Result<Self, de::Error> { reader.u8() }
+}
+
+impl NvStruct for bool {
+    const ALIGN: usize = 1;
+    fn write(&self, writer: &mut Writer) { writer.bool(*self); }
+    fn read(reader: &mut Reader) -> Result<Self, de::Error> { reader.bool() }
+}
+
+impl NvStruct for u16 {
+    const ALIGN: usize = 2;
+    fn write(&self, writer: &mut Writer) { writer.u16(*self); }
+    fn read(reader: &mut Reader) -> Result<Self, de::Error> { reader.u16() }
+}
+
+impl NvStruct for u32 {
+    const ALIGN: usize = 4;
+    fn write(&self, writer: &mut Writer) { writer.u32(*self); }
+    fn read(reader: &mut Reader) -> Result<Self, de::Error> { reader.u32() }
+}
+
+impl<const N: usize> NvStruct for [u8; N] {
+    const ALIGN: usize = 1;
+    fn write(&self, writer: &mut Writer) { writer.bytes(self); }
+    fn read(reader: &mut Reader) -> Result<Self, de::Error> { reader.bytes() }
+}
+
+/// Tables stored in a single item are plain arrays of entries.
+impl<T: NvStruct> NvStruct for Vec<T> {
+    const ALIGN: usize = T::ALIGN;
+    fn write(&self, writer: &mut Writer) {
+        for entry in self {
+            writer.nested(entry);
+        }
+    }
+    fn read(reader: &mut Reader) -> Result<Self, de::Error> {
+        let mut ret = vec![];
+        while !reader.is_empty() {
+            ret.push(reader.nested()?);
+        }
+        Ok(ret)
+    }
+}
@@ -0,0 +1,654 @@
+use crate::command::de;
+use crate::command::sys::{ExNvIds, NvSysIds, NVID};
+
+use super::{NvStruct, Reader, Writer};
+
+use std::marker::PhantomData;
+
+use enumflags2::{bitflags, BitFlags};
+
+/// Legacy OSAL NV item ids.
+#[repr(u16)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OsalNvIds {
+    ExtAddr = 0x0001,
+    StartupOption = 0x0003,
+    Nib = 0x0021,
+    DeviceList = 0x0022,
+    AddrMgr = 0x0023,
+    ExtendedPanId = 0x002D,
+    NwkActiveKeyInfo = 0x003A,
+    NwkAlternKeyInfo = 0x003B,
+    BindingTable = 0x0041,
+    GroupTable = 0x0042,
+    ApsUseExtPanId = 0x0047,
+    ApsLinkKeyTable = 0x004C,
+    BdbNodeIsOnANetwork = 0x0055,
+    HasConfiguredZStack3 = 0x0060,
+    SecurityLevel = 0x0061,
+    PreCfgKey = 0x0062,
+    PreCfgKeysEnable = 0x0063,
+    SecurityMode = 0x0064,
+    UseDefaultTclk = 0x006D,
+    TrustCenterAddr = 0x0071,
+    NwkSecMaterialTableStart = 0x0075,
+    NwkSecMaterialTableEnd = 0x0080,
+    UserDesc = 0x0081,
+    NwkKey = 0x0082,
+    PanId = 0x0083,
+    ChanList = 0x0084,
+    LogicalType = 0x0087,
+    ZdoDirectCb = 0x008F,
+    TclkSeed = 0x0101,
+    TclkJoinDev = 0x0102,
+    TclkIcTableStart = 0x0104,
+    TclkIcTableEnd = 0x0110,
+    TclkTableStart = 0x0111,
+    TclkTableEnd = 0x01FF,
+    ApsLinkKeyDataStart = 0x0201,
+    ApsLinkKeyDataEnd = 0x02FF,
+    HasConfiguredZStack1 = 0x0F00,
+}
+
+impl OsalNvIds {
+    pub const fn id(self) -> NVID { NVID::legacy(self as u16) }
+}
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogicalType {
+    Coordinator = 0x00,
+    Router = 0x01,
+    EndDevice = 0x02,
+}
+
+/// NV item holding a `T`.
+pub struct Item<T> {
+    id: NVID,
+    _value: PhantomData<fn() -> T>,
+}
+
+impl<T> Item<T> {
+    pub const fn new(id: NVID) -> Self {
+        Self {
+            id,
+            _value: PhantomData,
+        }
+    }
+
+    pub fn id(&self) -> NVID { self.id }
+}
+
+impl<T> Clone for Item<T> {
+    fn clone(&self) -> Self { *self }
+}
+
+impl<T> Copy for Item<T> {}
+
+/// Table of `T`, one entry per NV item.
+///
+/// Z-Stack 3.x.0 keeps entries under an extended item, indexed by sub id,
+/// older firmware in a range of legacy items.
+pub struct Table<T> {
+    extended: ExNvIds,
+    legacy: Option<(OsalNvIds, OsalNvIds)>,
+    _value: PhantomData<fn() -> T>,
+}
+
+impl<T> Table<T> {
+    pub const fn new(extended: ExNvIds, legacy: Option<(OsalNvIds, OsalNvIds)>) -> Self {
+        Self {
+            extended,
+            legacy,
+            _value: PhantomData,
+        }
+    }
+
+    /// Item of entry `index`, `None` if out of range.
+    pub fn entry(&self, index: u16, extended: bool) -> Option<Item<T>> {
+        if extended {
+            let id = NVID::new(NvSysIds::ZStack as u8, self.extended as u16, index);
+            return Some(Item::new(id));
+        }
+        let (start, end) = self.legacy?;
+        let id = (start as u16).checked_add(index)?;
+        (id <= end as u16).then(|| Item::new(NVID::legacy(id)))
+    }
+
+    /// Number of entries, `None` if only bounded by the firmware.
+    pub fn len(&self, extended: bool) -> Option<u16> {
+        match (extended, self.legacy) {
+            (false, Some((start, end))) => Some(end as u16 - start as u16 + 1),
+            (false, None) => Some(0),
+            (true, _) => None,
+        }
+    }
+}
+
+/// [`STARTUP_OPTION`] flags, applied and cleared by the next reset.
+#[bitflags]
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StartupOption {
+    /// reset the configuration items to their defaults
+    ClearConfig = 0x01,
+    /// forget the network the device is on
+    ClearState = 0x02,
+}
+
+/// Value of the has configured markers once a network was set up.
+pub const CONFIGURED_MARKER: u8 = 0x55;
+
+/// Has configured marker, Z-Stack Home 1.2.
+pub const HAS_CONFIGURED_ZSTACK1: Item<u8> = Item::new(OsalNvIds::HasConfiguredZStack1.id());
+/// Has configured marker, Z-Stack 3.
+pub const HAS_CONFIGURED_ZSTACK3: Item<u8> = Item::new(OsalNvIds::HasConfiguredZStack3.id());
+/// Whether BDB commissioning left the device on a network, Z-Stack 3.
+pub const BDB_NODE_IS_ON_A_NETWORK: Item<bool> = Item::new(OsalNvIds::BdbNodeIsOnANetwork.id());
+/// [`StartupOption`] flags.
+pub const STARTUP_OPTION: Item<u8> = Item::new(OsalNvIds::StartupOption.id());
+/// IEEE address of the device, little-endian.
+pub const EXTADDR: Item<[u8; 8]> = Item::new(OsalNvIds::ExtAddr.id());
+pub const NIB: Item<Nib> = Item::new(OsalNvIds::Nib.id());
+pub const PANID: Item<u16> = Item::new(OsalNvIds::PanId.id());
+pub const EXTENDED_PAN_ID: Item<[u8; 8]> = Item::new(OsalNvIds::ExtendedPanId.id());
+/// Channel bit mask, bit `n` for channel `n`.
+pub const CHANLIST: Item<u32> = Item::new(OsalNvIds::ChanList.id());
+pub const PRECFGKEY: Item<[u8; 16]> = Item::new(OsalNvIds::PreCfgKey.id());
+pub const PRECFGKEYS_ENABLE: Item<bool> = Item::new(OsalNvIds::PreCfgKeysEnable.id());
+pub const APS_USE_EXT_PANID: Item<[u8; 8]> = Item::new(OsalNvIds::ApsUseExtPanId.id());
+/// [`LogicalType`] of the device.
+pub const LOGICAL_TYPE: Item<u8> = Item::new(OsalNvIds::LogicalType.id());
+/// Whether ZDO callbacks are sent over MT.
+pub const ZDO_DIRECT_CB: Item<bool> = Item::new(OsalNvIds::ZdoDirectCb.id());
+pub const TCLK_SEED: Item<[u8; 16]> = Item::new(OsalNvIds::TclkSeed.id());
+pub const NWK_ACTIVE_KEY_INFO: Item<NwkActiveKeyItems> =
+    Item::new(OsalNvIds::NwkActiveKeyInfo.id());
+pub const NWK_ALTERN_KEY_INFO: Item<NwkActiveKeyItems> =
+    Item::new(OsalNvIds::NwkAlternKeyInfo.id());
+/// Whole address manager table, before Z-Stack 3.x.0.
+pub const ADDRMGR: Item<Vec<AddrMgrEntry>> = Item::new(OsalNvIds::AddrMgr.id());
+pub const APS_LINK_KEY_TABLE: Item<ApsLinkKeyTable> = Item::new(OsalNvIds::ApsLinkKeyTable.id());
+
+/// Address manager entries, Z-Stack 3.x.0 only.
+pub const ADDRMGR_TABLE: Table<AddrMgrEntry> = Table::new(ExNvIds::AddrMgr, None);
+pub const TCLK_TABLE: Table<TCLinkKeyEntry> = Table::new(
+    ExNvIds::TClkTable,
+    Some((OsalNvIds::TclkTableStart, OsalNvIds::TclkTableEnd)),
+);
+/// Link keys derived from install codes, referenced by provisional
+/// [`TCLK_TABLE`] entries.
+pub const TCLK_IC_TABLE: Table<[u8; 16]> = Table::new(
+    ExNvIds::TClkIcTable,
+    Some((OsalNvIds::TclkIcTableStart, OsalNvIds::TclkIcTableEnd)),
+);
+pub const APS_KEY_DATA_TABLE: Table<ApsLinkKeyData> = Table::new(
+    ExNvIds::ApsKeyDataTable,
+    Some((OsalNvIds::ApsLinkKeyDataStart, OsalNvIds::ApsLinkKeyDataEnd)),
+);
+pub const NWK_SEC_MATERIAL_TABLE: Table<NwkSecMaterialDesc> = Table::new(
+    ExNvIds::NwkSecMaterialTable,
+    Some((
+        OsalNvIds::NwkSecMaterialTableStart,
+        OsalNvIds::NwkSecMaterialTableEnd,
+    )),
+);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct NwkKeyDesc {
+    pub key_seq_num: u8,
+    pub key: [u8; 16],
+}
+
+impl NvStruct for NwkKeyDesc {
+    const ALIGN: usize = 1;
+    fn write(&self, writer: &mut Writer) { writer.u8(self.key_seq_num).bytes(&self.key); }
+    fn read(reader: &mut Reader) -> Result<Self, de::Error> {
+        Ok(Self {
+            key_seq_num: reader.u8()?,
+            key: reader.bytes()?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct NwkActiveKeyItems {
+    pub active: NwkKeyDesc,
+    pub frame_counter: u32,
+}
+
+impl NvStruct for NwkActiveKeyItems {
+    const ALIGN: usize = 4;
+    fn write(&self, writer: &mut Writer) { writer.nested(&self.active).u32(self.frame_counter); }
+    fn read(reader: &mut Reader) -> Result<Self, de::Error> {
+        Ok(Self {
+            active: reader.nested()?,
+            frame_counter: reader.u32()?,
+        })
+    }
+}
+
+/// Outgoing network frame counter per extended PAN ID.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct NwkSecMaterialDesc {
+    pub frame_counter: u32,
+    pub extended_pan_id: [u8; 8],
+}
+
+impl NvStruct for NwkSecMaterialDesc {
+    const ALIGN: usize = 4;
+    fn write(&self, writer: &mut Writer) {
+        writer.u32(self.frame_counter).bytes(&self.extended_pan_id);
+    }
+    fn read(reader: &mut Reader) -> Result<Self, de::Error> {
+        Ok(Self {
+            frame_counter: reader.u32()?,
+            extended_pan_id: reader.bytes()?,
+        })
+    }
+}
+
+#[bitflags]
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddrMgrUser {
+    Assoc = 0x01,
+    Security = 0x02,
+    Binding = 0x04,
+    Private1 = 0x08,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AddrMgrEntry {
+    /// [`AddrMgrUser`] bits, 0 for an unused entry.
+    pub user: u8,
+    pub nwk_addr: u16,
+    pub ext_addr: [u8; 8],
+}
+
+impl AddrMgrEntry {
+    pub fn users(&self) -> BitFlags<AddrMgrUser> { BitFlags::from_bits_truncate(self.user) }
+}
+
+impl Default for AddrMgrEntry {
+    /// Unused entry.
+    fn default() -> Self {
+        Self {
+            user: 0x00,
+            nwk_addr: 0xFFFF,
+            ext_addr: [0xFF; 8],
+        }
+    }
+}
+
+impl NvStruct for AddrMgrEntry {
+    const ALIGN: usize = 2;
+    fn write(&self, writer: &mut Writer) {
+        writer
+            .u8(self.user)
+            .u16(self.nwk_addr)
+            .bytes(&self.ext_addr);
+    }
+    fn read(reader: &mut Reader) -> Result<Self, de::Error> {
+        Ok(Self {
+            user: reader.u8()?,
+            nwk_addr: reader.u16()?,
+            ext_addr: reader.bytes()?,
+        })
+    }
+}
+
+/// [`TCLinkKeyEntry::key_attributes`] values.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, num_derive::FromPrimitive)]
+pub enum KeyAttributes {
+    /// derived from an install code, not yet used
+    ProvisionalKey = 0x00,
+    UnverifiedKey = 0x01,
+    VerifiedKey = 0x02,
+    DistributedDefaultKey = 0xFC,
+    DefaultKey = 0xFF,
+}
+
+/// Trust center link key of a device, Z-Stack 3.0 and later. The key is
+/// either derived from [`TCLK_SEED`] or stored in the install code table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TCLinkKeyEntry {
+    pub tx_frame_counter: u32,
+    pub rx_frame_counter: u32,
+    pub ext_addr: [u8; 8],
+    pub key_attributes: u8,
+    pub key_type: u8,
+    /// Seed shift for derived keys, install code table index otherwise.
+    pub seed_shift_ic_index: u8,
+}
+
+impl NvStruct for TCLinkKeyEntry {
+    const ALIGN: usize = 4;
+    fn write(&self, writer: &mut Writer) {
+        writer
+            .u32(self.tx_frame_counter)
+            .u32(self.rx_frame_counter)
+            .bytes(&self.ext_addr)
+            .u8(self.key_attributes)
+            .u8(self.key_type)
+            .u8(self.seed_shift_ic_index);
+    }
+    fn read(reader: &mut Reader) -> Result<Self, de::Error> {
+        Ok(Self {
+            tx_frame_counter: reader.u32()?,
+            rx_frame_counter: reader.u32()?,
+            ext_addr: reader.bytes()?,
+            key_attributes: reader.u8()?,
+            key_type: reader.u8()?,
+            seed_shift_ic_index: reader.u8()?,
+        })
+    }
+}
+
+/// APS link key with its frame counters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ApsLinkKeyData {
+    pub key: [u8; 16],
+    pub tx_frame_counter: u32,
+    pub rx_frame_counter: u32,
+}
+
+impl NvStruct for ApsLinkKeyData {
+    const ALIGN: usize = 4;
+    fn write(&self, writer: &mut Writer) {
+        writer
+            .bytes(&self.key)
+            .u32(self.tx_frame_counter)
+            .u32(self.rx_frame_counter);
+    }
+    fn read(reader: &mut Reader) -> Result<Self, de::Error> {
+        Ok(Self {
+            key: reader.bytes()?,
+            tx_frame_counter: reader.u32()?,
+            rx_frame_counter: reader.u32()?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ApsLinkKeyTableEntry {
+    /// Index of the device in the address manager table.
+    pub addr_mgr_index: u16,
+    /// Item holding the [`ApsLinkKeyData`].
+    pub link_key_nv_id: u16,
+    /// See [`AuthenticationState`].
+    pub authentication_state: u8,
+}
+
+/// [`ApsLinkKeyTableEntry::authentication_state`] values.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, num_derive::FromPrimitive)]
+pub enum AuthenticationState {
+    NotAuthenticated = 0x00,
+    AuthenticatedCbck = 0x01,
+    AuthenticatedEa = 0x02,
+}
+
+impl NvStruct for ApsLinkKeyTableEntry {
+    const ALIGN: usize = 2;
+    fn write(&self, writer: &mut Writer) {
+        writer
+            .u16(self.addr_mgr_index)
+            .u16(self.link_key_nv_id)
+            .u8(self.authentication_state);
+    }
+    fn read(reader: &mut Reader) -> Result<Self, de::Error> {
+        Ok(Self {
+            addr_mgr_index: reader.u16()?,
+            link_key_nv_id: reader.u16()?,
+            authentication_state: reader.u8()?,
+        })
+    }
+}
+
+/// Entries prefixed by their count.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ApsLinkKeyTable {
+    pub entries: Vec<ApsLinkKeyTableEntry>,
+}
+
+impl NvStruct for ApsLinkKeyTable {
+    const ALIGN: usize = 2;
+    fn write(&self, writer: &mut Writer) {
+        writer.u16(self.entries.len() as u16);
+        for entry in &self.entries {
+            writer.nested(entry);
+        }
+    }
+    fn read(reader: &mut Reader) -> Result<Self, de::Error> {
+        let len = reader.u16()?;
+        let entries = (0..len)
+            .map(|_| reader.nested())
+            .collect::<Result<_, _>>()?;
+        Ok(Self { entries })
+    }
+}
+
+/// Network information base, `nwkIB_t`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Nib {
+    pub sequence_num: u8,
+    pub passive_ack_timeout: u8,
+    pub max_broadcast_retries: u8,
+    pub max_children: u8,
+    pub max_depth: u8,
+    pub max_routers: u8,
+    pub dummy_neighbor_table: u8,
+    pub broadcast_delivery_time: u8,
+    pub report_constant_cost: u8,
+    pub route_disc_retries: u8,
+    pub dummy_routing_table: u8,
+    pub secure_all_frames: u8,
+    pub security_level: u8,
+    pub sym_link: u8,
+    pub capability_flags: u8,
+    pub transaction_persistence_time: u16,
+    pub nwk_protocol_version: u8,
+    pub route_discovery_time: u8,
+    pub route_expiry_time: u8,
+    pub nwk_dev_address: u16,
+    pub nwk_logical_channel: u8,
+    pub nwk_coord_address: u16,
+    pub nwk_coord_ext_address: [u8; 8],
+    pub nwk_pan_id: u16,
+    pub nwk_state: u8,
+    pub channel_list: u32,
+    pub beacon_order: u8,
+    pub super_frame_order: u8,
+    pub scan_duration: u8,
+    pub batt_life_ext: u8,
+    pub allocated_router_addresses: u32,
+    pub allocated_end_device_addresses: u32,
+    pub node_depth: u8,
+    pub extended_pan_id: [u8; 8],
+    pub nwk_key_loaded: bool,
+    pub spare1: NwkKeyDesc,
+    pub spare2: NwkKeyDesc,
+    pub spare3: u8,
+    pub spare4: u8,
+    pub nwk_link_status_period: u8,
+    pub nwk_router_age_limit: u8,
+    pub nwk_use_multicast: bool,
+    pub nwk_is_concentrator: bool,
+    pub nwk_concentrator_discovery_time: u8,
+    pub nwk_concentrator_radius: u8,
+    pub nwk_all_fresh: u8,
+    pub nwk_manager_addr: u16,
+    pub nwk_total_transmissions: u16,
+    pub nwk_update_id: u8,
+}
+
+impl NvStruct for Nib {
+    const ALIGN: usize = 4;
+    fn write(&self, writer: &mut Writer) {
+        writer
+            .u8(self.sequence_num)
+            .u8(self.passive_ack_timeout)
+            .u8(self.max_broadcast_retries)
+            .u8(self.max_children)
+            .u8(self.max_depth)
+            .u8(self.max_routers)
+            .u8(self.dummy_neighbor_table)
+            .u8(self.broadcast_delivery_time)
+            .u8(self.report_constant_cost)
+            .u8(self.route_disc_retries)
+            .u8(self.dummy_routing_table)
+            .u8(self.secure_all_frames)
+            .u8(self.security_level)
+            .u8(self.sym_link)
+            .u8(self.capability_flags)
+            .u16(self.transaction_persistence_time)
+            .u8(self.nwk_protocol_version)
+            .u8(self.route_discovery_time)
+            .u8(self.route_expiry_time)
+            .u16(self.nwk_dev_address)
+            .u8(self.nwk_logical_channel)
+            .u16(self.nwk_coord_address)
+            .bytes(&self.nwk_coord_ext_address)
+            .u16(self.nwk_pan_id)
+            .u8(self.nwk_state)
+            .u32(self.channel_list)
+            .u8(self.beacon_order)
+            .u8(self.super_frame_order)
+            .u8(self.scan_duration)
+            .u8(self.batt_life_ext)
+            .u32(self.allocated_router_addresses)
+            .u32(self.allocated_end_device_addresses)
+            .u8(self.node_depth)
+            .bytes(&self.extended_pan_id)
+            .bool(self.nwk_key_loaded)
+            .nested(&self.spare1)
+            .nested(&self.spare2)
+            .u8(self.spare3)
+            .u8(self.spare4)
+            .u8(self.nwk_link_status_period)
+            .u8(self.nwk_router_age_limit)
+            .bool(self.nwk_use_multicast)
+            .bool(self.nwk_is_concentrator)
+            .u8(self.nwk_concentrator_discovery_time)
+            .u8(self.nwk_concentrator_radius)
+            .u8(self.nwk_all_fresh)
+            .u16(self.nwk_manager_addr)
+            .u16(self.nwk_total_transmissions)
+            .u8(self.nwk_update_id);
+    }
+    fn read(reader: &mut Reader) -> Result<Self, de::Error> {
+        Ok(Self {
+            sequence_num: reader.u8()?,
+            passive_ack_timeout: reader.u8()?,
+            max_broadcast_retries: reader.u8()?,
+            max_children: reader.u8()?,
+            max_depth: reader.u8()?,
+            max_routers: reader.u8()?,
+            dummy_neighbor_table: reader.u8()?,
+            broadcast_delivery_time: reader.u8()?,
+            report_constant_cost: reader.u8()?,
+            route_disc_retries: reader.u8()?,
+            dummy_routing_table: reader.u8()?,
+            secure_all_frames: reader.u8()?,
+            security_level: reader.u8()?,
+            sym_link: reader.u8()?,
+            capability_flags: reader.u8()?,
+            transaction_persistence_time: reader.u16()?,
+            nwk_protocol_version: reader.u8()?,
+            route_discovery_time: reader.u8()?,
+            route_expiry_time: reader.u8()?,
+            nwk_dev_address: reader.u16()?,
+            nwk_logical_channel: reader.u8()?,
+            nwk_coord_address: reader.u16()?,
+            nwk_coord_ext_address: reader.bytes()?,
+            nwk_pan_id: reader.u16()?,
+            nwk_state: reader.u8()?,
+            channel_list: reader.u32()?,
+            beacon_order: reader.u8()?,
+            super_frame_order: reader.u8()?,
+            scan_duration: reader.u8()?,
+            batt_life_ext: reader.u8()?,
+            allocated_router_addresses: reader.u32()?,
+            allocated_end_device_addresses: reader.u32()?,
+            node_depth: reader.u8()?,
+            extended_pan_id: reader.bytes()?,
+            nwk_key_loaded: reader.bool()?,
+            spare1: reader.nested()?,
+            spare2: reader.nested()?,
+            spare3: reader.u8()?,
+            spare4: reader.u8()?,
+            nwk_link_status_period: reader.u8()?,
+            nwk_router_age_limit: reader.u8()?,
+            nwk_use_multicast: reader.bool()?,
+            nwk_is_concentrator: reader.bool()?,
+            nwk_concentrator_discovery_time: reader.u8()?,
+            nwk_concentrator_radius: reader.u8()?,
+            nwk_all_fresh: reader.u8()?,
+            nwk_manager_addr: reader.u16()?,
+            nwk_total_transmissions: reader.u16()?,
+            nwk_update_id: reader.u8()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn struct_sizes() {
+        let nib = Nib::default();
+        assert_eq!(nib.encode(false).len(), 110);
+        assert_eq!(nib.encode(true).len(), 116);
+
+        let key = NwkActiveKeyItems::default();
+        assert_eq!(key.encode(false).len(), 21);
+        assert_eq!(key.encode(true).len(), 24);
+
+        let entry = TCLinkKeyEntry::default();
+        assert_eq!(entry.encode(false).len(), 19);
+        assert_eq!(entry.encode(true).len(), 20);
+
+        let table = vec![AddrMgrEntry::default(); 2];
+        assert_eq!(table.encode(false).len(), 22);
+        assert_eq!(table.encode(true).len(), 24);
+    }
+
+    #[test]
+    fn aligned_round_trip() {
+        let key = NwkActiveKeyItems {
+            active: NwkKeyDesc {
+                key_seq_num: 1,
+                key: [0xAB; 16],
+            },
+            frame_counter: 0x01020304,
+        };
+        let data = key.encode(true);
+        assert_eq!(&data[17..20], &[0x00; 3]);
+        assert_eq!(&data[20..], &[0x04, 0x03, 0x02, 0x01]);
+        assert_eq!(NwkActiveKeyItems::decode(&data, true).unwrap(), key);
+        assert!(NwkActiveKeyItems::decode(&data, false).is_err());
+
+        let nib = Nib {
+            nwk_pan_id: 0x1A62,
+            channel_list: 1 << 15,
+            extended_pan_id: [0xDD; 8],
+            nwk_update_id: 3,
+            ..Default::default()
+        };
+        for align in [false, true] {
+            assert_eq!(Nib::decode(&nib.encode(align), align).unwrap(), nib);
+        }
+    }
+
+    #[test]
+    fn table_entries() {
+        let entry = TCLK_TABLE.entry(2, false).unwrap();
+        assert_eq!(entry.id(), NVID::legacy(0x0113));
+        assert!(TCLK_TABLE.entry(0xFF, false).is_none());
+        assert_eq!(TCLK_TABLE.entry(2, true).unwrap().id().sub_id(), 2);
+        assert!(ADDRMGR_TABLE.entry(0, false).is_none());
+    }
+}
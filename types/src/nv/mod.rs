@@ -0,0 +1,14 @@
+mod codec;
+mod items;
+
+pub use codec::{NvStruct, Reader, Writer};
+pub use items::{
+    AddrMgrEntry, AddrMgrUser, ApsLinkKeyData, ApsLinkKeyTable, ApsLinkKeyTableEntry,
+    AuthenticationState, Item, KeyAttributes, LogicalType, Nib, NwkActiveKeyItems, NwkKeyDesc,
+    NwkSecMaterialDesc, OsalNvIds, StartupOption, TCLinkKeyEntry, Table, ADDRMGR, ADDRMGR_TABLE,
+    APS_KEY_DATA_TABLE, APS_LINK_KEY_TABLE, APS_USE_EXT_PANID, BDB_NODE_IS_ON_A_NETWORK, CHANLIST,
+    CONFIGURED_MARKER, EXTADDR, EXTENDED_PAN_ID, HAS_CONFIGURED_ZSTACK1, HAS_CONFIGURED_ZSTACK3,
+    LOGICAL_TYPE, NIB, NWK_ACTIVE_KEY_INFO, NWK_ALTERN_KEY_INFO, NWK_SEC_MATERIAL_TABLE, PANID,
+    PRECFGKEY, PRECFGKEYS_ENABLE, STARTUP_OPTION, TCLK_IC_TABLE, TCLK_SEED, TCLK_TABLE,
+    ZDO_DIRECT_CB,
+};
@@ -1,6 +1,11 @@
 use crate::command;
 
-use log::debug;
+use log::{debug, warn};
+
+/// Start of frame marker.
+pub const SOF: u8 = 0xFE;
+/// Maximum length of the data field, see Z-stack Monitor and Test API, 2.1.1.
+pub const MAX_DATA_LEN: u8 = 250;
 
 /// See Z-stack Monitor and Test API, 2.1.1.
 #[derive(Debug, Clone)]
@@ -22,54 +27,50 @@ pub enum Error {
     UnexpectedEOF,
     #[error("frame corrupted")]
     FrameCorrupted,
+    #[error("I/O error: {0:?}")]
+    IO(std::io::Error),
 }
 
+fn checksum(data: &[u8]) -> u8 { data.iter().fold(u8::MIN, |acc, &e| acc ^ e) }
+
 impl Packet {
     pub fn from_command(command: &impl command::ser::Command) -> Self {
         let command = command.serialize();
-        let frame_check_sequence = command.iter().fold(u8::MIN, |acc, &e| acc ^ e);
+        let frame_check_sequence = checksum(command.as_slice());
         Self {
-            start_of_frame: 0xFE,
+            start_of_frame: SOF,
             command,
             frame_check_sequence,
         }
     }
 
+    /// Reads a single frame, discarding any bytes preceding a valid frame.
+    ///
+    /// The reader is consumed one byte at a time so nothing past the returned
+    /// frame is read. Prefer a long-lived [`Decoder`] when reading a stream.
     pub fn from_reader(mut reader: impl std::io::Read) -> Result<Self, Error> {
-        let mut start_of_frame = u8::MIN;
-        reader
-            .read_exact(std::slice::from_mut(&mut start_of_frame))
-            .map_err(|_| Error::UnexpectedEOF)?;
-        let mut data_len = u8::MIN;
-        reader
-            .read_exact(std::slice::from_mut(&mut data_len))
-            .map_err(|_| Error::UnexpectedEOF)?;
-        let mut data_frame = vec![u8::MIN; data_len as usize + 2];
-        reader
-            .read_exact(data_frame.as_mut_slice())
-            .map_err(|_| Error::UnexpectedEOF)?;
-        let mut frame_check_sequence = u8::MIN;
-        reader
-            .read_exact(std::slice::from_mut(&mut frame_check_sequence))
-            .map_err(|_| Error::UnexpectedEOF)?;
-        debug!(
-            "frame: {:x?}, data_len={}, sof={}, fcs={}",
-            data_frame, data_len, start_of_frame, frame_check_sequence
-        );
-
-        let mut command = vec![data_len];
-        command.extend(data_frame);
-        let frame_check_sequence_target = command.iter().fold(u8::MIN, |acc, &e| acc ^ e);
-        if frame_check_sequence != frame_check_sequence_target {
-            return Err(Error::FrameCorrupted);
+        let mut decoder = Decoder::new();
+        loop {
+            if let Some(packet) = decoder.decode() {
+                return Ok(packet);
+            }
+            let mut byte = u8::MIN;
+            reader
+                .read_exact(std::slice::from_mut(&mut byte))
+                .map_err(|_| Error::UnexpectedEOF)?;
+            decoder.extend(&[byte]);
         }
+    }
 
-        let ret = Self {
-            start_of_frame,
-            command,
-            frame_check_sequence,
-        };
-        Ok(ret)
+    /// Command type bits of the frame, see [`command::CommandType`].
+    pub fn command_type(&self) -> u8 { self.command[1] & command::COMMAND_TYPE_FLAG }
+
+    /// Subsystem and command id of the frame, without the command type bits.
+    pub fn command_id(&self) -> [u8; 2] {
+        [
+            self.command[1] & !command::COMMAND_TYPE_FLAG,
+            self.command[2],
+        ]
     }
 
     pub fn serialize(&self) -> Vec<u8> {
@@ -81,9 +82,100 @@ impl Packet {
     }
 }
 
+/// Incremental MT frame decoder.
+///
+/// Bytes are buffered until a complete frame is available. Anything that is
+/// not part of a frame starting with [`SOF`], carrying a sane length and a
+/// matching FCS is discarded, so the stream recovers on the next valid frame.
+#[derive(Debug, Default)]
+pub struct Decoder {
+    buffer: Vec<u8>,
+    /// bytes dropped since the last decoded frame
+    discarded: usize,
+}
+
+impl Decoder {
+    pub fn new() -> Self { Self::default() }
+
+    pub fn extend(&mut self, data: &[u8]) { self.buffer.extend_from_slice(data); }
+
+    /// Number of buffered bytes not yet decoded.
+    pub fn pending(&self) -> usize { self.buffer.len() }
+
+    fn discard(&mut self, len: usize) {
+        self.buffer.drain(..len);
+        self.discarded += len;
+    }
+
+    /// Returns the next complete frame in the buffer, if any.
+    pub fn decode(&mut self) -> Option<Packet> {
+        loop {
+            let Some(pos) = self.buffer.iter().position(|&e| e == SOF) else {
+                self.discard(self.buffer.len());
+                return None;
+            };
+            self.discard(pos);
+            if self.buffer.len() < 2 {
+                return None;
+            }
+
+            let data_len = self.buffer[1];
+            if data_len > MAX_DATA_LEN {
+                debug!("frame length out of range, data_len={}", data_len);
+                self.discard(1);
+                continue;
+            }
+            // SOF, length, 2 bytes of command id, data, FCS
+            let frame_len = data_len as usize + 5;
+            if self.buffer.len() < frame_len {
+                return None;
+            }
+
+            let command = self.buffer[1..frame_len - 1].to_vec();
+            let frame_check_sequence = self.buffer[frame_len - 1];
+            if checksum(command.as_slice()) != frame_check_sequence {
+                debug!("frame check sequence mismatch, frame: {:x?}", command);
+                self.discard(1);
+                continue;
+            }
+
+            self.buffer.drain(..frame_len);
+            if self.discarded > 0 {
+                warn!("discarded {} bytes before frame", self.discarded);
+                self.discarded = 0;
+            }
+            debug!(
+                "frame: {:x?}, data_len={}, fcs={}",
+                command, data_len, frame_check_sequence
+            );
+            return Some(Packet {
+                start_of_frame: SOF,
+                command,
+                frame_check_sequence,
+            });
+        }
+    }
+
+    /// Reads from `reader` until a complete frame is decoded.
+    pub fn read_from(&mut self, reader: &mut impl std::io::Read) -> Result<Packet, Error> {
+        let mut chunk = [u8::MIN; 256];
+        loop {
+            if let Some(packet) = self.decode() {
+                return Ok(packet);
+            }
+            match reader.read(&mut chunk) {
+                Ok(0) => return Err(Error::UnexpectedEOF),
+                Ok(len) => self.extend(&chunk[..len]),
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(Error::IO(e)),
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::packet::Packet;
+    use crate::packet::{Decoder, Packet};
 
     #[test]
     fn ping_request() {
@@ -91,4 +183,29 @@ mod tests {
         let expected = vec![0xFE, 0x00, 0x21, 0x01, 0x20];
         assert_eq!(Packet::from_command(&command).serialize(), expected)
     }
+
+    #[test]
+    fn decode_skips_garbage() {
+        let frame = [0xFE, 0x02, 0x61, 0x01, 0x59, 0x06, 0x3D];
+        let mut input = vec![0x00, 0x12, 0xFE, 0xFF];
+        input.extend(frame);
+        let packet = Packet::from_reader(input.as_slice()).unwrap();
+        assert_eq!(packet.serialize(), frame);
+    }
+
+    #[test]
+    fn decode_resyncs_after_corrupted_frame() {
+        let frame = [0xFE, 0x02, 0x61, 0x01, 0x59, 0x06, 0x3D];
+        let mut corrupted = frame;
+        corrupted[4] ^= 0x01;
+
+        let mut decoder = Decoder::new();
+        decoder.extend(&corrupted);
+        assert!(decoder.decode().is_none());
+        decoder.extend(&frame[..3]);
+        assert!(decoder.decode().is_none());
+        decoder.extend(&frame[3..]);
+        assert_eq!(decoder.decode().unwrap().serialize(), frame);
+        assert_eq!(decoder.pending(), 0);
+    }
 }